@@ -0,0 +1,98 @@
+// Lookup time and memory of dictionary backends, run by `cargo bench --bench dict_backend`.
+// Bases are of RS(10,8) (8 bytes) and Hamming(7) (120 bits), where half of lookups hit. Memory is given by live heap
+// bytes of an empty dictionary, and bytes per entry of a full dictionary, of the backend only and with the LRU order
+// updated by BasisDict.
+use libecc::types::*;
+use rust_gd::{BasisDict, CompactDictBackend, DictBackend, DictBase, MemoryDictBackend};
+use std::{
+  alloc::{GlobalAlloc, Layout, System},
+  hint::black_box,
+  sync::atomic::{AtomicUsize, Ordering},
+  time::Instant,
+};
+
+const DICT_SIZE: usize = 1 << 16;
+const LOOKUPS: usize = 1 << 22;
+
+// Allocator counting live heap bytes
+struct Counting;
+static LIVE_BYTELEN: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    LIVE_BYTELEN.fetch_add(layout.size(), Ordering::Relaxed);
+    System.alloc(layout)
+  }
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    LIVE_BYTELEN.fetch_sub(layout.size(), Ordering::Relaxed);
+    System.dealloc(ptr, layout)
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn bench<T, B>(name: &str, new_backend: impl Fn() -> B, base: impl Fn(usize) -> T)
+where
+  T: DictBase + 'static,
+  B: DictBackend<T> + 'static,
+{
+  let bases = (0..DICT_SIZE).map(&base).collect::<Vec<_>>();
+  let queries = (0..LOOKUPS)
+    .map(|i| base((i * 7919) % (2 * DICT_SIZE)))
+    .collect::<Vec<_>>();
+
+  let before = LIVE_BYTELEN.load(Ordering::Relaxed);
+  let mut backend = new_backend();
+  for (id, b) in bases.iter().enumerate() {
+    backend.insert(id, b).unwrap();
+  }
+  let backend_bytelen = LIVE_BYTELEN.load(Ordering::Relaxed) - before;
+
+  let start = Instant::now();
+  let mut hits = 0usize;
+  for q in queries.iter() {
+    hits += black_box(backend.get_id(q).unwrap()).is_some() as usize;
+  }
+  let elapsed = start.elapsed();
+  assert_eq!(hits, LOOKUPS / 2);
+  drop(backend);
+
+  let before = LIVE_BYTELEN.load(Ordering::Relaxed);
+  let mut dict = BasisDict::with_backend(DICT_SIZE, Box::new(new_backend()));
+  let empty_bytelen = LIVE_BYTELEN.load(Ordering::Relaxed) - before;
+  for b in bases.iter() {
+    dict.put_base(b).unwrap();
+  }
+  let dict_bytelen = LIVE_BYTELEN.load(Ordering::Relaxed) - before;
+
+  println!(
+    "{:<26} {:>6.1} ns/lookup {:>6} bytes (empty) {:>6.1} bytes/entry (backend) {:>6.1} bytes/entry (with LRU)",
+    name,
+    elapsed.as_nanos() as f64 / LOOKUPS as f64,
+    empty_bytelen,
+    backend_bytelen as f64 / DICT_SIZE as f64,
+    dict_bytelen as f64 / DICT_SIZE as f64,
+  );
+}
+
+fn main() {
+  let bytes = |i: usize| (i as u64).to_le_bytes().to_vec();
+  let bits = |i: usize| {
+    let mut b = BVRep::from_slice(&(i as u128).to_le_bytes());
+    b.truncate(120);
+    b
+  };
+  bench(
+    "compact U8VRep (8 bytes)",
+    || CompactDictBackend::new(DICT_SIZE),
+    bytes,
+  );
+  bench("memory U8VRep (8 bytes)", MemoryDictBackend::default, bytes);
+  bench(
+    "compact BVRep (120 bits)",
+    || CompactDictBackend::new(DICT_SIZE),
+    bits,
+  );
+  bench("memory BVRep (120 bits)", MemoryDictBackend::default, bits);
+}
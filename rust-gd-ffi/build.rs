@@ -0,0 +1,16 @@
+use std::{env, path::PathBuf};
+
+// Generate the C header into OUT_DIR, which tests/header.rs compares with the checked-in include/rust_gd.h
+fn main() {
+  let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+  let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+  println!("cargo:rerun-if-changed=src/lib.rs");
+  println!("cargo:rerun-if-changed=cbindgen.toml");
+  let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+  cbindgen::Builder::new()
+    .with_crate(&crate_dir)
+    .with_config(config)
+    .generate()
+    .expect("Unable to generate C header")
+    .write_to_file(out_dir.join("rust_gd.h"));
+}
@@ -0,0 +1,120 @@
+#ifndef RUST_GD_H
+#define RUST_GD_H
+
+/* Generated by cbindgen from rust-gd-ffi/src/lib.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a function
+ */
+typedef enum {
+  GD_STATUS_OK = 0,
+  /**
+   * Null pointer, invalid parameter, or a call other than the retry of a pending one
+   */
+  GD_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * The output does not fit in the buffer. The required length is set to out_len, and the same call must be repeated
+   * with a buffer of at least that length to take the output, since the dictionary has already been updated.
+   */
+  GD_STATUS_BUFFER_TOO_SMALL = 2,
+  /**
+   * GD failed, e.g., for a broken input to gd_dup
+   */
+  GD_STATUS_FAILED = 3,
+  GD_STATUS_PANICKED = 4,
+} GdStatus;
+
+/**
+ * Opaque handle of a GD instance with its dictionary
+ */
+typedef struct GdHandle GdHandle;
+
+/**
+ * Create a GD instance of the (code_len, info_len) Reed-Solomon code over GF(2^8) with a dictionary of dict_size
+ * entries. Returns NULL on failure.
+ */
+GdHandle *gd_reed_solomon_new(size_t code_len,
+                              size_t info_len,
+                              size_t dict_size);
+
+/**
+ * Create a GD instance of the Hamming code of the given degree, i.e., of (2^deg - 1)-bit codewords, with a dictionary
+ * of dict_size entries. Returns NULL on failure.
+ */
+GdHandle *gd_hamming_new(size_t deg,
+                         size_t dict_size);
+
+/**
+ * Free a handle. NULL is ignored.
+ *
+ * # Safety
+ * handle must be NULL or returned by a constructor and not yet freed.
+ */
+void gd_free(GdHandle *handle);
+
+/**
+ * Set the error alignment given by a rows x cols matrix in row-major order, which must be identical between dedup
+ * and dup instances. For Hamming codes, entries must be 0 or 1.
+ *
+ * # Safety
+ * handle must be a valid handle, and matrix must point to rows * cols bytes.
+ */
+GdStatus gd_set_error_alignment(GdHandle *handle,
+                                const uint8_t *matrix,
+                                size_t rows,
+                                size_t cols);
+
+/**
+ * Upper bound of the length of deduplicated data of src_len bytes, with which gd_dedup never returns
+ * GD_STATUS_BUFFER_TOO_SMALL.
+ *
+ * # Safety
+ * handle must be a valid handle.
+ */
+size_t gd_dedup_bound(const GdHandle *handle,
+                      size_t src_len);
+
+/**
+ * Deduplicate src_len bytes of src into dst of dst_cap bytes. The length of the deduplicated data and the padding
+ * of its last chunk, which must be given to gd_dup, are set to out_len and out_pad.
+ *
+ * # Safety
+ * handle must be a valid handle, src and dst must point to src_len and dst_cap bytes, and out_len and out_pad must be
+ * valid pointers.
+ */
+GdStatus gd_dedup(GdHandle *handle,
+                  const uint8_t *src,
+                  size_t src_len,
+                  uint8_t *dst,
+                  size_t dst_cap,
+                  size_t *out_len,
+                  size_t *out_pad);
+
+/**
+ * Duplicate src_len bytes of deduplicated data in src with the padding of its last chunk into dst of dst_cap bytes.
+ * The length of the duplicated data is set to out_len.
+ *
+ * # Safety
+ * handle must be a valid handle, src and dst must point to src_len and dst_cap bytes, and out_len must be a valid
+ * pointer.
+ */
+GdStatus gd_dup(GdHandle *handle,
+                const uint8_t *src,
+                size_t src_len,
+                size_t pad,
+                uint8_t *dst,
+                size_t dst_cap,
+                size_t *out_len);
+
+/**
+ * Message of the last error on the calling thread, or NULL if none. Valid until the next error on the thread.
+ */
+const char *gd_last_error(void);
+
+#endif /* RUST_GD_H */
@@ -0,0 +1,303 @@
+// C ABI of rust-gd. A GD instance is held by an opaque handle, and every function reports errors by GdStatus, whose
+// message is available from gd_last_error on the same thread. Deduplicated data is identical to that of rust-gd.
+use anyhow::{anyhow, ensure, Result};
+use rust_gd::{Deduped, GDInner, GD};
+use std::{
+  cell::RefCell,
+  ffi::{c_char, CString},
+  panic::{catch_unwind, AssertUnwindSafe},
+  ptr, slice,
+};
+use tokio::runtime::{Builder, Runtime};
+
+/// Result of a function
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdStatus {
+  Ok = 0,
+  /// Null pointer, invalid parameter, or a call other than the retry of a pending one
+  InvalidArgument = 1,
+  /// The output does not fit in the buffer. The required length is set to out_len, and the same call must be repeated
+  /// with a buffer of at least that length to take the output, since the dictionary has already been updated.
+  BufferTooSmall = 2,
+  /// GD failed, e.g., for a broken input to gd_dup
+  Failed = 3,
+  Panicked = 4,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Op {
+  Dedup,
+  Dup(usize),
+}
+
+// Output of a call not yet taken by the caller due to a small buffer
+#[derive(Debug)]
+struct Pending {
+  op: Op,
+  src: Vec<u8>,
+  out: Vec<u8>,
+  pad: usize,
+}
+
+/// Opaque handle of a GD instance with its dictionary
+pub struct GdHandle {
+  gd: GDInner,
+  rt: Runtime,
+  pending: Option<Pending>,
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+  let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+  LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+// Run f catching errors and panics, which must not unwind across the C ABI
+fn guard<F: FnOnce() -> Result<GdStatus>>(f: F) -> GdStatus {
+  match catch_unwind(AssertUnwindSafe(f)) {
+    Ok(Ok(status)) => status,
+    Ok(Err(e)) => {
+      set_last_error(format!("{:#}", e));
+      GdStatus::Failed
+    }
+    Err(_) => {
+      set_last_error("Panicked in rust-gd".to_string());
+      GdStatus::Panicked
+    }
+  }
+}
+
+fn invalid(msg: &str) -> Result<GdStatus> {
+  set_last_error(msg.to_string());
+  Ok(GdStatus::InvalidArgument)
+}
+
+fn new_handle(gd: GD, dict_size: usize) -> *mut GdHandle {
+  let mut handle = ptr::null_mut();
+  guard(|| {
+    let rt = Builder::new_current_thread().build()?;
+    let gd = rt.block_on(gd.setup(dict_size))?;
+    handle = Box::into_raw(Box::new(GdHandle {
+      gd,
+      rt,
+      pending: None,
+    }));
+    Ok(GdStatus::Ok)
+  });
+  handle
+}
+
+// Slice of the caller's buffer, where a null pointer is allowed only for an empty one
+unsafe fn input<'a>(src: *const u8, len: usize) -> Option<&'a [u8]> {
+  match (src.is_null(), len) {
+    (true, 0) => Some(&[]),
+    (true, _) => None,
+    (false, _) => Some(slice::from_raw_parts(src, len)),
+  }
+}
+
+/// Create a GD instance of the (code_len, info_len) Reed-Solomon code over GF(2^8) with a dictionary of dict_size
+/// entries. Returns NULL on failure.
+#[no_mangle]
+pub extern "C" fn gd_reed_solomon_new(
+  code_len: usize,
+  info_len: usize,
+  dict_size: usize,
+) -> *mut GdHandle {
+  new_handle(GD::ReedSolomon(code_len, info_len), dict_size)
+}
+
+/// Create a GD instance of the Hamming code of the given degree, i.e., of (2^deg - 1)-bit codewords, with a dictionary
+/// of dict_size entries. Returns NULL on failure.
+#[no_mangle]
+pub extern "C" fn gd_hamming_new(deg: usize, dict_size: usize) -> *mut GdHandle {
+  new_handle(GD::Hamming(deg), dict_size)
+}
+
+/// Free a handle. NULL is ignored.
+///
+/// # Safety
+/// handle must be NULL or returned by a constructor and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gd_free(handle: *mut GdHandle) {
+  if !handle.is_null() {
+    drop(Box::from_raw(handle));
+  }
+}
+
+/// Set the error alignment given by a rows x cols matrix in row-major order, which must be identical between dedup
+/// and dup instances. For Hamming codes, entries must be 0 or 1.
+///
+/// # Safety
+/// handle must be a valid handle, and matrix must point to rows * cols bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gd_set_error_alignment(
+  handle: *mut GdHandle,
+  matrix: *const u8,
+  rows: usize,
+  cols: usize,
+) -> GdStatus {
+  guard(|| {
+    let Some(handle) = handle.as_mut() else {
+      return invalid("Null handle");
+    };
+    let Some(entries) = rows.checked_mul(cols).and_then(|len| input(matrix, len)) else {
+      return invalid("Null matrix");
+    };
+    ensure!(cols > 0, "Empty matrix");
+    let trans = entries
+      .chunks(cols)
+      .map(|row| row.to_vec())
+      .collect::<Vec<_>>();
+    handle.rt.block_on(handle.gd.set_error_alignment(&trans))?;
+    Ok(GdStatus::Ok)
+  })
+}
+
+/// Upper bound of the length of deduplicated data of src_len bytes, with which gd_dedup never returns
+/// GD_STATUS_BUFFER_TOO_SMALL.
+///
+/// # Safety
+/// handle must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn gd_dedup_bound(handle: *const GdHandle, src_len: usize) -> usize {
+  match handle.as_ref() {
+    // every chunk costs at most its codeword padded to bytes, a separator, and an id of at most 8 bytes
+    Some(handle) => {
+      let chunk_bytelen = handle.gd.chunk_bytelen().max(1);
+      src_len.div_ceil(chunk_bytelen) * (chunk_bytelen + 10) + 16
+    }
+    None => 0,
+  }
+}
+
+/// Deduplicate src_len bytes of src into dst of dst_cap bytes. The length of the deduplicated data and the padding
+/// of its last chunk, which must be given to gd_dup, are set to out_len and out_pad.
+///
+/// # Safety
+/// handle must be a valid handle, src and dst must point to src_len and dst_cap bytes, and out_len and out_pad must be
+/// valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn gd_dedup(
+  handle: *mut GdHandle,
+  src: *const u8,
+  src_len: usize,
+  dst: *mut u8,
+  dst_cap: usize,
+  out_len: *mut usize,
+  out_pad: *mut usize,
+) -> GdStatus {
+  guard(|| {
+    let (Some(handle), Some(src), Some(out_len), Some(out_pad)) = (
+      handle.as_mut(),
+      input(src, src_len),
+      out_len.as_mut(),
+      out_pad.as_mut(),
+    ) else {
+      return invalid("Null pointer");
+    };
+    handle.run(Op::Dedup, src, dst, dst_cap, out_len, out_pad)
+  })
+}
+
+/// Duplicate src_len bytes of deduplicated data in src with the padding of its last chunk into dst of dst_cap bytes.
+/// The length of the duplicated data is set to out_len.
+///
+/// # Safety
+/// handle must be a valid handle, src and dst must point to src_len and dst_cap bytes, and out_len must be a valid
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn gd_dup(
+  handle: *mut GdHandle,
+  src: *const u8,
+  src_len: usize,
+  pad: usize,
+  dst: *mut u8,
+  dst_cap: usize,
+  out_len: *mut usize,
+) -> GdStatus {
+  guard(|| {
+    let (Some(handle), Some(src), Some(out_len)) =
+      (handle.as_mut(), input(src, src_len), out_len.as_mut())
+    else {
+      return invalid("Null pointer");
+    };
+    let mut out_pad = 0;
+    handle.run(Op::Dup(pad), src, dst, dst_cap, out_len, &mut out_pad)
+  })
+}
+
+/// Message of the last error on the calling thread, or NULL if none. Valid until the next error on the thread.
+#[no_mangle]
+pub extern "C" fn gd_last_error() -> *const c_char {
+  LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
+
+impl GdHandle {
+  unsafe fn run(
+    &mut self,
+    op: Op,
+    src: &[u8],
+    dst: *mut u8,
+    dst_cap: usize,
+    out_len: &mut usize,
+    out_pad: &mut usize,
+  ) -> Result<GdStatus> {
+    // the input is copied only when the output is kept for the repeated call
+    let (pending_src, out, pad) = match self.pending.take() {
+      Some(pending) if pending.op == op && pending.src == src => {
+        (Some(pending.src), pending.out, pending.pad)
+      }
+      Some(pending) => {
+        self.pending = Some(pending);
+        return invalid("Output of the previous call must be taken by repeating it");
+      }
+      None => {
+        let (out, pad) = self.process(&op, src)?;
+        (None, out, pad)
+      }
+    };
+
+    *out_len = out.len();
+    *out_pad = pad;
+    if dst_cap < out.len() {
+      self.pending = Some(Pending {
+        op,
+        src: pending_src.unwrap_or_else(|| src.to_vec()),
+        out,
+        pad,
+      });
+      set_last_error(format!("Buffer of {} bytes is required", *out_len));
+      return Ok(GdStatus::BufferTooSmall);
+    }
+    if !out.is_empty() {
+      ensure!(!dst.is_null(), "Null output buffer");
+      ptr::copy_nonoverlapping(out.as_ptr(), dst, out.len());
+    }
+    Ok(GdStatus::Ok)
+  }
+
+  fn process(&mut self, op: &Op, src: &[u8]) -> Result<(Vec<u8>, usize)> {
+    match op {
+      Op::Dedup => {
+        let deduped = self.rt.block_on(self.gd.dedup(src))?;
+        Ok((deduped.data, deduped.last_chunk_pad_bytelen))
+      }
+      Op::Dup(pad) => {
+        let deduped = Deduped {
+          data: src.to_vec(),
+          last_chunk_pad_bytelen: *pad,
+        };
+        let duped = self
+          .rt
+          .block_on(self.gd.dup(&deduped))
+          .map_err(|e| anyhow!("Failed to duplicate: {}", e))?;
+        Ok((duped, 0))
+      }
+    }
+  }
+}
@@ -0,0 +1,156 @@
+// Test program of the C ABI of rust-gd, built and run by tests/c_program.rs.
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "rust_gd.h"
+
+#define CHECK(cond)                                                          \
+  do {                                                                       \
+    if (!(cond)) {                                                           \
+      const char *err = gd_last_error();                                     \
+      fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n", __FILE__, \
+              __LINE__, #cond, err ? err : "none");                          \
+      exit(1);                                                               \
+    }                                                                        \
+  } while (0)
+
+// Deduplicate and duplicate src with a pair of handles, and check the roundtrip
+static size_t roundtrip(GdHandle *dedup, GdHandle *dup, const uint8_t *src,
+                        size_t src_len) {
+  size_t bound = gd_dedup_bound(dedup, src_len);
+  uint8_t *deduped = malloc(bound);
+  size_t deduped_len = 0, pad = 0;
+  CHECK(deduped != NULL);
+  CHECK(gd_dedup(dedup, src, src_len, deduped, bound, &deduped_len, &pad) ==
+        GD_STATUS_OK);
+  CHECK(deduped_len <= bound);
+
+  uint8_t *duped = malloc(src_len + 1);
+  size_t duped_len = 0;
+  CHECK(duped != NULL);
+  CHECK(gd_dup(dup, deduped, deduped_len, pad, duped, src_len + 1,
+               &duped_len) == GD_STATUS_OK);
+  CHECK(duped_len == src_len);
+  CHECK(memcmp(duped, src, src_len) == 0);
+
+  free(deduped);
+  free(duped);
+  return deduped_len;
+}
+
+static void test_reed_solomon(void) {
+  const char *word = "寿限無(じゅげむ)寿限無(じゅげむ)五劫(ごこう)のすりきれ";
+  size_t word_len = strlen(word);
+  size_t src_len = word_len * 64;
+  uint8_t *src = malloc(src_len);
+  CHECK(src != NULL);
+  for (size_t i = 0; i < 64; i++) {
+    memcpy(src + i * word_len, word, word_len);
+  }
+
+  GdHandle *dedup = gd_reed_solomon_new(10, 8, 255);
+  GdHandle *dup = gd_reed_solomon_new(10, 8, 255);
+  CHECK(dedup != NULL && dup != NULL);
+  size_t deduped_len = roundtrip(dedup, dup, src, src_len);
+  printf("RS(10,8) > %zu bytes -> %zu bytes\n", src_len, deduped_len);
+  CHECK(deduped_len < src_len);
+
+  // a small buffer keeps the output until the same call is repeated
+  uint8_t small[4];
+  uint8_t *deduped = NULL;
+  size_t required = 0, pad = 0;
+  CHECK(gd_dedup(dedup, src, 100, small, sizeof(small), &required, &pad) ==
+        GD_STATUS_BUFFER_TOO_SMALL);
+  CHECK(required > sizeof(small));
+  CHECK(gd_dup(dedup, small, 0, 0, small, sizeof(small), &required) ==
+        GD_STATUS_INVALID_ARGUMENT);
+  deduped = malloc(required);
+  CHECK(deduped != NULL);
+  size_t deduped_len2 = 0;
+  CHECK(gd_dedup(dedup, src, 100, deduped, required, &deduped_len2, &pad) ==
+        GD_STATUS_OK);
+  CHECK(deduped_len2 == required);
+  uint8_t duped[100];
+  size_t duped_len = 0;
+  CHECK(gd_dup(dup, deduped, deduped_len2, pad, duped, sizeof(duped),
+               &duped_len) == GD_STATUS_OK);
+  CHECK(duped_len == 100 && memcmp(duped, src, 100) == 0);
+  free(deduped);
+
+  // error alignment by a permutation of 10 symbols, set on both sides
+  uint8_t perm[10 * 10] = {0};
+  for (size_t i = 0; i < 10; i++) {
+    perm[i * 10 + (i + 3) % 10] = 1;
+  }
+  CHECK(gd_set_error_alignment(dedup, perm, 10, 10) == GD_STATUS_OK);
+  CHECK(gd_set_error_alignment(dup, perm, 10, 10) == GD_STATUS_OK);
+  roundtrip(dedup, dup, src, src_len);
+
+  // broken inputs are reported rather than crashing
+  CHECK(gd_set_error_alignment(dedup, perm, 3, 3) == GD_STATUS_FAILED);
+  CHECK(gd_last_error() != NULL);
+  uint8_t garbage[3] = {0xff, 0xff, 0xff};
+  uint8_t out[64];
+  size_t out_len = 0;
+  CHECK(gd_dup(dup, garbage, sizeof(garbage), 0, out, sizeof(out),
+               &out_len) == GD_STATUS_FAILED);
+
+  gd_free(dedup);
+  gd_free(dup);
+  free(src);
+}
+
+static void test_hamming(void) {
+  const char *word = "the quick brown fox jumps over the lazy dog. ";
+  size_t word_len = strlen(word);
+  size_t src_len = word_len * 32;
+  uint8_t *src = malloc(src_len);
+  CHECK(src != NULL);
+  for (size_t i = 0; i < 32; i++) {
+    memcpy(src + i * word_len, word, word_len);
+  }
+
+  GdHandle *dedup = gd_hamming_new(7, 255);
+  GdHandle *dup = gd_hamming_new(7, 255);
+  CHECK(dedup != NULL && dup != NULL);
+  size_t deduped_len = roundtrip(dedup, dup, src, src_len);
+  printf("Hamming(7) > %zu bytes -> %zu bytes\n", src_len, deduped_len);
+  CHECK(deduped_len < src_len);
+
+  gd_free(dedup);
+  gd_free(dup);
+  free(src);
+}
+
+static void test_invalid_arguments(void) {
+  CHECK(gd_reed_solomon_new(8, 10, 255) == NULL);
+  CHECK(gd_last_error() != NULL);
+  CHECK(gd_hamming_new(1, 255) == NULL);
+  gd_free(NULL);
+  CHECK(gd_dedup_bound(NULL, 100) == 0);
+
+  size_t len = 0, pad = 0;
+  uint8_t buf[16];
+  CHECK(gd_dedup(NULL, buf, sizeof(buf), buf, sizeof(buf), &len, &pad) ==
+        GD_STATUS_INVALID_ARGUMENT);
+  GdHandle *gd = gd_reed_solomon_new(10, 8, 16);
+  CHECK(gd != NULL);
+  CHECK(gd_dedup(gd, NULL, 4, buf, sizeof(buf), &len, &pad) ==
+        GD_STATUS_INVALID_ARGUMENT);
+  CHECK(gd_dedup(gd, buf, sizeof(buf), buf, sizeof(buf), NULL, &pad) ==
+        GD_STATUS_INVALID_ARGUMENT);
+  CHECK(gd_set_error_alignment(gd, NULL, 10, 10) == GD_STATUS_INVALID_ARGUMENT);
+  // empty input is fine with null pointers
+  CHECK(gd_dedup(gd, NULL, 0, NULL, 0, &len, &pad) == GD_STATUS_OK);
+  CHECK(len == 0);
+  gd_free(gd);
+}
+
+int main(void) {
+  test_reed_solomon();
+  test_hamming();
+  test_invalid_arguments();
+  printf("All C tests passed\n");
+  return 0;
+}
@@ -0,0 +1,35 @@
+// Build tests/c/test_gd.c against the static library and the generated header, and run it.
+use std::{env, path::PathBuf, process::Command};
+
+#[test]
+fn c_program_works() {
+  let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  // the static library is built into target/<profile>/deps together with the test binary
+  let lib_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+  let staticlib = lib_dir.join("librust_gd_ffi.a");
+  assert!(staticlib.exists(), "{} is not built", staticlib.display());
+
+  let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+  let exe = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_gd");
+  let status = Command::new(cc)
+    .arg("-std=c99")
+    .arg("-Wall")
+    .arg("-Werror")
+    .arg("-I")
+    .arg(manifest_dir.join("include"))
+    .arg(manifest_dir.join("tests/c/test_gd.c"))
+    .arg(&staticlib)
+    .args(["-lpthread", "-ldl", "-lm", "-o"])
+    .arg(&exe)
+    .status()
+    .expect("C compiler is not available");
+  assert!(status.success(), "Failed to compile the C test program");
+
+  let output = Command::new(&exe).output().unwrap();
+  println!("{}", String::from_utf8_lossy(&output.stdout));
+  assert!(
+    output.status.success(),
+    "C test program failed: {}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+}
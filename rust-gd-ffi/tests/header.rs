@@ -0,0 +1,18 @@
+// The checked-in include/rust_gd.h must be identical to the header generated from the exported functions.
+// Run with UPDATE_HEADER=1 to overwrite it with the generated one.
+use std::{env, fs, path::PathBuf};
+
+#[test]
+fn header_is_up_to_date() {
+  let generated = fs::read_to_string(PathBuf::from(env!("OUT_DIR")).join("rust_gd.h")).unwrap();
+  let checked_in_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include/rust_gd.h");
+  if env::var_os("UPDATE_HEADER").is_some() {
+    fs::write(&checked_in_path, &generated).unwrap();
+  }
+  let checked_in = fs::read_to_string(&checked_in_path).unwrap();
+  assert!(
+    generated == checked_in,
+    "{} is outdated, run the tests with UPDATE_HEADER=1 to regenerate it",
+    checked_in_path.display()
+  );
+}
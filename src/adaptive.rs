@@ -0,0 +1,244 @@
+use crate::{
+  error::*,
+  util::{get_varint, put_varint},
+  Deduped, GDInner, GD,
+};
+use libecc::types::*;
+
+// Statistics of a frame deduplicated by a GD instance
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameStats {
+  pub chunks: usize,
+  // chunks whose bases are found in the dictionary or identical to that of the preceding chunk
+  pub base_hits: usize,
+  // counted only if enabled by GDInner::set_deviation_stats, and 0 otherwise
+  pub distinct_deviations: usize,
+}
+
+impl FrameStats {
+  pub fn hit_rate(&self) -> f64 {
+    if self.chunks == 0 {
+      return 1.0;
+    }
+    self.base_hits as f64 / self.chunks as f64
+  }
+
+  // ratio of distinct deviations to chunks, which is low when errors are aligned to the code
+  pub fn deviation_diversity(&self) -> f64 {
+    if self.chunks == 0 {
+      return 0.0;
+    }
+    self.distinct_deviations as f64 / self.chunks as f64
+  }
+}
+
+// Conditions on the statistics of the active GD instance to try switching to another one
+#[derive(Debug, Clone)]
+pub struct AdaptivePolicy {
+  pub min_hit_rate: f64,
+  pub max_deviation_diversity: f64,
+  // minimum number of frames between trials
+  pub probe_interval: usize,
+}
+
+impl Default for AdaptivePolicy {
+  fn default() -> Self {
+    AdaptivePolicy {
+      min_hit_rate: 0.5,
+      max_deviation_diversity: 0.5,
+      probe_interval: 16,
+    }
+  }
+}
+
+// GD switching among preconfigured GD instances at frame boundaries.
+// Deduplicated data is [index of the GD instance (varint)][deduplicated data by the instance].
+// When the statistics of the active instance violate the policy, the next frame is deduplicated by every instance and
+// the smallest one is emitted, where changes of dictionaries by the others are undone. The dup side follows the switch
+// since each frame is duplicated by the instance recorded in it. The first frame is always tried.
+#[derive(Debug)]
+pub struct AdaptiveGD {
+  inners: Vec<GDInner>,
+  policy: AdaptivePolicy,
+  active: usize,
+  frames_since_trial: usize,
+  trial_pending: bool,
+}
+
+impl AdaptiveGD {
+  pub async fn setup(candidates: &[(GD, usize)], policy: AdaptivePolicy) -> Result<Self> {
+    ensure!(!candidates.is_empty(), "No candidate configuration");
+    ensure!(policy.probe_interval > 0, "Probe interval must be positive");
+    let mut inners = Vec::with_capacity(candidates.len());
+    for (gd, dict_size) in candidates.iter() {
+      let mut inner = gd.setup(*dict_size).await?;
+      inner.set_deviation_stats(true);
+      inners.push(inner);
+    }
+    Ok(AdaptiveGD {
+      inners,
+      policy,
+      active: 0,
+      frames_since_trial: 0,
+      trial_pending: true,
+    })
+  }
+
+  // Access to each GD instance, e.g., to set error alignment.
+  // Dictionary synchronization, packet mode and shared dictionary are not available for instances.
+  pub fn gd_mut(&mut self, gd_idx: usize) -> Option<&mut GDInner> {
+    self.inners.get_mut(gd_idx)
+  }
+
+  // Index of the GD instance used for the last frame
+  pub fn active(&self) -> usize {
+    self.active
+  }
+
+  pub fn last_stats(&self) -> &FrameStats {
+    self.inners[self.active].last_stats()
+  }
+
+  pub async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let deduped = if self.trial_pending && self.inners.len() > 1 {
+      self.frames_since_trial = 0;
+      self.trial(buf).await?
+    } else {
+      self.inners[self.active].dedup(buf).await?
+    };
+    self.frames_since_trial += 1;
+
+    let stats = self.last_stats();
+    self.trial_pending = self.frames_since_trial >= self.policy.probe_interval
+      && (stats.hit_rate() < self.policy.min_hit_rate
+        || stats.deviation_diversity() > self.policy.max_deviation_diversity);
+
+    let mut data = U8VRep::new();
+    put_varint(&mut data, self.active);
+    data.extend_from_slice(&deduped.data);
+    Ok(Deduped {
+      data,
+      last_chunk_pad_bytelen: deduped.last_chunk_pad_bytelen,
+    })
+  }
+
+  // Deduplicate the frame by every instance and keep only the smallest one, preferring the active instance on ties
+  async fn trial(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let mut trials = Vec::with_capacity(self.inners.len());
+    let mut failure = None;
+    for inner in self.inners.iter_mut() {
+      let literal = match inner.begin_journal() {
+        Ok(literal) => literal,
+        Err(e) => {
+          failure = Some(e);
+          break;
+        }
+      };
+      match inner.dedup(buf).await {
+        Ok(deduped) => trials.push((literal, deduped)),
+        Err(e) => {
+          inner.end_journal(true, literal)?;
+          failure = Some(e);
+          break;
+        }
+      }
+    }
+    let best = match failure {
+      Some(_) => None,
+      None => (0..trials.len()).min_by_key(|i| (trials[*i].1.data.len(), *i != self.active)),
+    };
+
+    let mut chosen = None;
+    for (i, (literal, deduped)) in trials.into_iter().enumerate() {
+      let rollback = Some(i) != best;
+      self.inners[i].end_journal(rollback, literal)?;
+      if !rollback {
+        chosen = Some(deduped);
+      }
+    }
+    if let Some(e) = failure {
+      return Err(e);
+    }
+    self.active = best.ok_or(anyhow!("No GD instance"))?;
+    chosen.ok_or(anyhow!("No GD instance"))
+  }
+
+  pub async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
+    let (gd_idx, len) = get_varint(&deduped.data)?;
+    ensure!(gd_idx < self.inners.len(), "Unknown GD instance {}", gd_idx);
+    self.active = gd_idx;
+    let part = Deduped {
+      data: deduped.data[len..].to_vec(),
+      last_chunk_pad_bytelen: deduped.last_chunk_pad_bytelen,
+    };
+    self.inners[gd_idx].dup(&part).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  // frame of records randomly drawn from a pool of 8 records of the given length
+  fn frame(rng: &mut StdRng, pool: &[U8VRep]) -> U8VRep {
+    (0..160)
+      .flat_map(|_| pool[rng.gen_range(0..pool.len())].clone())
+      .take(1600)
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn adaptive_works() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let pool_16 = (0..8)
+      .map(|_| (0..16).map(|_| rng.gen()).collect())
+      .collect::<Vec<U8VRep>>();
+    let pool_10 = (0..8)
+      .map(|_| (0..10).map(|_| rng.gen()).collect())
+      .collect::<Vec<U8VRep>>();
+    // data changes its alignment from 16 bytes to 10 bytes
+    let frames = (0..16)
+      .map(|i| frame(&mut rng, if i < 8 { &pool_16 } else { &pool_10 }))
+      .collect::<Vec<_>>();
+
+    let candidates = [
+      (GD::ReedSolomon(16, 15), 255),
+      (GD::ReedSolomon(10, 9), 255),
+    ];
+    let policy = AdaptivePolicy {
+      probe_interval: 1,
+      ..Default::default()
+    };
+    let mut gd_dedup = AdaptiveGD::setup(&candidates, policy.clone())
+      .await
+      .unwrap();
+    let mut gd_dup = AdaptiveGD::setup(&candidates, policy).await.unwrap();
+    let mut gd_fixed = candidates[0].0.setup(candidates[0].1).await.unwrap();
+
+    let (mut adaptive_bytelen, mut fixed_bytelen) = (0, 0);
+    let mut actives = vec![];
+    for buf in frames.iter() {
+      let x = gd_dedup.dedup(buf).await.unwrap();
+      assert_eq!(&gd_dup.dup(&x).await.unwrap(), buf);
+      assert_eq!(gd_dup.active(), gd_dedup.active());
+      actives.push(gd_dedup.active());
+      adaptive_bytelen += x.data.len();
+      fixed_bytelen += gd_fixed.dedup(buf).await.unwrap().data.len();
+    }
+    println!(
+      "Adaptive > {} bytes (fixed: {} bytes), instances: {:?}",
+      adaptive_bytelen, fixed_bytelen, actives
+    );
+    assert_eq!(actives[0], 0);
+    assert_eq!(actives[15], 1);
+    assert!(adaptive_bytelen < fixed_bytelen);
+
+    // distinct deviations are counted only if enabled, as by AdaptiveGD
+    assert!(gd_dedup.last_stats().distinct_deviations > 0);
+    assert_eq!(gd_fixed.last_stats().distinct_deviations, 0);
+    gd_fixed.set_deviation_stats(true);
+    gd_fixed.dedup(&frames[0]).await.unwrap();
+    assert!(gd_fixed.last_stats().distinct_deviations > 0);
+  }
+}
@@ -0,0 +1,111 @@
+use crate::{error::*, GDInner, GD};
+use libecc::types::*;
+
+// Result of deduplication of a sample with a configuration, i.e., a GD and its dictionary size
+#[derive(Debug, Clone)]
+pub struct Analysis {
+  pub gd: GD,
+  pub dict_size: usize,
+  pub sample_bytelen: usize,
+  pub deduped_bytelen: usize,
+}
+
+impl Analysis {
+  // deduplicated size relative to the sample
+  pub fn rate(&self) -> f64 {
+    self.deduped_bytelen as f64 / self.sample_bytelen.max(1) as f64
+  }
+
+  pub async fn setup(&self) -> Result<GDInner> {
+    self.gd.setup(self.dict_size).await
+  }
+}
+
+// Candidate configurations covering typical alignments of data: Reed-Solomon codes of lengths 4 to 128 bytes with
+// deviations of 1, 2 and 4 bytes, and Hamming codes of degrees 4 to 10, each with dictionaries of 2^8-1, 2^12-1 and
+// 2^16-1 entries.
+pub fn default_candidates() -> Vec<(GD, usize)> {
+  let dict_sizes = [(1 << 8) - 1, (1 << 12) - 1, (1 << 16) - 1];
+  let rs = [4, 8, 16, 32, 64, 128].into_iter().flat_map(|code_len| {
+    [1, 2, 4]
+      .into_iter()
+      .filter(move |dev_len| *dev_len < code_len)
+      .map(move |dev_len| GD::ReedSolomon(code_len, code_len - dev_len))
+  });
+  let hamming = (4..=10).map(GD::Hamming);
+  rs.chain(hamming)
+    .flat_map(|gd| dict_sizes.map(|dict_size| (gd.clone(), dict_size)))
+    .collect()
+}
+
+// Deduplicate the sample with every candidate configuration through the actual dedup path, and return the results
+// ranked from the smallest deduplicated size, where ties are broken by the smaller dictionary.
+pub async fn analyze(sample: &U8SRep, candidates: &[(GD, usize)]) -> Result<Vec<Analysis>> {
+  ensure!(!candidates.is_empty(), "No candidate configuration");
+  let mut ranked = Vec::with_capacity(candidates.len());
+  for (gd, dict_size) in candidates.iter() {
+    let mut gd_inner = gd.setup(*dict_size).await?;
+    let deduped = gd_inner.dedup(sample).await?;
+    ranked.push(Analysis {
+      gd: gd.clone(),
+      dict_size: *dict_size,
+      sample_bytelen: sample.len(),
+      deduped_bytelen: deduped.data.len(),
+    });
+  }
+  ranked.sort_by_key(|a| (a.deduped_bytelen, a.dict_size));
+  Ok(ranked)
+}
+
+// Analyze the sample and set up the best configuration with a fresh dictionary
+pub async fn setup_best(
+  sample: &U8SRep,
+  candidates: &[(GD, usize)],
+) -> Result<(GDInner, Vec<Analysis>)> {
+  let ranked = analyze(sample, candidates).await?;
+  let best = ranked[0].setup().await?;
+  Ok((best, ranked))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn analyze_works() {
+    // records of 16 bytes, each of which differs from the others only in its last byte
+    let sample = (0..2048u32)
+      .flat_map(|i| {
+        let mut record = b"record-aligned-".to_vec();
+        record.push((i % 7) as u8);
+        record
+      })
+      .collect::<Vec<_>>();
+    let candidates = vec![
+      (GD::ReedSolomon(10, 8), 255),
+      (GD::ReedSolomon(16, 15), 255),
+      (GD::ReedSolomon(16, 15), 4095),
+      (GD::Hamming(7), 255),
+    ];
+    let ranked = analyze(&sample, &candidates).await.unwrap();
+    assert_eq!(ranked.len(), candidates.len());
+    assert!(ranked
+      .windows(2)
+      .all(|w| w[0].deduped_bytelen <= w[1].deduped_bytelen));
+    // the code aligned to records wins with the smaller dictionary
+    assert!(matches!(ranked[0].gd, GD::ReedSolomon(16, 15)));
+    assert_eq!(ranked[0].dict_size, 255);
+    assert!(ranked[0].rate() < 0.2);
+
+    let (mut gd_dedup, _) = setup_best(&sample, &candidates).await.unwrap();
+    let mut gd_dup = ranked[0].setup().await.unwrap();
+    let x = gd_dedup.dedup(&sample).await.unwrap();
+    assert_eq!(x.data.len(), ranked[0].deduped_bytelen);
+    assert_eq!(gd_dup.dup(&x).await.unwrap(), sample);
+
+    assert!(analyze(&sample, &[]).await.is_err());
+    assert!(default_candidates().iter().all(
+      |(gd, _)| matches!(gd, GD::ReedSolomon(n, k) if k < n) || matches!(gd, GD::Hamming(4..=10))
+    ));
+  }
+}
@@ -0,0 +1,309 @@
+use crate::{
+  error::*,
+  util::{get_varint, put_varint},
+  Deduped, GDInner,
+};
+use libecc::types::*;
+use std::io::{self, Read, Write};
+use tokio::runtime::{Builder, Runtime};
+
+// Streaming format:
+// [MAGIC][frame bytelen (varint)][frame 0]...[frame N-1][0 (varint)][raw bytelen (varint)]
+// where each frame is [deduped bytelen (varint)][last chunk pad bytelen (varint)][deduped data], and the dictionary
+// is kept across frames. The end marker and the total length let the reader detect truncated streams.
+// Deduplicated data of every frame is bounded by the worst-case expansion of the configured GD, which both the writer
+// and the reader derive from it, and hence packet mode, whose frames are not bounded, is not available.
+const MAGIC: &[u8; 8] = b"RGDSTRM1";
+
+// Runtime driving async dedup and dup, which cannot be used within another tokio runtime
+fn runtime() -> Result<Runtime> {
+  Ok(Builder::new_current_thread().build()?)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Blocking writer implementing std::io::Write. The input is buffered and deduplicated every frame_bytelen bytes.
+// flush() emits the buffered input as a shorter frame, and finish() must be called to write the trailer.
+pub struct GdWriter<W: Write> {
+  inner: W,
+  gd: GDInner,
+  rt: Runtime,
+  frame_bytelen: usize,
+  max_deduped_bytelen: usize,
+  buffered: U8VRep,
+  raw_bytelen: usize,
+}
+
+impl<W: Write> GdWriter<W> {
+  pub fn new(mut inner: W, gd: GDInner, frame_bytelen: usize) -> Result<Self> {
+    let chunk_bytelen = gd.chunk_bytelen();
+    ensure!(
+      frame_bytelen > 0 && frame_bytelen.is_multiple_of(chunk_bytelen),
+      "Frame length {} must be a positive multiple of the chunk length {}",
+      frame_bytelen,
+      chunk_bytelen
+    );
+    let max_deduped_bytelen = gd.max_deduped_bytelen(frame_bytelen)?;
+    let mut header = MAGIC.to_vec();
+    put_varint(&mut header, frame_bytelen);
+    inner.write_all(&header)?;
+    Ok(GdWriter {
+      inner,
+      gd,
+      rt: runtime()?,
+      frame_bytelen,
+      max_deduped_bytelen,
+      buffered: U8VRep::with_capacity(frame_bytelen),
+      raw_bytelen: 0,
+    })
+  }
+
+  pub fn get_ref(&self) -> &W {
+    &self.inner
+  }
+
+  // Flush the buffered input and write the trailer. Returns the underlying writer.
+  pub fn finish(mut self) -> Result<W> {
+    self.write_buffered()?;
+    let mut trailer = U8VRep::new();
+    put_varint(&mut trailer, 0);
+    put_varint(&mut trailer, self.raw_bytelen);
+    self.inner.write_all(&trailer)?;
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+
+  fn write_frame(&mut self, frame: &U8SRep) -> Result<()> {
+    let deduped = self.rt.block_on(self.gd.dedup(frame))?;
+    ensure!(
+      deduped.data.len() <= self.max_deduped_bytelen,
+      "Too long frame of {} bytes beyond the bound of {} bytes",
+      deduped.data.len(),
+      self.max_deduped_bytelen
+    );
+    let mut header = U8VRep::new();
+    put_varint(&mut header, deduped.data.len());
+    put_varint(&mut header, deduped.last_chunk_pad_bytelen);
+    self.inner.write_all(&header)?;
+    self.inner.write_all(&deduped.data)?;
+    self.raw_bytelen += frame.len();
+    Ok(())
+  }
+
+  fn write_buffered(&mut self) -> Result<()> {
+    if self.buffered.is_empty() {
+      return Ok(());
+    }
+    let frame = std::mem::take(&mut self.buffered);
+    self.write_frame(&frame)
+  }
+}
+
+impl<W: Write> Write for GdWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let len = buf.len().min(self.frame_bytelen - self.buffered.len());
+    self.buffered.extend_from_slice(&buf[..len]);
+    if self.buffered.len() == self.frame_bytelen {
+      self.write_buffered().map_err(io::Error::other)?;
+    }
+    Ok(len)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.write_buffered().map_err(io::Error::other)?;
+    self.inner.flush()
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Blocking reader implementing std::io::Read, which duplicates frames one by one as they are consumed.
+// Reading a stream without the trailer fails rather than returning a silently truncated output.
+pub struct GdReader<R: Read> {
+  inner: R,
+  gd: GDInner,
+  rt: Runtime,
+  frame_bytelen: usize,
+  max_deduped_bytelen: usize,
+  duped: U8VRep,
+  duped_pos: usize,
+  raw_bytelen: usize,
+  finished: bool,
+}
+
+impl<R: Read> GdReader<R> {
+  pub fn new(mut inner: R, gd: GDInner) -> Result<Self> {
+    let mut magic = [0u8; 8];
+    inner.read_exact(&mut magic)?;
+    ensure!(&magic == MAGIC, "Not a streaming GD format");
+    let frame_bytelen = read_varint(&mut inner)?;
+    ensure!(
+      frame_bytelen > 0 && frame_bytelen.is_multiple_of(gd.chunk_bytelen()),
+      "Frame length {} is inconsistent with the chunk length {}",
+      frame_bytelen,
+      gd.chunk_bytelen()
+    );
+    let max_deduped_bytelen = gd.max_deduped_bytelen(frame_bytelen)?;
+    Ok(GdReader {
+      inner,
+      gd,
+      rt: runtime()?,
+      frame_bytelen,
+      max_deduped_bytelen,
+      duped: U8VRep::new(),
+      duped_pos: 0,
+      raw_bytelen: 0,
+      finished: false,
+    })
+  }
+
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+
+  // Duplicate the next frame. Returns false at the end of the stream.
+  fn next_frame(&mut self) -> Result<bool> {
+    let deduped_bytelen = read_varint(&mut self.inner)?;
+    if deduped_bytelen == 0 {
+      let raw_bytelen = read_varint(&mut self.inner)?;
+      ensure!(
+        raw_bytelen == self.raw_bytelen,
+        "Trailer records {} bytes but {} bytes are read",
+        raw_bytelen,
+        self.raw_bytelen
+      );
+      return Ok(false);
+    }
+    ensure!(
+      deduped_bytelen <= self.max_deduped_bytelen,
+      "Too long frame of {} bytes",
+      deduped_bytelen
+    );
+    let last_chunk_pad_bytelen = read_varint(&mut self.inner)?;
+    let mut data = vec![0u8; deduped_bytelen];
+    self.inner.read_exact(&mut data)?;
+    let deduped = Deduped {
+      data,
+      last_chunk_pad_bytelen,
+    };
+    self.duped = self.rt.block_on(self.gd.dup(&deduped))?;
+    ensure!(
+      !self.duped.is_empty() && self.duped.len() <= self.frame_bytelen,
+      "Broken frame of {} bytes",
+      self.duped.len()
+    );
+    self.duped_pos = 0;
+    self.raw_bytelen += self.duped.len();
+    Ok(true)
+  }
+}
+
+impl<R: Read> Read for GdReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if buf.is_empty() {
+      return Ok(0);
+    }
+    while self.duped_pos == self.duped.len() {
+      if self.finished {
+        return Ok(0);
+      }
+      self.finished = !self.next_frame().map_err(io::Error::other)?;
+      if self.finished {
+        self.duped.clear();
+        self.duped_pos = 0;
+      }
+    }
+    let len = buf.len().min(self.duped.len() - self.duped_pos);
+    buf[..len].copy_from_slice(&self.duped[self.duped_pos..self.duped_pos + len]);
+    self.duped_pos += len;
+    Ok(len)
+  }
+}
+
+fn read_varint<R: Read>(inner: &mut R) -> Result<usize> {
+  let mut bytes = U8VRep::new();
+  loop {
+    let mut b = [0u8; 1];
+    inner.read_exact(&mut b)?;
+    bytes.push(b[0]);
+    if b[0] & 0x80 == 0 {
+      return Ok(get_varint(&bytes)?.0);
+    }
+    ensure!(bytes.len() * 7 < usize::BITS as usize, "Too long varint");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{util::SplitMix64, GD};
+  use std::io::{copy, BufReader, BufWriter, Cursor};
+
+  #[test]
+  fn blocking_io_works() {
+    let words = "寿限無(じゅげむ)寿限無(じゅげむ)五劫(ごこう)のすりきれ海砂利(かいじゃり)"
+      .repeat(200)
+      .into_bytes();
+    let rt = runtime().unwrap();
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let gd_dedup = rt.block_on(gd.setup(255)).unwrap();
+      let gd_dup = rt.block_on(gd.setup(255)).unwrap();
+      let frame_bytelen = gd_dedup.chunk_bytelen() * 100;
+
+      let writer = GdWriter::new(Vec::new(), gd_dedup, frame_bytelen).unwrap();
+      let mut buffered = BufWriter::with_capacity(333, writer);
+      copy(&mut Cursor::new(&words), &mut buffered).unwrap();
+      buffered.flush().unwrap();
+      let mut writer = buffered.into_inner().ok().unwrap();
+      writer.write_all(&words[..77]).unwrap();
+      let stream = writer.finish().unwrap();
+      println!(
+        "Blocking > {} bytes -> {} bytes",
+        words.len() + 77,
+        stream.len()
+      );
+      // the dictionary is kept across frames
+      assert!(stream.len() < words.len() / 2);
+
+      let mut reader = BufReader::new(GdReader::new(stream.as_slice(), gd_dup).unwrap());
+      let mut duped = Vec::new();
+      reader.read_to_end(&mut duped).unwrap();
+      assert_eq!(duped[..words.len()], words);
+      assert_eq!(duped[words.len()..], words[..77]);
+
+      // streams without the trailer are rejected
+      let truncated = &stream[..stream.len() - 2];
+      let mut reader = GdReader::new(truncated, rt.block_on(gd.setup(255)).unwrap()).unwrap();
+      assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+  }
+
+  #[test]
+  fn blocking_io_bounds_expanding_frames() {
+    // random data expanded by Hamming(4), whose 1-byte chunks take AsIs tokens of 2 + 11 + 4 bits with multi-symbol
+    // separator, beyond twice the input of a frame for a dictionary of a single base
+    let mut rng = SplitMix64::new(47);
+    let words = (0..1 << 15)
+      .map(|_| rng.next_u64() as u8)
+      .collect::<Vec<_>>();
+    let rt = runtime().unwrap();
+    let mut gd_dedup = rt.block_on(GD::Hamming(4).setup(1)).unwrap();
+    let mut gd_dup = rt.block_on(GD::Hamming(4).setup(1)).unwrap();
+    gd_dedup.set_multi_symbol_separator().unwrap();
+    gd_dup.set_multi_symbol_separator().unwrap();
+    let frame_bytelen = 1 << 15;
+
+    let mut writer = GdWriter::new(Vec::new(), gd_dedup, frame_bytelen).unwrap();
+    writer.write_all(&words).unwrap();
+    let stream = writer.finish().unwrap();
+    assert!(stream.len() > words.len() * 2 + 1024);
+
+    let mut reader = GdReader::new(stream.as_slice(), gd_dup).unwrap();
+    let mut duped = Vec::new();
+    reader.read_to_end(&mut duped).unwrap();
+    assert_eq!(duped, words);
+
+    // packet mode is not available since its frames are not bounded
+    let mut gd_packet = rt.block_on(GD::Hamming(4).setup(255)).unwrap();
+    gd_packet.set_packet_mode(4).unwrap();
+    assert!(GdWriter::new(Vec::new(), gd_packet, frame_bytelen).is_err());
+  }
+}
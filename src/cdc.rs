@@ -0,0 +1,157 @@
+use crate::{
+  error::*,
+  util::{get_varint, put_varint},
+};
+use libecc::types::*;
+
+// Gear hash table generated by splitmix64 with a fixed seed, which must be shared between dedup and dup sides
+const GEAR: [u64; 256] = {
+  let mut table = [0u64; 256];
+  let mut state = 0x5EED_0F6D_u64;
+  let mut i = 0;
+  while i < 256 {
+    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    table[i] = z ^ (z >> 31);
+    i += 1;
+  }
+  table
+};
+
+/// Content-defined chunking based on gear hash (FastCDC with normalized chunking).
+/// Given data is split into variable-length segments at content-defined boundaries before GD,
+/// so that an inserted or deleted byte shifts only the segment containing it.
+#[derive(Debug, Clone)]
+pub struct ContentDefinedChunker {
+  min_bytelen: usize,
+  avg_bytelen: usize,
+  max_bytelen: usize,
+  mask_small: u64, // used before reaching avg_bytelen, harder to find a boundary
+  mask_large: u64, // used after reaching avg_bytelen, easier to find a boundary
+}
+
+impl ContentDefinedChunker {
+  pub fn new(min_bytelen: usize, avg_bytelen: usize, max_bytelen: usize) -> Result<Self> {
+    ensure!(
+      0 < min_bytelen && min_bytelen < avg_bytelen && avg_bytelen < max_bytelen,
+      "Invalid params: 0 < min < avg < max is required"
+    );
+    // mask bits are taken from MSBs since they reflect the hash of a wider window
+    let bits = (usize::BITS - 1 - avg_bytelen.leading_zeros()).max(2);
+    Ok(ContentDefinedChunker {
+      min_bytelen,
+      avg_bytelen,
+      max_bytelen,
+      mask_small: !0u64 << (u64::BITS - (bits + 1)),
+      mask_large: !0u64 << (u64::BITS - (bits - 1)),
+    })
+  }
+
+  // Returns the length of the first segment of the given buffer
+  fn cut(&self, buf: &U8SRep) -> usize {
+    if buf.len() <= self.min_bytelen {
+      return buf.len();
+    }
+    let end = buf.len().min(self.max_bytelen);
+    let normal = end.min(self.avg_bytelen);
+
+    let mut hash = 0u64;
+    for (idx, b) in buf.iter().enumerate().take(end).skip(self.min_bytelen) {
+      hash = (hash << 1).wrapping_add(GEAR[*b as usize]);
+      let mask = if idx < normal {
+        self.mask_small
+      } else {
+        self.mask_large
+      };
+      if hash & mask == 0 {
+        return idx + 1;
+      }
+    }
+    end
+  }
+
+  // Upper bound of the number of segments of bytelen bytes, where every segment but the last is longer than min_bytelen
+  pub(crate) fn max_segments(&self, bytelen: usize) -> usize {
+    bytelen.div_ceil(self.min_bytelen)
+  }
+
+  pub fn segment_lens(&self, buf: &U8SRep) -> Vec<usize> {
+    let mut res = Vec::new();
+    let mut ptr = 0usize;
+    while ptr < buf.len() {
+      let len = self.cut(&buf[ptr..]);
+      res.push(len);
+      ptr += len;
+    }
+    res
+  }
+}
+
+// Segment lengths are encoded in the header of deduplicated data so that dup can reassemble segments
+pub(crate) fn encode_segment_header(segment_lens: &[usize]) -> U8VRep {
+  let mut header = U8VRep::new();
+  put_varint(&mut header, segment_lens.len());
+  segment_lens
+    .iter()
+    .for_each(|len| put_varint(&mut header, *len));
+  header
+}
+
+// Returns segment lengths and the byte length of the header
+pub(crate) fn decode_segment_header(data: &U8SRep) -> Result<(Vec<usize>, usize)> {
+  let (segment_num, mut ptr) = get_varint(data)?;
+  let mut segment_lens = Vec::new();
+  for _ in 0..segment_num {
+    let (len, consumed) = get_varint(&data[ptr..])?;
+    segment_lens.push(len);
+    ptr += consumed;
+  }
+  Ok((segment_lens, ptr))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  #[test]
+  fn cdc_resync_works() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let data: Vec<u8> = (0..16384).map(|_| rng.gen()).collect();
+    let chunker = ContentDefinedChunker::new(64, 256, 1024).unwrap();
+
+    let lens = chunker.segment_lens(&data);
+    assert_eq!(lens.iter().sum::<usize>(), data.len());
+    assert!(lens[..lens.len() - 1]
+      .iter()
+      .all(|l| *l >= 64 && *l <= 1024));
+
+    // insert a byte at the beginning, then boundaries are resynchronized right after the first segment
+    let mut shifted = vec![0xAAu8];
+    shifted.extend_from_slice(&data);
+    let shifted_lens = chunker.segment_lens(&shifted);
+    let ends = |lens: &[usize], offset: usize| {
+      lens
+        .iter()
+        .scan(0usize, |acc, l| {
+          *acc += l;
+          Some(*acc - offset)
+        })
+        .collect::<Vec<_>>()
+    };
+    let org_ends = ends(&lens, 0);
+    let shifted_ends = ends(&shifted_lens, 1);
+    let common = shifted_ends.iter().filter(|e| org_ends.contains(e)).count();
+    assert!(common + 2 >= org_ends.len());
+
+    let header = encode_segment_header(&lens);
+    assert_eq!(
+      decode_segment_header(&header).unwrap(),
+      (lens, header.len())
+    );
+
+    assert!(ContentDefinedChunker::new(256, 64, 1024).is_err());
+  }
+}
@@ -0,0 +1,183 @@
+#[cfg(feature = "std")]
+use crate::error::*;
+use alloc::{borrow::ToOwned, vec, vec::Vec};
+#[cfg(feature = "std")]
+use bitvec::prelude::*;
+use libecc::types::*;
+
+pub(crate) fn pad_bytelen(len: usize, chunk_bytelen: usize) -> usize {
+  let residue = len % chunk_bytelen;
+  if residue == 0 {
+    0
+  } else {
+    chunk_bytelen - residue
+  }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn pad_bitlen(bytelen: usize, chunk_bitlen: usize) -> usize {
+  let residue = (bytelen % chunk_bitlen) * u8::BITS as usize % chunk_bitlen;
+  if residue == 0 {
+    0
+  } else {
+    chunk_bitlen - residue
+  }
+}
+
+// Split each segment of a buffer into chunks of chunk_bytelen bytes,
+// where the last chunk of a segment is padded with zeros in front if needed
+pub(crate) fn split_into_chunks(
+  buf: &U8SRep,
+  chunk_bytelen: usize,
+  segment_lens: &[usize],
+) -> Vec<U8VRep> {
+  let mut res = Vec::new();
+  let mut seg_ptr = 0usize;
+  for len in segment_lens {
+    let segment = &buf[seg_ptr..seg_ptr + len];
+    let pad = pad_bytelen(*len, chunk_bytelen);
+    let mut byte_ptr = 0usize;
+    while byte_ptr < segment.len() {
+      if byte_ptr + chunk_bytelen > segment.len() {
+        let mut padded = vec![0u8; pad];
+        padded.extend_from_slice(&segment[byte_ptr..]);
+        res.push(padded);
+      } else {
+        res.push(segment[byte_ptr..byte_ptr + chunk_bytelen].to_owned());
+      }
+      byte_ptr += chunk_bytelen;
+    }
+    seg_ptr += len;
+  }
+  res
+}
+
+// Concatenate chunks and remove paddings in the last chunk of each segment
+#[cfg(feature = "std")]
+pub(crate) fn merge_chunks(
+  chunks: &[U8VRep],
+  chunk_bytelen: usize,
+  segment_lens: &[usize],
+) -> Result<U8VRep> {
+  let mut res = U8VRep::new();
+  let mut chunk_iter = chunks.iter();
+  for len in segment_lens {
+    let chunk_num = len.div_ceil(chunk_bytelen);
+    let pad = pad_bytelen(*len, chunk_bytelen);
+    for i in 0..chunk_num {
+      let chunk = chunk_iter.next().ok_or(anyhow!("Insufficient chunks"))?;
+      ensure!(chunk.len() == chunk_bytelen, "Invalid chunk length");
+      if i == chunk_num - 1 {
+        res.extend_from_slice(&chunk[pad..]);
+      } else {
+        res.extend_from_slice(chunk);
+      }
+    }
+  }
+  ensure!(chunk_iter.next().is_none(), "Too many chunks");
+  Ok(res)
+}
+
+// Split each segment of a buffer into chunks of chunk_bitlen bits across byte boundaries,
+// where the last chunk of a segment is padded with zero bits in front if needed
+#[cfg(feature = "std")]
+pub(crate) fn split_into_bit_chunks(
+  buf: &U8SRep,
+  chunk_bitlen: usize,
+  segment_lens: &[usize],
+) -> Vec<BVRep> {
+  let mut res = Vec::new();
+  let mut seg_ptr = 0usize;
+  for len in segment_lens {
+    let segment = buf[seg_ptr..seg_ptr + len].view_bits::<Msb0>();
+    let pad = pad_bitlen(*len, chunk_bitlen);
+    for chunk in segment.chunks(chunk_bitlen) {
+      if chunk.len() < chunk_bitlen {
+        let mut padded = bitvec![u8, Msb0; 0; pad];
+        padded.extend_from_bitslice(chunk);
+        res.push(padded);
+      } else {
+        res.push(chunk.to_bitvec());
+      }
+    }
+    seg_ptr += len;
+  }
+  res
+}
+
+// Concatenate chunks of chunk_bitlen bits and remove padding bits in the last chunk of each segment
+#[cfg(feature = "std")]
+pub(crate) fn merge_bit_chunks(
+  chunks: &[BVRep],
+  chunk_bitlen: usize,
+  segment_lens: &[usize],
+) -> Result<U8VRep> {
+  // segment lengths may be given in an untrusted frame
+  let chunk_nums = segment_lens
+    .iter()
+    .map(|len| {
+      len
+        .checked_mul(u8::BITS as usize)
+        .map(|bitlen| bitlen.div_ceil(chunk_bitlen))
+        .ok_or(anyhow!("Too long segment"))
+    })
+    .collect::<Result<Vec<_>>>()?;
+  ensure!(
+    chunk_nums
+      .iter()
+      .try_fold(0usize, |acc, n| acc.checked_add(*n))
+      == Some(chunks.len()),
+    "Segments are inconsistent with chunks"
+  );
+
+  let mut res = BVRep::new();
+  let mut chunk_iter = chunks.iter();
+  for (len, chunk_num) in segment_lens.iter().zip(chunk_nums) {
+    let pad = pad_bitlen(*len, chunk_bitlen);
+    for i in 0..chunk_num {
+      let chunk = chunk_iter.next().ok_or(anyhow!("Insufficient chunks"))?;
+      ensure!(chunk.len() == chunk_bitlen, "Invalid chunk length");
+      if i == chunk_num - 1 {
+        res.extend_from_bitslice(&chunk[pad..]);
+      } else {
+        res.extend_from_bitslice(chunk);
+      }
+    }
+  }
+  ensure!(chunk_iter.next().is_none(), "Too many chunks");
+  Ok(res.into_vec())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn split_merge_works() {
+    let buf: Vec<u8> = (0u8..20).collect();
+    let segment_lens = [7usize, 8, 5];
+    let chunks = split_into_chunks(&buf, 4, &segment_lens);
+    assert_eq!(chunks.len(), 2 + 2 + 2);
+    assert_eq!(chunks[1], vec![0u8, 4, 5, 6]);
+    assert_eq!(merge_chunks(&chunks, 4, &segment_lens).unwrap(), buf);
+    assert!(merge_chunks(&chunks[1..], 4, &segment_lens).is_err());
+  }
+
+  #[test]
+  fn split_merge_bits_works() {
+    let buf: Vec<u8> = (0u8..20).collect();
+    let segment_lens = [7usize, 8, 5];
+    // 56, 64 and 40 bits in chunks of 15 bits
+    let chunks = split_into_bit_chunks(&buf, 15, &segment_lens);
+    assert_eq!(chunks.len(), 4 + 5 + 3);
+    assert_eq!(chunks[0], buf.view_bits::<Msb0>()[..15]);
+    assert_eq!(chunks[3][..4], bits![u8, Msb0; 0; 4]);
+    assert_eq!(merge_bit_chunks(&chunks, 15, &segment_lens).unwrap(), buf);
+    assert!(merge_bit_chunks(&chunks[1..], 15, &segment_lens).is_err());
+    // segment lengths overflowing in bits or exceeding the chunks
+    assert_eq!(pad_bitlen(7, 15), 4);
+    assert_eq!(pad_bitlen(usize::MAX - 1, 15), 8);
+    assert!(merge_bit_chunks(&chunks, 15, &[1 << 62]).is_err());
+    assert!(merge_bit_chunks(&chunks, 15, &[usize::MAX / 16, usize::MAX / 16, 1]).is_err());
+  }
+}
@@ -0,0 +1,228 @@
+use crate::{
+  error::*,
+  util::{get_varint, put_varint, MAX_VARINT_BYTELEN},
+  Deduped, GDInner, GD,
+};
+use anyhow::Error;
+use bytes::{Buf, BytesMut};
+use futures::{SinkExt, StreamExt};
+use libecc::types::*;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+// Default upper bound of the length of a frame accepted by the decoder
+pub const DEFAULT_MAX_FRAME_BYTELEN: usize = 8 * 1024 * 1024;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Codec of deduplicated frames for tokio_util::codec::Framed. Each frame on the wire is
+// [bytelen of the rest (varint)][last chunk pad bytelen (varint)][deduplicated data].
+// The codec only frames Deduped, since Encoder and Decoder are synchronous while dedup and dup are async. GdFramed
+// runs GD on both directions of a Framed with this codec.
+#[derive(Debug, Clone)]
+pub struct GdCodec {
+  max_frame_bytelen: usize,
+}
+
+impl Default for GdCodec {
+  fn default() -> Self {
+    GdCodec {
+      max_frame_bytelen: DEFAULT_MAX_FRAME_BYTELEN,
+    }
+  }
+}
+
+impl GdCodec {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set_max_frame_bytelen(&mut self, max_frame_bytelen: usize) {
+    self.max_frame_bytelen = max_frame_bytelen;
+  }
+}
+
+// Length prefix of a frame, or None if more bytes are needed
+fn peek_frame_bytelen(src: &U8SRep) -> Result<Option<(usize, usize)>> {
+  match get_varint(src) {
+    Ok(res) => Ok(Some(res)),
+    Err(_) if src.len() < MAX_VARINT_BYTELEN && src.iter().all(|b| b & 0x80 != 0) => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+impl Encoder<Deduped> for GdCodec {
+  type Error = Error;
+
+  fn encode(&mut self, item: Deduped, dst: &mut BytesMut) -> Result<()> {
+    let mut pad = U8VRep::new();
+    put_varint(&mut pad, item.last_chunk_pad_bytelen);
+    let frame_bytelen = pad.len() + item.data.len();
+    ensure!(
+      frame_bytelen <= self.max_frame_bytelen,
+      "Frame of {} bytes exceeds the maximum {} bytes",
+      frame_bytelen,
+      self.max_frame_bytelen
+    );
+    let mut len = U8VRep::new();
+    put_varint(&mut len, frame_bytelen);
+    dst.reserve(len.len() + frame_bytelen);
+    dst.extend_from_slice(&len);
+    dst.extend_from_slice(&pad);
+    dst.extend_from_slice(&item.data);
+    Ok(())
+  }
+}
+
+impl Decoder for GdCodec {
+  type Item = Deduped;
+  type Error = Error;
+
+  fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Deduped>> {
+    let Some((frame_bytelen, len_bytelen)) = peek_frame_bytelen(src)? else {
+      return Ok(None);
+    };
+    ensure!(
+      frame_bytelen <= self.max_frame_bytelen,
+      "Frame of {} bytes exceeds the maximum {} bytes",
+      frame_bytelen,
+      self.max_frame_bytelen
+    );
+    if src.len() < len_bytelen + frame_bytelen {
+      src.reserve(len_bytelen + frame_bytelen - src.len());
+      return Ok(None);
+    }
+    src.advance(len_bytelen);
+    let frame = src.split_to(frame_bytelen);
+    let (last_chunk_pad_bytelen, pad_bytelen) = get_varint(&frame)?;
+    Ok(Some(Deduped {
+      data: frame[pad_bytelen..].to_vec(),
+      last_chunk_pad_bytelen,
+    }))
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// GD over a Framed transport with GdCodec, where dedup and dup are awaited on the calling task as GDInner itself, i.e.,
+// decoding and encoding of chunks run on the blocking thread pool of tokio. Separate GD instances are owned for sending
+// and receiving, so a single GdFramed can carry both directions of a connection.
+#[derive(Debug)]
+pub struct GdFramed<T> {
+  framed: Framed<T, GdCodec>,
+  encoder_gd: GDInner,
+  decoder_gd: GDInner,
+}
+
+impl<T> GdFramed<T>
+where
+  T: AsyncRead + AsyncWrite + Unpin,
+{
+  pub fn new(io: T, encoder_gd: GDInner, decoder_gd: GDInner) -> Self {
+    GdFramed {
+      framed: Framed::new(io, GdCodec::new()),
+      encoder_gd,
+      decoder_gd,
+    }
+  }
+
+  // Transport with fresh dictionaries of the given GD in both directions
+  pub async fn setup(io: T, gd: &GD, dict_size: usize) -> Result<Self> {
+    Ok(GdFramed::new(
+      io,
+      gd.setup(dict_size).await?,
+      gd.setup(dict_size).await?,
+    ))
+  }
+
+  pub fn set_max_frame_bytelen(&mut self, max_frame_bytelen: usize) {
+    self
+      .framed
+      .codec_mut()
+      .set_max_frame_bytelen(max_frame_bytelen);
+  }
+
+  pub fn encoder_gd_mut(&mut self) -> &mut GDInner {
+    &mut self.encoder_gd
+  }
+
+  pub fn decoder_gd_mut(&mut self) -> &mut GDInner {
+    &mut self.decoder_gd
+  }
+
+  // Deduplicate buf as a frame and send it
+  pub async fn send(&mut self, buf: &U8SRep) -> Result<()> {
+    let deduped = self.encoder_gd.dedup(buf).await?;
+    self.framed.send(deduped).await
+  }
+
+  // Receive a frame and duplicate it. Returns None at the end of the transport.
+  pub async fn next(&mut self) -> Option<Result<U8VRep>> {
+    match self.framed.next().await? {
+      Ok(deduped) => Some(self.decoder_gd.dup(&deduped).await),
+      Err(e) => Some(Err(e)),
+    }
+  }
+
+  pub fn into_inner(self) -> T {
+    self.framed.into_inner()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn codec_works() {
+    let records = (0..64u8)
+      .map(|i| {
+        let mut record = b"codec-frame-rec-".repeat(4);
+        record.push(i % 5);
+        record
+      })
+      .collect::<Vec<_>>();
+
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let (client, server) = tokio::io::duplex(256);
+      let mut tx = GdFramed::setup(client, &gd, 255).await.unwrap();
+      let mut rx = GdFramed::setup(server, &gd, 255).await.unwrap();
+      let sent = records.clone();
+      let sender = tokio::spawn(async move {
+        for record in sent.iter() {
+          tx.send(record).await.unwrap();
+        }
+        tx
+      });
+      for record in records.iter() {
+        assert_eq!(&rx.next().await.unwrap().unwrap(), record);
+      }
+      // the end of the transport after the sender is dropped
+      drop(sender.await.unwrap());
+      assert!(rx.next().await.is_none());
+    }
+
+    // partial frames wait for more bytes, and oversized frames are rejected
+    let mut gd_dedup = GD::ReedSolomon(10, 8).setup(255).await.unwrap();
+    let mut codec = GdCodec::new();
+    let mut wire = BytesMut::new();
+    let deduped = gd_dedup.dedup(&records[0]).await.unwrap();
+    codec.encode(deduped.clone(), &mut wire).unwrap();
+    let mut src = BytesMut::new();
+    for b in wire.iter().take(wire.len() - 1) {
+      src.extend_from_slice(&[*b]);
+      assert!(codec.decode(&mut src).unwrap().is_none());
+    }
+    src.extend_from_slice(&wire[wire.len() - 1..]);
+    let decoded = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(decoded.data, deduped.data);
+    assert_eq!(
+      decoded.last_chunk_pad_bytelen,
+      deduped.last_chunk_pad_bytelen
+    );
+    assert!(src.is_empty());
+
+    codec.encode(deduped.clone(), &mut src).unwrap();
+    codec.set_max_frame_bytelen(4);
+    assert!(codec.decode(&mut src).is_err());
+    assert!(codec.encode(deduped, &mut src).is_err());
+  }
+}
@@ -0,0 +1,172 @@
+use crate::{
+  chunk::{pad_bytelen, split_into_chunks},
+  dict::BasisDict,
+  dict_backend::DictBase,
+  error::*,
+  separator::{Separator, TokenWriter},
+  Deduped, GD,
+};
+use bitvec::prelude::*;
+use libecc::{types::*, *};
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Synchronous deduplicator available without std, e.g., for the dedup side running on embedded devices. Chunks are
+// decoded one by one on the calling thread, and the output is identical to that of GDInner::dedup of the same GD in its
+// default configuration, so that it is duplicated by GDInner::dup on the other side. Dictionary modes, content-defined
+// chunking, bitstream chunking, multi-level GD, multi-symbol separator, raw literals and stored fallback of GDInner are
+// not available, and neither is deletion-based GD.
+#[derive(Debug)]
+pub enum DedupGD {
+  ReedSolomon(ReedSolomon, BasisDict<U8VRep>),
+  Hamming(Hamming, BasisDict<BVRep>),
+}
+
+impl DedupGD {
+  pub(crate) fn new(gd: &GD, dict_size: usize) -> Result<Self> {
+    match gd {
+      GD::ReedSolomon(a, b) => Ok(DedupGD::ReedSolomon(
+        ReedSolomon::new_blocking(*a, *b)?,
+        BasisDict::new(dict_size),
+      )),
+      GD::Hamming(a) => {
+        let code = Hamming::new(*a as u32)?;
+        ensure!(code.code_bit_len >= 8, "Insufficient code length");
+        Ok(DedupGD::Hamming(code, BasisDict::new(dict_size)))
+      }
+      GD::Deletion(..) => bail!("Deletion-based GD is not available in DedupGD"),
+    }
+  }
+
+  pub fn chunk_bytelen(&self) -> usize {
+    match self {
+      DedupGD::ReedSolomon(code, _) => code.code_byte_len(),
+      DedupGD::Hamming(code, _) => code.code_bit_len / u8::BITS as usize,
+    }
+  }
+
+  // Must be identical to that of GDInner::set_error_alignment on the dup side
+  pub fn set_error_alignment(&mut self, trans: &[U8VRep]) -> Result<()> {
+    match self {
+      DedupGD::ReedSolomon(code, _) => code.set_precoding(trans),
+      DedupGD::Hamming(code, _) => code.set_precoding(trans),
+    }
+  }
+
+  // Drop all bases in the dictionary at the same position of a stream as GDInner::reset_dict on the dup side
+  pub fn reset_dict(&mut self) {
+    match self {
+      DedupGD::ReedSolomon(_, basis_dict) => basis_dict.clear(),
+      DedupGD::Hamming(_, basis_dict) => basis_dict.clear(),
+    }
+  }
+
+  pub fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let chunk_bytelen = self.chunk_bytelen();
+    let targets = split_into_chunks(buf, chunk_bytelen, &[buf.len()]);
+
+    let mut res = TokenWriter::new(false);
+    match self {
+      DedupGD::ReedSolomon(code, basis_dict) => {
+        for chunk in targets {
+          let decoded = code.decode(&chunk)?;
+          put_chunk(
+            &mut res,
+            basis_dict,
+            &decoded.base,
+            &BVRep::from_slice(&decoded.base),
+            &BVRep::from_slice(&decoded.deviation),
+          )?;
+        }
+      }
+      DedupGD::Hamming(code, basis_dict) => {
+        // Byte alignment as BitGD, i.e., each chunk of bytes is padded in front to the code length
+        let code_pad_len = code.code_bit_len - chunk_bytelen * u8::BITS as usize;
+        for chunk in targets {
+          let mut target = bitvec![u8, Msb0; 0; code_pad_len];
+          target.extend_from_raw_slice(&chunk);
+          let decoded = code.decode(&target)?;
+          put_chunk(
+            &mut res,
+            basis_dict,
+            &decoded.base,
+            &decoded.base,
+            &decoded.deviation,
+          )?;
+        }
+      }
+    }
+
+    Ok(Deduped {
+      data: res.finish(),
+      last_chunk_pad_bytelen: pad_bytelen(buf.len(), chunk_bytelen),
+    })
+  }
+}
+
+// Write [separator][id or base][deviation] of a chunk, where the base is put into the dictionary if not found
+fn put_chunk<T: DictBase>(
+  res: &mut TokenWriter,
+  basis_dict: &mut BasisDict<T>,
+  base: &T,
+  base_bits: &BSRep,
+  deviation_bits: &BSRep,
+) -> Result<()> {
+  match basis_dict.get_id(base)? {
+    Some(bit_id) => res.put_chunk(&Separator::Deduped, None, &bit_id, deviation_bits),
+    None => {
+      basis_dict.put_base(base)?;
+      res.put_chunk(&Separator::AsIs, None, base_bits, deviation_bits)
+    }
+  }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn dedup_gd_works() {
+    let words = "寿限無(じゅげむ)寿限無(じゅげむ)五劫(ごこう)のすりきれ海砂利(かいじゃり)"
+      .repeat(64)
+      .into_bytes();
+    let perm = (0..10)
+      .map(|i| (0..10).map(|j| (j == (i + 3) % 10) as u8).collect())
+      .collect::<Vec<U8VRep>>();
+
+    for (gd, trans) in [
+      (GD::ReedSolomon(10, 8), None),
+      (GD::ReedSolomon(10, 8), Some(perm)),
+      (GD::Hamming(7), None),
+      (GD::Hamming(4), None),
+    ] {
+      let mut dedup_gd = gd.setup_dedup(255).unwrap();
+      let mut gd_dedup = gd.setup(255).await.unwrap();
+      let mut gd_dup = gd.setup(255).await.unwrap();
+      if let Some(trans) = &trans {
+        dedup_gd.set_error_alignment(trans).unwrap();
+        gd_dedup.set_error_alignment(trans).await.unwrap();
+        gd_dup.set_error_alignment(trans).await.unwrap();
+      }
+      assert_eq!(dedup_gd.chunk_bytelen(), gd_dedup.chunk_bytelen());
+
+      // the dictionary is kept across frames, with a shorter last chunk in every frame
+      for frame in [&words[..], &words[..77], &words[5..]] {
+        let deduped = dedup_gd.dedup(frame).unwrap();
+        let expected = gd_dedup.dedup(frame).await.unwrap();
+        assert_eq!(deduped.data, expected.data);
+        assert_eq!(
+          deduped.last_chunk_pad_bytelen,
+          expected.last_chunk_pad_bytelen
+        );
+        assert_eq!(gd_dup.dup(&deduped).await.unwrap(), frame);
+      }
+
+      dedup_gd.reset_dict();
+      gd_dup.reset_dict();
+      let deduped = dedup_gd.dedup(&words).unwrap();
+      assert_eq!(gd_dup.dup(&deduped).await.unwrap(), words);
+    }
+
+    assert!(GD::Deletion(8, 4, 0).setup_dedup(255).is_err());
+  }
+}
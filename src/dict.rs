@@ -1,32 +1,200 @@
+use crate::dict_backend::{CompactDictBackend, DictBackend, DictBase};
 use crate::error::*;
+use crate::lru::LruOrder;
 use crate::types::*;
+use crate::util::Fnv64;
+use alloc::{boxed::Box, vec::Vec};
 use bitvec::prelude::*;
-use hashlink::LinkedHashMap;
-use std::collections::HashMap;
+use core::hash::{Hash, Hasher};
 
+pub(crate) fn id_bitlen_of(dict_size: usize) -> usize {
+  (0usize.leading_zeros() - dict_size.leading_zeros()) as usize
+}
+
+pub(crate) fn usize_id_to_bitvec_id(id: usize, id_bitlen: usize) -> IdRep {
+  let bs_id: BitVec<usize, Msb0> = BitVec::from_element(id);
+  let mut res = BVRep::new();
+  res.extend_from_bitslice(&bs_id[bs_id.len() - id_bitlen..]);
+  res
+}
+
+pub(crate) fn bitvec_id_to_usize_id(bit_id: &IdSRep) -> usize {
+  // https://github.com/bitvecto-rs/bitvec/issues/119
+  let mut id = 0usize;
+  for (mut dst, src) in id.view_bits_mut::<Lsb0>()[..bit_id.len()]
+    .iter_mut()
+    .zip(bit_id.iter().rev())
+  {
+    dst.set(*src);
+  }
+  id
+}
+
+// Change of the dictionary recorded in a journal to be undone
 #[derive(Debug, Clone)]
+enum JournalOp<T> {
+  // id moved to the back from the position next to prev (or the front if None)
+  Touched {
+    id: usize,
+    prev: Option<usize>,
+  },
+  // id newly assigned, evicting the base and its hash if given
+  Put {
+    id: usize,
+    evicted: Option<(T, u64)>,
+  },
+}
+
+// LRU dictionary assigning ids to bases, where the mapping between ids and bases and the LRU order of ids with hashes
+// of their bases are held by a backend. Nothing is kept per id in the dictionary itself but the journal.
+#[derive(Debug)]
 pub struct BasisDict<T>
 where
-  T: std::cmp::Eq + std::hash::Hash + std::clone::Clone + std::fmt::Debug,
+  T: core::cmp::Eq + core::hash::Hash + core::clone::Clone + core::fmt::Debug,
 {
   dict_size: usize,
   id_bitlen: usize,
-  dict_id_to_base: HashMap<usize, T>,
-  dict_base_to_id: LinkedHashMap<T, usize>,
-  // TODO: Add a table to manage LRU
+  backend: Box<dyn DictBackend<T>>,
+  // changes since the outermost begin_journal, which can be undone by rollback_journal,
+  // and positions in the journal where nested journals began
+  journal: Vec<JournalOp<T>>,
+  journal_marks: Vec<usize>,
+}
+
+// Snapshot of the entries of a dictionary in the LRU order from the least recently used one, where each base is given
+// in its byte representation of DictBase. Changes recorded in journals are not distinguished from committed ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DictSnapshot {
+  pub dict_size: usize,
+  // (id, base)
+  pub entries: Vec<(usize, U8VRep)>,
 }
 
 impl<T> BasisDict<T>
 where
-  T: std::cmp::Eq + std::hash::Hash + std::clone::Clone + std::fmt::Debug,
+  T: DictBase + 'static,
 {
   pub fn new(dict_size: usize) -> Self {
-    let id_bitlen = (0usize.leading_zeros() - dict_size.leading_zeros()) as usize;
+    Self::with_backend(dict_size, Box::new(CompactDictBackend::<T>::new(dict_size)))
+  }
+
+  // Copy of the dictionary, which fails if its backend cannot be copied, e.g., files of a file-backed one
+  pub fn try_clone(&self) -> Result<Self> {
+    Ok(BasisDict {
+      dict_size: self.dict_size,
+      id_bitlen: self.id_bitlen,
+      backend: self.backend.try_clone_box()?,
+      journal: self.journal.clone(),
+      journal_marks: self.journal_marks.clone(),
+    })
+  }
+}
+
+impl<T> BasisDict<T>
+where
+  T: DictBase,
+{
+  pub fn snapshot(&self) -> Result<DictSnapshot> {
+    let mut entries = Vec::with_capacity(self.lru().len());
+    for id in self.lru_ids() {
+      let base = self
+        .backend
+        .get_base(id)?
+        .ok_or(anyhow!("a base is missing for id {}", id))?;
+      entries.push((id, base.to_bytes()));
+    }
+    Ok(DictSnapshot {
+      dict_size: self.dict_size,
+      entries,
+    })
+  }
+
+  // Replace all entries with those of the snapshot, which must be taken from a dictionary of the same size.
+  // The dictionary is left empty if the snapshot is invalid.
+  pub fn restore(&mut self, snapshot: &DictSnapshot) -> Result<()> {
+    self.clear();
+    let res = self.restore_entries(snapshot);
+    if res.is_err() {
+      self.clear();
+    }
+    res
+  }
+
+  fn restore_entries(&mut self, snapshot: &DictSnapshot) -> Result<()> {
+    ensure!(
+      snapshot.dict_size == self.dict_size,
+      "Snapshot of a dictionary of {} entries is given to that of {} entries",
+      snapshot.dict_size,
+      self.dict_size
+    );
+    // ids are assigned from 0 until the dictionary is full
+    let mut ids = snapshot
+      .entries
+      .iter()
+      .map(|(id, _)| *id)
+      .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ensure!(
+      ids.len() <= self.dict_size && ids.iter().enumerate().all(|(i, id)| i == *id),
+      "Invalid ids in snapshot"
+    );
+    for (id, bytes) in snapshot.entries.iter() {
+      let base = T::from_bytes(bytes)?;
+      ensure!(
+        self.backend.get_id(&base)?.is_none(),
+        "Duplicated base in snapshot"
+      );
+      self.backend.insert(*id, &base)?;
+      self.backend.lru_mut().push_back(*id, base_hash(&base))?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<T> serde::Serialize for BasisDict<T>
+where
+  T: DictBase,
+{
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let snapshot = self.snapshot().map_err(serde::ser::Error::custom)?;
+    serde::Serialize::serialize(&snapshot, serializer)
+  }
+}
+
+// Deserialized into a dictionary held in memory
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for BasisDict<T>
+where
+  T: DictBase + 'static,
+{
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let snapshot = <DictSnapshot as serde::Deserialize>::deserialize(deserializer)?;
+    let mut dict = BasisDict::new(snapshot.dict_size);
+    dict.restore(&snapshot).map_err(serde::de::Error::custom)?;
+    Ok(dict)
+  }
+}
+
+fn base_hash<T: Hash>(base: &T) -> u64 {
+  let mut hasher = Fnv64::default();
+  base.hash(&mut hasher);
+  hasher.finish()
+}
+
+impl<T> BasisDict<T>
+where
+  T: core::cmp::Eq + core::hash::Hash + core::clone::Clone + core::fmt::Debug,
+{
+  pub fn with_backend(dict_size: usize, backend: Box<dyn DictBackend<T>>) -> Self {
+    let id_bitlen = id_bitlen_of(dict_size);
     BasisDict {
       dict_size,
       id_bitlen,
-      dict_id_to_base: HashMap::new(),
-      dict_base_to_id: LinkedHashMap::new(),
+      backend,
+      journal: Vec::new(),
+      journal_marks: Vec::new(),
     }
   }
 
@@ -34,14 +202,94 @@ where
     self.id_bitlen
   }
 
+  pub fn dict_size(&self) -> usize {
+    self.dict_size
+  }
+
+  fn lru(&self) -> &dyn LruOrder {
+    self.backend.lru()
+  }
+
+  // ids from the least recently used one
+  fn lru_ids(&self) -> impl Iterator<Item = usize> + '_ {
+    let lru = self.lru();
+    core::iter::successors(lru.front(), move |id| lru.next(*id))
+  }
+
+  // digest of entries and their LRU order, which must be identical between dedup and dup sides
+  pub fn digest(&self) -> u64 {
+    let mut hasher = Fnv64::default();
+    self.dict_size.hash(&mut hasher);
+    let lru = self.lru();
+    self.lru_ids().for_each(|id| {
+      lru.hash(id).unwrap_or_default().hash(&mut hasher);
+      id.hash(&mut hasher);
+    });
+    hasher.finish()
+  }
+
+  // drop all entries, e.g., at a checkpoint, while keeping the size
+  pub fn clear(&mut self) {
+    self.backend.clear();
+    self.journal.clear();
+    self.journal_marks.clear();
+  }
+
+  // Start recording changes, e.g., to discard a frame that is not emitted. Journals can be nested.
+  pub fn begin_journal(&mut self) {
+    self.journal_marks.push(self.journal.len());
+  }
+
+  // Keep changes since the last begin_journal, which are still undone by the rollback of an outer journal
+  pub fn commit_journal(&mut self) {
+    self.journal_marks.pop();
+    if self.journal_marks.is_empty() {
+      self.journal.clear();
+    }
+  }
+
+  // Undo all changes since the last begin_journal in the reverse order
+  pub fn rollback_journal(&mut self) -> Result<()> {
+    let Some(mark) = self.journal_marks.pop() else {
+      return Ok(());
+    };
+    let ops = self.journal.split_off(mark);
+    for op in ops.into_iter().rev() {
+      match op {
+        JournalOp::Touched { id, prev } => {
+          let lru = self.backend.lru_mut();
+          let hash = lru.remove(id).ok_or(anyhow!("Broken journal"))?;
+          lru.insert_after(prev, id, hash)?;
+        }
+        JournalOp::Put { id, evicted } => {
+          self.backend.lru_mut().remove(id);
+          self.backend.remove(id)?;
+          if let Some((base, hash)) = evicted {
+            self.backend.insert(id, &base)?;
+            self.backend.lru_mut().insert_after(None, id, hash)?;
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // move id to the back of the LRU order, recording its position if journaling
+  fn touch(&mut self, id: usize) {
+    if !self.journal_marks.is_empty() && self.lru().contains(id) {
+      let prev = self.lru().prev(id);
+      self.journal.push(JournalOp::Touched { id, prev });
+    }
+    self.backend.lru_mut().to_back(id);
+  }
+
   // call only in dedup
-  pub fn get_id(&mut self, base: &T) -> Option<IdRep> {
-    if let Some(id) = self.dict_base_to_id.get(base) {
-      let res = self.usize_id_to_bitvec_id(id);
-      self.dict_base_to_id.to_back(base); // update internal linked list
-      Some(res)
+  pub fn get_id(&mut self, base: &T) -> Result<Option<IdRep>> {
+    if let Some(id) = self.backend.get_id(base)? {
+      self.touch(id); // update internal linked list
+      Ok(Some(self.usize_id_to_bitvec_id(&id)))
     } else {
-      None
+      Ok(None)
     }
   }
 
@@ -49,13 +297,20 @@ where
   // call in dup when base is given
   pub fn put_base(&mut self, base: &T) -> Result<IdRep> {
     // LRU
-    let new_id = if self.dict_base_to_id.len() < self.dict_size {
-      self.dict_base_to_id.len()
+    let (new_id, evicted) = if self.lru().len() < self.dict_size {
+      (self.lru().len(), None)
     } else {
-      self.remove_lru_entry_get_freed_id()?
+      let (id, base, hash) = self.remove_lru_entry()?;
+      (id, Some((base, hash)))
     };
-    self.dict_base_to_id.insert(base.to_owned(), new_id);
-    self.dict_id_to_base.insert(new_id, base.to_owned());
+    if !self.journal_marks.is_empty() {
+      self.journal.push(JournalOp::Put {
+        id: new_id,
+        evicted,
+      });
+    }
+    self.backend.insert(new_id, base)?;
+    self.backend.lru_mut().push_back(new_id, base_hash(base))?;
     // println!("> newid = {}", new_id);
     let res = self.usize_id_to_bitvec_id(&new_id);
 
@@ -64,77 +319,57 @@ where
 
   // call only in dup when id is given
   pub fn get_base(&mut self, bit_id: &IdSRep) -> Result<T> {
-    // https://github.com/bitvecto-rs/bitvec/issues/119
-    let mut id = 0usize;
-    for (mut dst, src) in id.view_bits_mut::<Lsb0>()[..bit_id.len()]
-      .iter_mut()
-      .zip(bit_id.iter().rev())
-    {
-      dst.set(*src);
-    }
-
+    let id = bitvec_id_to_usize_id(bit_id);
     let base = self
-      .dict_id_to_base
-      .get(&id)
-      .ok_or(())
-      .map_err(|_| anyhow!("Invalid dictionary"))?;
-    self.dict_base_to_id.to_back(base); // update internal linked list
+      .backend
+      .get_base(id)?
+      .ok_or(anyhow!("Invalid dictionary"))?;
+    self.touch(id); // update internal linked list
 
-    Ok(base.to_owned())
+    Ok(base)
   }
 
   fn usize_id_to_bitvec_id(&self, id: &usize) -> IdRep {
-    let bs_id: BitVec<usize, Msb0> = BitVec::from_element(id.to_owned());
-    let mut res = BVRep::new();
-    res.extend_from_bitslice(&bs_id[bs_id.len() - self.id_bitlen..]);
-    res
+    usize_id_to_bitvec_id(*id, self.id_bitlen)
   }
 
-  fn remove_lru_entry_get_freed_id(&mut self) -> Result<usize> {
-    // 1. pop LRU entry from linked hash map (base-to-id) and get dropped id
-    let (k, v) = self
-      .dict_base_to_id
+  fn remove_lru_entry(&mut self) -> Result<(usize, T, u64)> {
+    // 1. pop LRU entry from the order and get dropped id
+    let (id, hash) = self
+      .backend
+      .lru_mut()
       .pop_front()
-      .ok_or(())
-      .map_err(|_| anyhow!("Invalid dictionary"))?;
-
-    // 2. drop entry from hash map (id-to-base)
-    let (vr, kr) = self
-      .dict_id_to_base
-      .remove_entry(&v)
-      .ok_or(())
-      .map_err(|_| anyhow!("Invalid dictionary"))?;
+      .ok_or(anyhow!("Invalid dictionary"))?;
 
-    // 3. return the id.
-    ensure!(kr == k && vr == v, "Failed to remove...Broken dictionary");
+    // 2. drop entry from backend
+    let base = self
+      .backend
+      .remove(id)?
+      .ok_or(anyhow!("Failed to remove...Broken dictionary"))?;
 
-    Ok(v)
+    // 3. return the id with the dropped entry.
+    Ok((id, base, hash))
   }
 
   #[allow(dead_code)]
   pub fn check_inner_integrity(&self) -> Result<()> {
-    // check consistency between id_to_base and base_to_id
+    // check consistency between the LRU order and the backend
     ensure!(
-      self.dict_base_to_id.len() == self.dict_id_to_base.len(),
+      self.lru().len() == self.backend.len() && self.lru_ids().count() == self.backend.len(),
       "Different size of dictionary"
     );
 
-    let res: Vec<Result<()>> = self
-      .dict_base_to_id
-      .iter()
-      .map(|(base, id)| {
-        if let Some(base_r) = self.dict_id_to_base.get(id) {
-          if *base_r == *base {
-            Ok(())
-          } else {
-            Err(anyhow!("a base is inconsistent for id {}", id))
-          }
-        } else {
-          Err(anyhow!("a base is missing in dict_id_to_base"))
-        }
-      })
-      .collect();
-    ensure!(res.iter().all(|x| (*x).is_ok()), "Inconsistent dictionary");
+    for id in self.lru_ids() {
+      let base = self
+        .backend
+        .get_base(id)?
+        .ok_or(anyhow!("a base is missing for id {}", id))?;
+      ensure!(
+        self.backend.get_id(&base)? == Some(id),
+        "a base is inconsistent for id {}",
+        id
+      );
+    }
 
     Ok(())
   }
@@ -143,6 +378,7 @@ where
 #[cfg(test)]
 mod tests {
   use super::*;
+  use alloc::{borrow::ToOwned, string::ToString, vec};
   const WORD_STR: &str =
     "寿限無(じゅげむ)寿限無(じゅげむ)五劫(ごこう)のすりきれ海砂利(かいじゃり)水魚(すいぎょ)の水行末(すいぎょうまつ) ";
   const MSG_BYTELEN: usize = 3;
@@ -162,7 +398,7 @@ mod tests {
       }
 
       let buf = &word_bytes[byte_ptr..byte_ptr + MSG_BYTELEN];
-      if let Some(bit_id) = basis_dict_1.get_id(&buf.to_vec()) {
+      if let Some(bit_id) = basis_dict_1.get_id(&buf.to_vec()).unwrap() {
         let base_r = basis_dict_2.get_base(&bit_id).unwrap();
         assert_eq!(base_r, buf.to_vec());
       } else {
@@ -180,4 +416,49 @@ mod tests {
       byte_ptr += CODE_BYTELEN;
     }
   }
+
+  #[test]
+  fn dict_journal_works() {
+    let bases = (0..2 * DICT_SIZE as u8)
+      .map(|i| vec![i; MSG_BYTELEN])
+      .collect::<Vec<_>>();
+    let mut basis_dict = BasisDict::<U8VRep>::new(DICT_SIZE);
+    for base in bases[..DICT_SIZE - 2].iter() {
+      basis_dict.put_base(base).unwrap();
+    }
+    let digest = basis_dict.digest();
+
+    // touch, fill, and evict entries, and then undo them
+    basis_dict.begin_journal();
+    assert!(basis_dict.get_id(&bases[3]).unwrap().is_some());
+    assert!(basis_dict.get_id(&bases[0]).unwrap().is_some());
+    for base in bases[DICT_SIZE - 2..].iter() {
+      basis_dict.put_base(base).unwrap();
+    }
+    assert!(basis_dict.get_id(&bases[DICT_SIZE]).unwrap().is_some());
+    assert_ne!(basis_dict.digest(), digest);
+    basis_dict.rollback_journal().unwrap();
+    assert_eq!(basis_dict.digest(), digest);
+    assert!(basis_dict.check_inner_integrity().is_ok());
+
+    // changes committed in a nested journal are undone by the outer one
+    basis_dict.begin_journal();
+    basis_dict.put_base(&bases[DICT_SIZE]).unwrap();
+    basis_dict.begin_journal();
+    basis_dict.put_base(&bases[DICT_SIZE + 1]).unwrap();
+    basis_dict.commit_journal();
+    basis_dict.begin_journal();
+    basis_dict.put_base(&bases[DICT_SIZE + 2]).unwrap();
+    basis_dict.rollback_journal().unwrap();
+    assert!(basis_dict.get_id(&bases[DICT_SIZE + 2]).unwrap().is_none());
+    assert!(basis_dict.get_id(&bases[DICT_SIZE + 1]).unwrap().is_some());
+    basis_dict.rollback_journal().unwrap();
+    assert_eq!(basis_dict.digest(), digest);
+
+    basis_dict.begin_journal();
+    basis_dict.put_base(&bases[DICT_SIZE]).unwrap();
+    basis_dict.commit_journal();
+    basis_dict.rollback_journal().unwrap();
+    assert!(basis_dict.get_id(&bases[DICT_SIZE]).unwrap().is_some());
+  }
 }
@@ -0,0 +1,1029 @@
+use crate::{
+  error::*,
+  lru::{LruList, LruNode, LruOrder},
+  util::{get_varint, put_varint, put_varint_array, Fnv64, MAX_VARINT_BYTELEN},
+};
+use alloc::{borrow::ToOwned, boxed::Box, vec, vec::Vec};
+use bitvec::domain::Domain;
+use core::{
+  hash::{Hash, Hasher},
+  marker::PhantomData,
+};
+use libecc::types::*;
+#[cfg(feature = "std")]
+use crate::lru::LruNodes;
+#[cfg(feature = "std")]
+use memmap2::MmapMut;
+#[cfg(feature = "std")]
+use std::{
+  collections::HashMap,
+  fs::{File, OpenOptions},
+  path::{Path, PathBuf},
+};
+
+// Storage of a dictionary given to GD::setup_with_storage
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DictStorage {
+  Memory,
+  // directory in which files of the dictionary are created
+  File(PathBuf),
+}
+
+#[cfg(feature = "std")]
+impl DictStorage {
+  pub(crate) fn backend<T>(&self, dict_size: usize) -> Result<Box<dyn DictBackend<T>>>
+  where
+    T: DictBase + 'static,
+  {
+    match self {
+      DictStorage::Memory => Ok(Box::new(CompactDictBackend::<T>::new(dict_size))),
+      DictStorage::File(dir) => Ok(Box::new(FileDictBackend::<T>::create(dir, dict_size)?)),
+    }
+  }
+}
+
+// Bases that can be stored in a dictionary backend
+pub trait DictBase: Eq + Hash + Clone + core::fmt::Debug + Send + Sync {
+  fn to_bytes(&self) -> U8VRep;
+  fn from_bytes(bytes: &U8SRep) -> Result<Self>;
+  // Give to_bytes to f in consecutive parts, so that lookups hash and compare bases as bytes without allocation
+  fn visit_bytes<F: FnMut(&U8SRep)>(&self, mut f: F) {
+    f(&self.to_bytes())
+  }
+}
+
+impl DictBase for U8VRep {
+  fn to_bytes(&self) -> U8VRep {
+    self.to_owned()
+  }
+  fn from_bytes(bytes: &U8SRep) -> Result<Self> {
+    Ok(bytes.to_vec())
+  }
+  fn visit_bytes<F: FnMut(&U8SRep)>(&self, mut f: F) {
+    f(self)
+  }
+}
+
+// [bit length (varint)][bits padded to bytes with zeros]
+impl DictBase for BVRep {
+  fn to_bytes(&self) -> U8VRep {
+    let mut res = U8VRep::new();
+    put_varint(&mut res, self.len());
+    let mut aligned = self.to_owned();
+    aligned.force_align();
+    aligned.set_uninitialized(false);
+    res.extend_from_slice(aligned.as_raw_slice());
+    res
+  }
+  fn visit_bytes<F: FnMut(&U8SRep)>(&self, mut f: F) {
+    match self.domain() {
+      // bits beginning at a byte boundary, which is always the case for bases of GD
+      Domain::Region {
+        head: None,
+        body,
+        tail,
+      } => {
+        let mut varint = [0u8; MAX_VARINT_BYTELEN];
+        let len = put_varint_array(&mut varint, self.len());
+        f(&varint[..len]);
+        f(body);
+        if let Some(tail) = tail {
+          f(&[tail.load_value()]);
+        }
+      }
+      _ => f(&self.to_bytes()),
+    }
+  }
+  fn from_bytes(bytes: &U8SRep) -> Result<Self> {
+    let (bitlen, len) = get_varint(bytes)?;
+    ensure!(
+      (bytes.len() - len) * u8::BITS as usize >= bitlen,
+      "Truncated base"
+    );
+    let mut res = BVRep::from_slice(&bytes[len..]);
+    res.truncate(bitlen);
+    Ok(res)
+  }
+}
+
+// Mapping between ids and bases of a dictionary with the LRU order of ids, where BasisDict updates the order and
+// assigns ids. The order is kept by the backend, so that a file-backed one holds it on files as well as bases.
+pub trait DictBackend<T>: core::fmt::Debug + Send + Sync {
+  // number of stored bases
+  fn len(&self) -> usize;
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+  fn get_id(&self, base: &T) -> Result<Option<usize>>;
+  fn get_base(&self, id: usize) -> Result<Option<T>>;
+  // call only for an unused id and a base not in the dictionary
+  fn insert(&mut self, id: usize, base: &T) -> Result<()>;
+  fn remove(&mut self, id: usize) -> Result<Option<T>>;
+  // drop all bases and the LRU order
+  fn clear(&mut self);
+  fn lru(&self) -> &dyn LruOrder;
+  fn lru_mut(&mut self) -> &mut dyn LruOrder;
+  // copy of the backend with the same ids and bases, which gives BasisDict::try_clone. This may fail, e.g., for files.
+  fn try_clone_box(&self) -> Result<Box<dyn DictBackend<T>>>;
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct MemoryDictBackend<T>
+where
+  T: DictBase,
+{
+  id_to_base: HashMap<usize, T>,
+  base_to_id: HashMap<T, usize>,
+  lru: LruList<Vec<LruNode>>,
+}
+
+#[cfg(feature = "std")]
+impl<T> Default for MemoryDictBackend<T>
+where
+  T: DictBase,
+{
+  fn default() -> Self {
+    MemoryDictBackend {
+      id_to_base: HashMap::new(),
+      base_to_id: HashMap::new(),
+      lru: LruList::default(),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl<T> DictBackend<T> for MemoryDictBackend<T>
+where
+  T: DictBase + 'static,
+{
+  fn len(&self) -> usize {
+    self.id_to_base.len()
+  }
+  fn get_id(&self, base: &T) -> Result<Option<usize>> {
+    Ok(self.base_to_id.get(base).copied())
+  }
+  fn get_base(&self, id: usize) -> Result<Option<T>> {
+    Ok(self.id_to_base.get(&id).cloned())
+  }
+  fn insert(&mut self, id: usize, base: &T) -> Result<()> {
+    ensure!(!self.id_to_base.contains_key(&id), "Id {} is in use", id);
+    self.id_to_base.insert(id, base.to_owned());
+    self.base_to_id.insert(base.to_owned(), id);
+    Ok(())
+  }
+  fn remove(&mut self, id: usize) -> Result<Option<T>> {
+    let base = self.id_to_base.remove(&id);
+    if let Some(b) = base.as_ref() {
+      self.base_to_id.remove(b);
+    }
+    Ok(base)
+  }
+  fn clear(&mut self) {
+    self.id_to_base.clear();
+    self.base_to_id.clear();
+    self.lru.clear();
+  }
+  fn lru(&self) -> &dyn LruOrder {
+    &self.lru
+  }
+  fn lru_mut(&mut self) -> &mut dyn LruOrder {
+    &mut self.lru
+  }
+  fn try_clone_box(&self) -> Result<Box<dyn DictBackend<T>>> {
+    Ok(Box::new(self.clone()))
+  }
+}
+
+// hash of the serialized base, which is much faster than Hash of bit vectors
+fn hash_of(bytes: &U8SRep) -> u64 {
+  let mut hasher = Fnv64::default();
+  hasher.write(bytes);
+  hasher.finish()
+}
+
+// hash_of(&base.to_bytes()) without allocation, where Fnv64 gives the same hash for bytes written in parts
+fn hash_of_base<T: DictBase>(base: &T) -> u64 {
+  let mut hasher = Fnv64::default();
+  base.visit_bytes(|part| hasher.write(part));
+  hasher.finish()
+}
+
+// base.to_bytes() == bytes without allocation
+fn base_eq_bytes<T: DictBase>(base: &T, bytes: &U8SRep) -> bool {
+  let mut rest = Some(bytes);
+  base.visit_bytes(|part| {
+    rest = rest.and_then(|r| r.strip_prefix(part));
+  });
+  rest.is_some_and(|r| r.is_empty())
+}
+
+const EMPTY_ENTRY: usize = 0;
+const REMOVED_ENTRY: usize = usize::MAX;
+const MIN_INDEX_LEN: usize = 8;
+
+// Compact in-memory dictionary, where every base is stored only once in a contiguous arena of fixed-length slots
+// indexed by id, and a base is looked up via an open-addressing index of (fingerprint of base, id + 1) with linear
+// probing, where id + 1 = 0 is an empty entry and usize::MAX is a removed one. All bases must be of the same length
+// when serialized, which holds for bases of a GD instance.
+// The arena, the index and the LRU order grow with the number of bases, where the index is doubled to keep its load
+// factor at most 3/4. So, nothing but a few entries of the index is allocated until bases are inserted, e.g., on no_std
+// targets.
+#[derive(Debug, Clone)]
+pub struct CompactDictBackend<T>
+where
+  T: DictBase,
+{
+  dict_size: usize,
+  base_bytelen: Option<usize>,
+  arena: U8VRep,
+  used: Vec<bool>,
+  index: Vec<(u64, usize)>,
+  len: usize,
+  removed: usize,
+  lru: LruList<Vec<LruNode>>,
+  _base: PhantomData<fn() -> T>,
+}
+
+impl<T> CompactDictBackend<T>
+where
+  T: DictBase,
+{
+  pub fn new(dict_size: usize) -> Self {
+    CompactDictBackend {
+      dict_size,
+      base_bytelen: None,
+      arena: U8VRep::new(),
+      used: Vec::new(),
+      index: vec![(0, EMPTY_ENTRY); MIN_INDEX_LEN],
+      len: 0,
+      removed: 0,
+      lru: LruList::default(),
+      _base: PhantomData,
+    }
+  }
+
+  fn is_used(&self, id: usize) -> bool {
+    self.used.get(id).copied().unwrap_or(false)
+  }
+
+  fn base_bytes(&self, id: usize) -> &U8SRep {
+    let bytelen = self.base_bytelen.unwrap_or(0);
+    &self.arena[id * bytelen..(id + 1) * bytelen]
+  }
+
+  // index of the entry having the id, or an id of the base
+  fn find_entry<F>(&self, fingerprint: u64, matches: F) -> Option<(usize, usize)>
+  where
+    F: Fn(usize) -> bool,
+  {
+    let mask = self.index.len() - 1;
+    for probe in 0..self.index.len() {
+      let idx = (fingerprint as usize).wrapping_add(probe) & mask;
+      match self.index[idx] {
+        (_, EMPTY_ENTRY) => return None,
+        (_, REMOVED_ENTRY) => continue,
+        (fp, entry) if fp == fingerprint && matches(entry - 1) => return Some((idx, entry - 1)),
+        _ => continue,
+      }
+    }
+    None
+  }
+
+  fn put_entry(&mut self, fingerprint: u64, id: usize) -> Result<()> {
+    let mask = self.index.len() - 1;
+    for probe in 0..self.index.len() {
+      let idx = (fingerprint as usize).wrapping_add(probe) & mask;
+      match self.index[idx].1 {
+        EMPTY_ENTRY => {
+          self.index[idx] = (fingerprint, id + 1);
+          return Ok(());
+        }
+        REMOVED_ENTRY => {
+          self.index[idx] = (fingerprint, id + 1);
+          self.removed -= 1;
+          return Ok(());
+        }
+        _ => continue,
+      }
+    }
+    bail!("Full hash index")
+  }
+
+  // Make room for an entry, where the index is rebuilt in the same length if removed entries can be dropped instead
+  fn reserve_entry(&mut self) -> Result<()> {
+    if (self.len + self.removed + 1) * 4 <= self.index.len() * 3 {
+      return Ok(());
+    }
+    let index_len = if (self.len + 1) * 2 <= self.index.len() {
+      self.index.len()
+    } else {
+      self
+        .index
+        .len()
+        .checked_mul(2)
+        .ok_or(anyhow!("Too large hash index"))?
+    };
+    self.index = vec![(0, EMPTY_ENTRY); index_len];
+    self.rebuild_index()
+  }
+
+  fn rebuild_index(&mut self) -> Result<()> {
+    self.index.fill((0, EMPTY_ENTRY));
+    self.removed = 0;
+    for id in 0..self.used.len() {
+      if self.used[id] {
+        self.put_entry(hash_of(self.base_bytes(id)), id)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<T> DictBackend<T> for CompactDictBackend<T>
+where
+  T: DictBase + 'static,
+{
+  fn len(&self) -> usize {
+    self.len
+  }
+
+  fn get_id(&self, base: &T) -> Result<Option<usize>> {
+    Ok(
+      self
+        .find_entry(hash_of_base(base), |id| {
+          base_eq_bytes(base, self.base_bytes(id))
+        })
+        .map(|(_, id)| id),
+    )
+  }
+
+  fn get_base(&self, id: usize) -> Result<Option<T>> {
+    if !self.is_used(id) {
+      return Ok(None);
+    }
+    T::from_bytes(self.base_bytes(id)).map(Some)
+  }
+
+  fn insert(&mut self, id: usize, base: &T) -> Result<()> {
+    ensure!(id < self.dict_size, "Invalid id {}", id);
+    ensure!(!self.is_used(id), "Id {} is in use", id);
+    let bytes = base.to_bytes();
+    let bytelen = *self.base_bytelen.get_or_insert(bytes.len());
+    ensure!(bytes.len() == bytelen, "Bases must be of the same length");
+
+    self.reserve_entry()?;
+    if self.used.len() <= id {
+      let arena_bytelen = (id + 1)
+        .checked_mul(bytelen)
+        .ok_or(anyhow!("Too large arena"))?;
+      self.used.resize(id + 1, false);
+      self.arena.resize(arena_bytelen, 0);
+    }
+    self.arena[id * bytelen..(id + 1) * bytelen].copy_from_slice(&bytes);
+    self.used[id] = true;
+    self.put_entry(hash_of(&bytes), id)?;
+    self.len += 1;
+    Ok(())
+  }
+
+  fn remove(&mut self, id: usize) -> Result<Option<T>> {
+    if !self.is_used(id) {
+      return Ok(None);
+    }
+    let base = T::from_bytes(self.base_bytes(id))?;
+    let (idx, _) = self
+      .find_entry(hash_of(self.base_bytes(id)), |entry_id| entry_id == id)
+      .ok_or(anyhow!("Broken hash index"))?;
+    self.index[idx] = (0, REMOVED_ENTRY);
+    self.removed += 1;
+    self.used[id] = false;
+    self.len -= 1;
+    Ok(Some(base))
+  }
+
+  fn clear(&mut self) {
+    self.base_bytelen = None;
+    self.arena.clear();
+    self.used.clear();
+    self.index = vec![(0, EMPTY_ENTRY); MIN_INDEX_LEN];
+    self.len = 0;
+    self.removed = 0;
+    self.lru.clear();
+  }
+
+  fn lru(&self) -> &dyn LruOrder {
+    &self.lru
+  }
+
+  fn lru_mut(&mut self) -> &mut dyn LruOrder {
+    &mut self.lru
+  }
+
+  fn try_clone_box(&self) -> Result<Box<dyn DictBackend<T>>> {
+    Ok(Box::new(self.clone()))
+  }
+}
+
+#[cfg(feature = "std")]
+const LOG_FILE: &str = "bases.log";
+#[cfg(feature = "std")]
+const ID_INDEX_FILE: &str = "ids.idx";
+#[cfg(feature = "std")]
+const HASH_INDEX_FILE: &str = "hash.idx";
+#[cfg(feature = "std")]
+const LRU_FILE: &str = "lru.idx";
+#[cfg(feature = "std")]
+const INITIAL_LOG_BYTELEN: usize = 1 << 20;
+#[cfg(feature = "std")]
+const EMPTY_SLOT: u64 = 0;
+#[cfg(feature = "std")]
+const TOMBSTONE: u64 = u64::MAX;
+
+// File-backed dictionary for dictionaries larger than RAM, consisting of three memory-mapped files in a directory:
+// - bases.log: append-only log of bases, each as [byte length of base (varint)][id (varint)][base]
+// - ids.idx: offset + 1 in the log of the base for each id (u64 LE), where 0 means unused
+// - hash.idx: open-addressing hash index of [hash of base (u64 LE)][id + 1 (u64 LE)] with linear probing,
+//   where id + 1 = 0 is an empty slot and u64::MAX is a removed one
+// - lru.idx: node of the LRU order for each id as [prev id + 1 (u64 LE)][next id + 1 (u64 LE)][hash of base (u64 LE)]
+// Only a few words are kept in memory whatever the number of ids is.
+// Bases dropped from the dictionary remain in the log until the log is compacted in place, which happens when it
+// becomes twice as large as live bases. A record is live if the id in it refers to its offset, and hence compaction,
+// rebuild of the hash index and copy of the dictionary walk the log once without any buffer. The dictionary is not persisted, i.e., creation fails if any of the files
+// exists, e.g., of another dictionary in the same directory, and the files are removed on drop together with the
+// directory if it was created by the backend.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FileDictBackend<T>
+where
+  T: DictBase,
+{
+  dir: PathBuf,
+  dict_size: usize,
+  log_file: File,
+  log: MmapMut,
+  log_bytelen: usize,
+  live_bytelen: usize,
+  ids: MmapMut,
+  slots: MmapMut,
+  slot_num: usize,
+  len: usize,
+  tombstones: usize,
+  lru: LruList<FileLruNodes>,
+  _base: PhantomData<fn() -> T>,
+  // dropped after the maps and the file above
+  _files: DictFiles,
+}
+
+// Files and the directory created by a FileDictBackend, which are removed on drop
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct DictFiles {
+  dir: Option<PathBuf>,
+  files: Vec<PathBuf>,
+}
+
+#[cfg(feature = "std")]
+impl DictFiles {
+  // Directory created if it does not exist
+  fn in_dir(dir: &Path) -> Result<Self> {
+    let created = !dir.exists();
+    std::fs::create_dir_all(dir)?;
+    Ok(DictFiles {
+      dir: created.then(|| dir.to_path_buf()),
+      files: Vec::new(),
+    })
+  }
+
+  // New file of the given length, which must not exist
+  fn create(&mut self, path: PathBuf, bytelen: usize) -> Result<(File, MmapMut)> {
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create_new(true)
+      .open(&path)
+      .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+    self.files.push(path);
+    file.set_len(bytelen as u64)?;
+    // SAFETY: the file has just been created by this backend in the directory given to the dictionary and is removed
+    // only on drop, and the File is kept open by the backend until the map is dropped. The backend resizes it only
+    // after flushing and before remapping, and other processes must not truncate or modify files in the directory.
+    let map = unsafe { MmapMut::map_mut(&file)? };
+    Ok((file, map))
+  }
+}
+
+#[cfg(feature = "std")]
+impl Drop for DictFiles {
+  fn drop(&mut self) {
+    for path in self.files.iter() {
+      let _ = std::fs::remove_file(path);
+    }
+    if let Some(dir) = &self.dir {
+      let _ = std::fs::remove_dir(dir);
+    }
+  }
+}
+
+// Nodes of the LRU order in lru.idx
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct FileLruNodes(MmapMut);
+
+#[cfg(feature = "std")]
+impl LruNodes for FileLruNodes {
+  fn node(&self, id: usize) -> LruNode {
+    if id >= self.0.len() / 24 {
+      return LruNode::default();
+    }
+    LruNode {
+      prev: read_u64(&self.0, id * 3),
+      next: read_u64(&self.0, id * 3 + 1),
+      hash: read_u64(&self.0, id * 3 + 2),
+    }
+  }
+  fn set_node(&mut self, id: usize, node: LruNode) {
+    write_u64(&mut self.0, id * 3, node.prev);
+    write_u64(&mut self.0, id * 3 + 1, node.next);
+    write_u64(&mut self.0, id * 3 + 2, node.hash);
+  }
+  fn reset(&mut self) {
+    self.0.fill(0);
+  }
+}
+
+#[cfg(feature = "std")]
+fn read_u64(map: &[u8], idx: usize) -> u64 {
+  u64::from_le_bytes(map[idx * 8..idx * 8 + 8].try_into().unwrap())
+}
+
+#[cfg(feature = "std")]
+fn write_u64(map: &mut [u8], idx: usize, value: u64) {
+  map[idx * 8..idx * 8 + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(feature = "std")]
+impl<T> FileDictBackend<T>
+where
+  T: DictBase,
+{
+  pub fn create<P: AsRef<Path>>(dir: P, dict_size: usize) -> Result<Self> {
+    let files = DictFiles::in_dir(dir.as_ref())?;
+    Self::create_in(dir.as_ref().to_path_buf(), dict_size, files)
+  }
+
+  fn create_in(dir: PathBuf, dict_size: usize, mut files: DictFiles) -> Result<Self> {
+    let slot_num = dict_size
+      .checked_mul(2)
+      .and_then(usize::checked_next_power_of_two)
+      .ok_or(anyhow!("Too large dictionary"))?
+      .max(2);
+    let ids_bytelen = dict_size.max(1).checked_mul(8);
+    let slots_bytelen = slot_num.checked_mul(16);
+    let lru_bytelen = dict_size.max(1).checked_mul(24);
+    let (Some(ids_bytelen), Some(slots_bytelen), Some(lru_bytelen)) = (ids_bytelen, slots_bytelen, lru_bytelen) else {
+      bail!("Too large dictionary");
+    };
+    let (log_file, log) = files.create(dir.join(LOG_FILE), INITIAL_LOG_BYTELEN)?;
+    let (_, ids) = files.create(dir.join(ID_INDEX_FILE), ids_bytelen)?;
+    let (_, slots) = files.create(dir.join(HASH_INDEX_FILE), slots_bytelen)?;
+    let (_, lru) = files.create(dir.join(LRU_FILE), lru_bytelen)?;
+    Ok(FileDictBackend {
+      dir,
+      dict_size,
+      log_file,
+      log,
+      log_bytelen: 0,
+      live_bytelen: 0,
+      ids,
+      slots,
+      slot_num,
+      len: 0,
+      tombstones: 0,
+      lru: LruList::new(FileLruNodes(lru)),
+      _base: PhantomData,
+      _files: files,
+    })
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  // Copy of the dictionary with the same ids, bases and LRU order in a new directory next to that of this dictionary, named by
+  // appending .1, .2, ... to it, which is removed when the copy is dropped. The copy is compacted, i.e., its log
+  // contains only live bases.
+  pub fn try_clone(&self) -> Result<Self> {
+    let mut n = 1usize;
+    let dir = loop {
+      let mut name = self.dir.as_os_str().to_owned();
+      name.push(format!(".{}", n));
+      let dir = PathBuf::from(name);
+      // create_dir fails for an existing directory, so that files of another dictionary are never truncated
+      match std::fs::create_dir(&dir) {
+        Ok(()) => break dir,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+        Err(e) => return Err(e.into()),
+      }
+    };
+    let files = DictFiles {
+      dir: Some(dir.to_owned()),
+      files: Vec::new(),
+    };
+    let mut res = Self::create_in(dir, self.dict_size, files)?;
+    let mut offset = 0usize;
+    while offset < self.log_bytelen {
+      let (id, start, end) = self.record(offset)?;
+      if self.offset_of(id) == Some(offset) {
+        let new_offset = res.append(&self.log[offset..end])?;
+        write_u64(&mut res.ids, id, new_offset as u64 + 1);
+        res.put_slot(hash_of(&self.log[start..end]), id)?;
+        res.len += 1;
+      }
+      offset = end;
+    }
+    let mut id = self.lru.front();
+    while let Some(i) = id {
+      let hash = self.lru.hash(i).ok_or(anyhow!("Broken LRU order"))?;
+      res.lru.push_back(i, hash)?;
+      id = self.lru.next(i);
+    }
+    Ok(res)
+  }
+
+  fn offset_of(&self, id: usize) -> Option<usize> {
+    match read_u64(&self.ids, id) {
+      0 => None,
+      v => Some((v - 1) as usize),
+    }
+  }
+
+  // Returns (id, offset of the base, end of the record)
+  fn record(&self, offset: usize) -> Result<(usize, usize, usize)> {
+    ensure!(offset < self.log_bytelen, "Broken base log");
+    let (bytelen, len) = get_varint(&self.log[offset..self.log_bytelen])?;
+    let (id, id_len) = get_varint(&self.log[offset + len..self.log_bytelen])?;
+    let start = offset + len + id_len;
+    ensure!(
+      id < self.dict_size && bytelen <= self.log_bytelen - start,
+      "Broken base log"
+    );
+    Ok((id, start, start + bytelen))
+  }
+
+  // Returns (offset of the base, end of the record)
+  fn record_range(&self, offset: usize) -> Result<(usize, usize)> {
+    let (_, start, end) = self.record(offset)?;
+    Ok((start, end))
+  }
+
+  fn base_at(&self, offset: usize) -> Result<T> {
+    let (start, end) = self.record_range(offset)?;
+    T::from_bytes(&self.log[start..end])
+  }
+
+  fn slot(&self, idx: usize) -> (u64, u64) {
+    (
+      read_u64(&self.slots, idx * 2),
+      read_u64(&self.slots, idx * 2 + 1),
+    )
+  }
+
+  fn set_slot(&mut self, idx: usize, hash: u64, entry: u64) {
+    write_u64(&mut self.slots, idx * 2, hash);
+    write_u64(&mut self.slots, idx * 2 + 1, entry);
+  }
+
+  // index of the slot having the id, or an id of the base
+  fn find_slot<F>(&self, hash: u64, mut matches: F) -> Result<Option<(usize, usize)>>
+  where
+    F: FnMut(usize) -> Result<bool>,
+  {
+    let mask = self.slot_num - 1;
+    for probe in 0..self.slot_num {
+      let idx = (hash as usize).wrapping_add(probe) & mask;
+      match self.slot(idx) {
+        (_, EMPTY_SLOT) => return Ok(None),
+        (_, TOMBSTONE) => continue,
+        (h, entry) if h == hash => {
+          let id = (entry - 1) as usize;
+          if matches(id)? {
+            return Ok(Some((idx, id)));
+          }
+        }
+        _ => continue,
+      }
+    }
+    Ok(None)
+  }
+
+  fn put_slot(&mut self, hash: u64, id: usize) -> Result<()> {
+    let mask = self.slot_num - 1;
+    for probe in 0..self.slot_num {
+      let idx = (hash as usize).wrapping_add(probe) & mask;
+      match self.slot(idx) {
+        (_, EMPTY_SLOT) => {
+          self.set_slot(idx, hash, id as u64 + 1);
+          return Ok(());
+        }
+        (_, TOMBSTONE) => {
+          self.set_slot(idx, hash, id as u64 + 1);
+          self.tombstones -= 1;
+          return Ok(());
+        }
+        _ => continue,
+      }
+    }
+    bail!("Full hash index")
+  }
+
+  // drop removed slots that lengthen probing
+  fn rebuild_hash_index(&mut self) -> Result<()> {
+    self.slots.fill(0);
+    self.tombstones = 0;
+    let mut offset = 0usize;
+    while offset < self.log_bytelen {
+      let (id, start, end) = self.record(offset)?;
+      if self.offset_of(id) == Some(offset) {
+        let hash = hash_of(&self.log[start..end]);
+        self.put_slot(hash, id)?;
+      }
+      offset = end;
+    }
+    Ok(())
+  }
+
+  // move live records to the head of the log while walking it, which never overwrites unvisited records
+  fn compact_log(&mut self) -> Result<()> {
+    let mut ptr = 0usize;
+    let mut offset = 0usize;
+    while offset < self.log_bytelen {
+      let (id, _, end) = self.record(offset)?;
+      if self.offset_of(id) == Some(offset) {
+        self.log.copy_within(offset..end, ptr);
+        write_u64(&mut self.ids, id, ptr as u64 + 1);
+        ptr += end - offset;
+      }
+      offset = end;
+    }
+    self.log_bytelen = ptr;
+    Ok(())
+  }
+
+  fn append(&mut self, record: &U8SRep) -> Result<usize> {
+    if self.log_bytelen > INITIAL_LOG_BYTELEN && self.log_bytelen > 2 * self.live_bytelen {
+      self.compact_log()?;
+    }
+    let required = self.log_bytelen + record.len();
+    if required > self.log.len() {
+      let new_bytelen = required.max(self.log.len() * 2);
+      self.log.flush()?;
+      self.log_file.set_len(new_bytelen as u64)?;
+      // SAFETY: the old map of the log is replaced here and no slice of it is alive across this call, and the log file
+      // is only grown, never truncated, by this backend. As in DictFiles::create, other processes must not modify it.
+      self.log = unsafe { MmapMut::map_mut(&self.log_file)? };
+    }
+    let offset = self.log_bytelen;
+    self.log[offset..required].copy_from_slice(record);
+    self.log_bytelen = required;
+    self.live_bytelen += record.len();
+    Ok(offset)
+  }
+}
+
+#[cfg(feature = "std")]
+impl<T> DictBackend<T> for FileDictBackend<T>
+where
+  T: DictBase + 'static,
+{
+  fn len(&self) -> usize {
+    self.len
+  }
+
+  fn get_id(&self, base: &T) -> Result<Option<usize>> {
+    let found = self.find_slot(hash_of_base(base), |id| match self.offset_of(id) {
+      Some(offset) => {
+        let (start, end) = self.record_range(offset)?;
+        Ok(base_eq_bytes(base, &self.log[start..end]))
+      }
+      None => Ok(false),
+    })?;
+    Ok(found.map(|(_, id)| id))
+  }
+
+  fn get_base(&self, id: usize) -> Result<Option<T>> {
+    if id >= self.dict_size {
+      return Ok(None);
+    }
+    self
+      .offset_of(id)
+      .map(|offset| self.base_at(offset))
+      .transpose()
+  }
+
+  fn insert(&mut self, id: usize, base: &T) -> Result<()> {
+    ensure!(id < self.dict_size, "Invalid id {}", id);
+    ensure!(self.offset_of(id).is_none(), "Id {} is in use", id);
+    let bytes = base.to_bytes();
+    let mut record = U8VRep::with_capacity(bytes.len() + 8);
+    put_varint(&mut record, bytes.len());
+    put_varint(&mut record, id);
+    record.extend_from_slice(&bytes);
+
+    let offset = self.append(&record)?;
+    write_u64(&mut self.ids, id, offset as u64 + 1);
+    self.put_slot(hash_of(&bytes), id)?;
+    self.len += 1;
+    Ok(())
+  }
+
+  fn remove(&mut self, id: usize) -> Result<Option<T>> {
+    if id >= self.dict_size {
+      return Ok(None);
+    }
+    let Some(offset) = self.offset_of(id) else {
+      return Ok(None);
+    };
+    let base = self.base_at(offset)?;
+    let (start, end) = self.record_range(offset)?;
+    let (idx, _) = self
+      .find_slot(hash_of(&self.log[start..end]), |slot_id| Ok(slot_id == id))?
+      .ok_or(anyhow!("Broken hash index"))?;
+    self.set_slot(idx, 0, TOMBSTONE);
+    self.tombstones += 1;
+    write_u64(&mut self.ids, id, 0);
+    self.live_bytelen -= end - offset;
+    self.len -= 1;
+
+    if (self.len + self.tombstones) * 4 > self.slot_num * 3 {
+      self.rebuild_hash_index()?;
+    }
+    Ok(Some(base))
+  }
+
+  fn clear(&mut self) {
+    self.ids.fill(0);
+    self.slots.fill(0);
+    self.log_bytelen = 0;
+    self.live_bytelen = 0;
+    self.len = 0;
+    self.tombstones = 0;
+    self.lru.clear();
+  }
+
+  fn lru(&self) -> &dyn LruOrder {
+    &self.lru
+  }
+
+  fn lru_mut(&mut self) -> &mut dyn LruOrder {
+    &mut self.lru
+  }
+
+  fn try_clone_box(&self) -> Result<Box<dyn DictBackend<T>>> {
+    Ok(Box::new(self.try_clone()?))
+  }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn file_dict_backend_works() {
+    let dir = std::env::temp_dir().join(format!("rust-gd-dict-{}", std::process::id()));
+    let dict_size = 64;
+    let mut file = FileDictBackend::<BVRep>::create(&dir, dict_size).unwrap();
+    // files of a live dictionary are never truncated by another one
+    assert!(FileDictBackend::<BVRep>::create(&dir, dict_size).is_err());
+    let mut memory = MemoryDictBackend::<BVRep>::default();
+    let base = |i: usize| {
+      let mut b = BVRep::from_slice(&i.to_le_bytes().repeat(8));
+      b.truncate(509);
+      b
+    };
+
+    // overwrite ids many times to cause removal, rebuild of the hash index and compaction of the log
+    for i in 0..20_000usize {
+      let id = i % dict_size;
+      if i >= dict_size {
+        assert_eq!(file.remove(id).unwrap(), memory.remove(id).unwrap());
+      }
+      file.insert(id, &base(i)).unwrap();
+      memory.insert(id, &base(i)).unwrap();
+      assert_eq!(file.get_id(&base(i)).unwrap(), Some(id));
+    }
+    assert_eq!(file.len(), memory.len());
+    for id in 0..dict_size {
+      let b = memory.get_base(id).unwrap().unwrap();
+      assert_eq!(file.get_base(id).unwrap(), Some(b.clone()));
+      assert_eq!(file.get_id(&b).unwrap(), Some(id));
+    }
+    assert!(file.get_id(&base(0)).unwrap().is_none());
+    assert!(file.insert(0, &base(0)).is_err());
+    // the log has been compacted while inserting, and is compacted into live bases only
+    assert!(file.log_bytelen < INITIAL_LOG_BYTELEN);
+    file.compact_log().unwrap();
+    assert_eq!(file.log_bytelen, file.live_bytelen);
+    for id in 0..dict_size {
+      let b = memory.get_base(id).unwrap().unwrap();
+      assert_eq!(file.get_base(id).unwrap(), Some(b.clone()));
+      assert_eq!(file.get_id(&b).unwrap(), Some(id));
+    }
+
+    let copy = file.try_clone().unwrap();
+    assert_eq!(copy.len(), file.len());
+    for id in 0..dict_size {
+      let b = file.get_base(id).unwrap().unwrap();
+      assert_eq!(copy.get_base(id).unwrap(), Some(b.clone()));
+      assert_eq!(copy.get_id(&b).unwrap(), Some(id));
+    }
+    assert_ne!(copy.dir(), file.dir());
+    let copy_dir = copy.dir().to_path_buf();
+    drop(copy);
+    assert!(!copy_dir.exists());
+
+    file.clear();
+    assert!(file.is_empty());
+    assert!(file.get_base(0).unwrap().is_none());
+
+    // probing from a hash near usize::MAX wraps around the hash index
+    file.put_slot(u64::MAX - 1, 0).unwrap();
+    file.put_slot(u64::MAX - 1, 1).unwrap();
+    let found = file.find_slot(u64::MAX - 1, |id| Ok(id == 1)).unwrap();
+    assert_eq!(found.map(|(_, id)| id), Some(1));
+    drop(file);
+    assert!(!dir.exists());
+  }
+
+  #[test]
+  fn visit_bytes_works() {
+    let bytes = (0..40u8)
+      .map(|i| i.wrapping_mul(37) | 0x81)
+      .collect::<U8VRep>();
+    for bitlen in [0, 1, 7, 8, 9, 120, 127, 300] {
+      // dead bits of the last byte are 1 here
+      let mut aligned = BVRep::from_slice(&bytes);
+      aligned.truncate(bitlen);
+      let unaligned = BVRep::from_bitslice(&BVRep::from_slice(&bytes)[3..3 + bitlen]);
+      for base in [aligned, unaligned] {
+        let mut visited = U8VRep::new();
+        base.visit_bytes(|part| visited.extend_from_slice(part));
+        assert_eq!(visited, base.to_bytes());
+        assert_eq!(BVRep::from_bytes(&visited).unwrap(), base);
+        assert_eq!(hash_of_base(&base), hash_of(&visited));
+        assert!(base_eq_bytes(&base, &visited));
+        assert!(!base_eq_bytes(&base, &visited[..visited.len() - 1]));
+        visited.push(0);
+        assert!(!base_eq_bytes(&base, &visited));
+      }
+    }
+  }
+
+  #[test]
+  fn compact_dict_backend_works() {
+    let dict_size = 64;
+    let mut compact = CompactDictBackend::<U8VRep>::new(dict_size);
+    let mut memory = MemoryDictBackend::<U8VRep>::default();
+    let base = |i: usize| i.to_le_bytes().to_vec();
+
+    for i in 0..10_000usize {
+      let id = (i * 7) % dict_size;
+      if i >= dict_size {
+        assert_eq!(compact.remove(id).unwrap(), memory.remove(id).unwrap());
+      }
+      compact.insert(id, &base(i)).unwrap();
+      memory.insert(id, &base(i)).unwrap();
+      assert_eq!(compact.get_id(&base(i)).unwrap(), Some(id));
+    }
+    assert_eq!(compact.len(), memory.len());
+    for id in 0..dict_size {
+      let b = memory.get_base(id).unwrap().unwrap();
+      assert_eq!(compact.get_base(id).unwrap(), Some(b.clone()));
+      assert_eq!(compact.get_id(&b).unwrap(), Some(id));
+    }
+    assert!(compact.get_id(&base(0)).unwrap().is_none());
+    assert!(compact.insert(dict_size, &base(0)).is_err());
+    compact.remove(0).unwrap();
+    assert!(compact.insert(0, &vec![0u8]).is_err());
+
+    compact.clear();
+    assert!(compact.is_empty());
+    assert!(compact.get_base(0).unwrap().is_none());
+
+    // the index grows with bases, and is not allocated up front even for a huge dictionary
+    let mut huge = CompactDictBackend::<U8VRep>::new(usize::MAX);
+    assert_eq!(huge.index.len(), MIN_INDEX_LEN);
+    for id in 0..1000 {
+      huge.insert(id, &base(id)).unwrap();
+    }
+    assert!(huge.index.len() <= 2048);
+    assert!((0..1000).all(|id| huge.get_id(&base(id)).unwrap() == Some(id)));
+
+    // probing from a fingerprint near usize::MAX wraps around the index
+    compact.put_entry(u64::MAX - 1, 0).unwrap();
+    compact.put_entry(u64::MAX - 1, 1).unwrap();
+    let found = compact.find_entry(u64::MAX - 1, |id| id == 1);
+    assert_eq!(found.map(|(_, id)| id), Some(1));
+  }
+}
@@ -1 +1 @@
-pub use anyhow::{anyhow, bail, ensure, Context, Error, Result};
+pub use anyhow::{anyhow, bail, ensure, Result};
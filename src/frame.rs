@@ -0,0 +1,546 @@
+use crate::{
+  adaptive::FrameStats,
+  cdc::{decode_segment_header, encode_segment_header, ContentDefinedChunker},
+  chunk::pad_bytelen,
+  dict::BasisDict,
+  dict_backend::DictBase,
+  error::*,
+  literal::LiteralPolicy,
+  packet::PacketDict,
+  resync::StreamSync,
+  separator::{Separator, TokenWriter, MAX_RUN_CHUNKS},
+  shared_dict::SharedDictHandle,
+  stored::STORED_FALLBACK_MAX_EXPANSION,
+  util::{get_gamma, MAX_VARINT_BYTELEN},
+};
+use bitvec::prelude::*;
+use libecc::types::*;
+use std::collections::HashSet;
+
+// Bases and deviations of chunks given in tokens, i.e., bytes for byte-unit codes and bits otherwise
+pub(crate) trait TokenBits: DictBase + 'static {
+  fn to_bits(&self) -> BVRep;
+  fn push_bits(&self, res: &mut BVRep);
+  fn from_bits(bs: &BSRep) -> Self;
+}
+
+impl TokenBits for U8VRep {
+  fn to_bits(&self) -> BVRep {
+    BVRep::from_slice(self)
+  }
+  fn push_bits(&self, res: &mut BVRep) {
+    res.extend_from_raw_slice(self);
+  }
+  fn from_bits(bs: &BSRep) -> Self {
+    let mut bv = bs.to_bitvec();
+    bv.force_align();
+    bv.into_vec()
+  }
+}
+
+impl TokenBits for BVRep {
+  fn to_bits(&self) -> BVRep {
+    self.to_owned()
+  }
+  fn push_bits(&self, res: &mut BVRep) {
+    res.extend_from_bitslice(self);
+  }
+  fn from_bits(bs: &BSRep) -> Self {
+    bs.to_bitvec()
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Coder of frames shared by GD units, which holds dictionaries and modes set via GDInner, and gives bases and
+// deviations of chunks as tokens of the separator. Units only split chunks into bases and deviations and back.
+#[derive(Debug)]
+pub(crate) struct FrameCoder<T>
+where
+  T: TokenBits,
+{
+  pub(crate) basis_dict: BasisDict<T>,
+  pub(crate) chunker: Option<ContentDefinedChunker>,
+  pub(crate) sync: Option<StreamSync>,
+  pub(crate) packet: Option<PacketDict<T>>,
+  pub(crate) shared: Option<SharedDictHandle>,
+  // dictionary of deviations for multi-level GD
+  pub(crate) deviation_dict: Option<BasisDict<T>>,
+  // multi-symbol separator instead of single bit
+  pub(crate) multi_symbol: bool,
+  pub(crate) literal: Option<LiteralPolicy>,
+  // stored frame instead of GD frame if GD does not reduce the size
+  pub(crate) stored_fallback: bool,
+  // statistics of the last frame deduplicated
+  pub(crate) last_stats: FrameStats,
+  // count distinct deviations in the statistics, which costs a hash set of deviations per frame
+  pub(crate) deviation_stats: bool,
+}
+
+// GD unit whose frames are given by its frame coder
+pub(crate) trait FrameUnit {
+  type Base: TokenBits;
+  fn coder_mut(&mut self) -> &mut FrameCoder<Self::Base>;
+}
+
+impl<T> FrameCoder<T>
+where
+  T: TokenBits,
+{
+  pub(crate) fn new(basis_dict: BasisDict<T>) -> Self {
+    FrameCoder {
+      basis_dict,
+      chunker: None,
+      sync: None,
+      packet: None,
+      shared: None,
+      deviation_dict: None,
+      multi_symbol: false,
+      literal: None,
+      stored_fallback: false,
+      last_stats: FrameStats::default(),
+      deviation_stats: false,
+    }
+  }
+
+  // Copy of the coder, which fails if its dictionaries cannot be copied
+  pub(crate) fn try_clone(&self) -> Result<Self> {
+    Ok(FrameCoder {
+      basis_dict: self.basis_dict.try_clone()?,
+      chunker: self.chunker.clone(),
+      sync: self.sync.clone(),
+      packet: self.packet.clone(),
+      shared: self.shared.clone(),
+      deviation_dict: self.deviation_dict.as_ref().map(BasisDict::try_clone).transpose()?,
+      multi_symbol: self.multi_symbol,
+      literal: self.literal.clone(),
+      stored_fallback: self.stored_fallback,
+      last_stats: self.last_stats.clone(),
+      deviation_stats: self.deviation_stats,
+    })
+  }
+
+  // Segment lengths, segment header and padding of the last chunk
+  pub(crate) fn segment(&self, buf: &U8SRep, chunk_bytelen: usize) -> (Vec<usize>, U8VRep, usize) {
+    match &self.chunker {
+      Some(chunker) => {
+        let segment_lens = chunker.segment_lens(buf);
+        let header = encode_segment_header(&segment_lens);
+        (segment_lens, header, 0)
+      }
+      None => (
+        vec![buf.len()],
+        U8VRep::new(),
+        pad_bytelen(buf.len(), chunk_bytelen),
+      ),
+    }
+  }
+
+  pub(crate) fn parse_segment_header(&self, data: &U8SRep) -> Result<(Option<Vec<usize>>, usize)> {
+    match &self.chunker {
+      Some(_) => {
+        let (segment_lens, header_bytelen) = decode_segment_header(data)?;
+        Ok((Some(segment_lens), header_bytelen))
+      }
+      None => Ok((None, 0)),
+    }
+  }
+
+  // Upper bound of the byte length of a frame of bytelen bytes, which are split into chunks of chunk_bitlen bits whose
+  // bases and deviations take info_bitlen and dev_bitlen bits. Every chunk is bounded by the longest token of it, i.e.,
+  // a separator and an id or a base, and a separator and an id or a deviation. Frames are not bounded in packet mode,
+  // where the header carries commits not yet applied by the dup side.
+  pub(crate) fn max_frame_bytelen(
+    &self,
+    bytelen: usize,
+    chunk_bitlen: usize,
+    info_bitlen: usize,
+    dev_bitlen: usize,
+  ) -> Result<usize> {
+    ensure!(
+      self.packet.is_none(),
+      "Frames are not bounded in packet mode"
+    );
+    // [flags][epoch][frame number][digest]
+    let control_bytelen = self.sync.as_ref().map_or(0, |_| 1 + 2 * MAX_VARINT_BYTELEN + 8);
+    let segments = self.chunker.as_ref().map_or(1, |c| c.max_segments(bytelen));
+    // segment lengths, or padding of the last chunk in bits in bitstream mode
+    let segment_header_bytelen = segments.saturating_add(1).saturating_mul(MAX_VARINT_BYTELEN);
+    // the last chunk of each segment may be padded
+    let chunks = bytelen
+      .saturating_mul(u8::BITS as usize)
+      .div_ceil(chunk_bitlen)
+      .saturating_add(segments);
+
+    let sep_bitlen = if self.multi_symbol { 4 } else { 1 };
+    let dev_sep_bitlen = self.deviation_dict.as_ref().map_or(0, |_| 1);
+    let assigned_id_bitlen = self.shared.as_ref().map_or(0, |_| self.id_bitlen());
+    let base_bitlen = self.id_bitlen().max(assigned_id_bitlen + info_bitlen);
+    let dev_bitlen = self
+      .deviation_dict
+      .as_ref()
+      .map_or(dev_bitlen, |d| d.id_bitlen().max(dev_bitlen));
+    let token_bitlen = sep_bitlen + dev_sep_bitlen + base_bitlen + dev_bitlen;
+    let tokens_bytelen = chunks.saturating_mul(token_bitlen).div_ceil(u8::BITS as usize);
+
+    Ok(
+      control_bytelen
+        .saturating_add(segment_header_bytelen)
+        .saturating_add(tokens_bytelen)
+        .saturating_add(STORED_FALLBACK_MAX_EXPANSION),
+    )
+  }
+
+  // dictionary operations, where bases are committed upon acks in packet mode,
+  // and ids are explicitly given with bases in AsIs when the dictionary is shared
+  fn id_bitlen(&self) -> usize {
+    match &self.shared {
+      Some(shared) => shared.id_bitlen(),
+      None => self.basis_dict.id_bitlen(),
+    }
+  }
+  fn get_id(&mut self, base: &T) -> Result<Option<IdRep>> {
+    match (&mut self.packet, &self.shared) {
+      (Some(packet), _) => Ok(packet.get_id(base)),
+      (None, Some(shared)) => Ok(shared.get_id(&base.to_bits())),
+      (None, None) => self.basis_dict.get_id(base),
+    }
+  }
+  // Returns the id to be given with the base in AsIs if the dictionary is shared
+  fn put_base(&mut self, base: &T) -> Result<Option<IdRep>> {
+    match (&mut self.packet, &mut self.shared) {
+      (Some(packet), _) => packet.put_base(base),
+      (None, Some(shared)) => return shared.put_base(&base.to_bits()).map(Some),
+      (None, None) => {
+        self.basis_dict.put_base(base)?;
+      }
+    };
+    Ok(None)
+  }
+  fn put_base_with_id(&mut self, base: &T, id: Option<&IdSRep>) -> Result<()> {
+    match (&mut self.shared, id) {
+      (Some(shared), Some(id)) => shared.put_base_with_id(&base.to_bits(), id),
+      _ => self.put_base(base).map(|_| ()),
+    }
+  }
+  fn get_base(&mut self, id: &IdSRep) -> Result<T> {
+    match (&mut self.packet, &self.shared) {
+      (Some(packet), _) => packet.get_base(id),
+      (None, Some(shared)) => shared.get_base(id).map(|b| T::from_bits(&b)),
+      (None, None) => self.basis_dict.get_base(id),
+    }
+  }
+
+  // Returns the separator of the deviation and the id or the deviation itself in multi-level GD
+  fn dedup_deviation(&mut self, dev: &T) -> Result<(Option<Separator>, BVRep)> {
+    let Some(dict) = &mut self.deviation_dict else {
+      return Ok((None, dev.to_bits()));
+    };
+    match dict.get_id(dev)? {
+      Some(id) => Ok((Some(Separator::Deduped), id)),
+      None => {
+        dict.put_base(dev)?;
+        Ok((Some(Separator::AsIs), dev.to_bits()))
+      }
+    }
+  }
+  // Returns the deviation and its bit length in deduplicated data
+  fn dup_deviation(
+    &mut self,
+    sep: Option<Separator>,
+    bs: &BSRep,
+    dev_bitlen: usize,
+  ) -> Result<(T, usize)> {
+    match (sep, &mut self.deviation_dict) {
+      (Some(Separator::Deduped), Some(dict)) => {
+        let id_bitlen = dict.id_bitlen();
+        ensure!(bs.len() >= id_bitlen, "Truncated deduplicated data");
+        Ok((dict.get_base(&bs[..id_bitlen])?, id_bitlen))
+      }
+      (_, dict) => {
+        ensure!(bs.len() >= dev_bitlen, "Truncated deduplicated data");
+        let dev = T::from_bits(&bs[..dev_bitlen]);
+        if let Some(dict) = dict {
+          dict.put_base(&dev)?;
+        }
+        Ok((dev, dev_bitlen))
+      }
+    }
+  }
+
+  // Record changes of dictionaries to discard a GD frame, e.g., replaced with a stored frame.
+  // Returns the state of the literal policy to be restored on rollback.
+  pub(crate) fn begin_journal(&mut self) -> Result<Option<LiteralPolicy>> {
+    ensure!(
+      self.sync.is_none() && self.packet.is_none() && self.shared.is_none(),
+      "Frames cannot be discarded with the dictionary mode"
+    );
+    self.basis_dict.begin_journal();
+    if let Some(dict) = &mut self.deviation_dict {
+      dict.begin_journal();
+    }
+    Ok(self.literal.clone())
+  }
+  pub(crate) fn end_journal(
+    &mut self,
+    rollback: bool,
+    literal: Option<LiteralPolicy>,
+  ) -> Result<()> {
+    if rollback {
+      self.rollback_dedup_frame()?;
+      self.basis_dict.rollback_journal()?;
+      if let Some(dict) = &mut self.deviation_dict {
+        dict.rollback_journal()?;
+      }
+      self.literal = literal;
+    } else {
+      self.basis_dict.commit_journal();
+      if let Some(dict) = &mut self.deviation_dict {
+        dict.commit_journal();
+      }
+    }
+    Ok(())
+  }
+
+  // Control header of dictionary synchronization or packet mode, which begins a frame in dedup
+  pub(crate) fn begin_dedup_frame(&mut self) -> U8VRep {
+    match (&mut self.sync, &mut self.packet) {
+      (Some(sync), _) => sync.encode_control(&mut self.basis_dict),
+      (None, Some(packet)) => packet.begin_dedup_frame(),
+      (None, None) => U8VRep::new(),
+    }
+  }
+  pub(crate) fn end_dedup_frame(&mut self) -> Result<()> {
+    if let Some(packet) = &mut self.packet {
+      packet.end_frame();
+    }
+    if let Some(shared) = &mut self.shared {
+      shared.end_frame()?;
+    }
+    Ok(())
+  }
+  // Undo a frame failed or discarded in dedup, which releases ids reserved in the shared dictionary
+  pub(crate) fn rollback_dedup_frame(&mut self) -> Result<()> {
+    if let Some(shared) = &mut self.shared {
+      shared.rollback_frame()?;
+    }
+    Ok(())
+  }
+  // Returns the byte length of the control header
+  pub(crate) fn begin_dup_frame(&mut self, data: &U8SRep) -> Result<usize> {
+    match (&mut self.sync, &mut self.packet) {
+      (Some(sync), _) => sync.decode_control(data, &mut self.basis_dict),
+      (None, Some(packet)) => packet.begin_dup_frame(data),
+      (None, None) => Ok(0),
+    }
+  }
+  pub(crate) fn end_dup_frame(&mut self) {
+    if let Some(packet) = &mut self.packet {
+      packet.end_frame();
+    }
+  }
+
+  // Tokens of (base, deviation) of chunks padded to bytes, which also updates dictionaries and the statistics
+  pub(crate) fn encode_tokens<I>(&mut self, chunks: I) -> Result<U8VRep>
+  where
+    I: IntoIterator<Item = Result<(T, T)>>,
+  {
+    let mut res = TokenWriter::new(self.multi_symbol);
+    // previous chunk and the number of its repetitions not yet written in multi-symbol separator
+    let mut prev: Option<(T, T)> = None;
+    let mut run = 0usize;
+    let mut run_chunks = 0usize;
+    let mut stats = FrameStats::default();
+    let mut deviations = HashSet::new();
+    for chunk in chunks {
+      let (base, deviation) = chunk?;
+      stats.chunks += 1;
+      if self.deviation_stats {
+        deviations.insert(deviation.clone());
+      }
+      if self.multi_symbol {
+        if run_chunks + run < MAX_RUN_CHUNKS && prev == Some((base.clone(), deviation.clone())) {
+          stats.base_hits += 1;
+          run += 1;
+          continue;
+        }
+        if run > 0 {
+          res.put_run(run);
+          run_chunks += run;
+          run = 0;
+        }
+      }
+      // write result and update dict
+      let same_base = self.multi_symbol && prev.as_ref().is_some_and(|(b, _)| *b == base);
+      let (sep, id_or_base) = if same_base {
+        (Separator::SameBase, BVRep::new())
+      } else {
+        match self.get_id(&base)? {
+          Some(bit_id) => {
+            if let Some(literal) = &mut self.literal {
+              literal.on_hit();
+            }
+            (Separator::Deduped, bit_id)
+          }
+          None if self.literal.as_mut().is_some_and(|l| l.on_miss()) => {
+            (Separator::RawLiteral, base.to_bits())
+          }
+          None => {
+            let mut id_and_base = self.put_base(&base)?.unwrap_or_default();
+            base.push_bits(&mut id_and_base);
+            (Separator::AsIs, id_and_base)
+          }
+        }
+      };
+      if matches!(sep, Separator::Deduped | Separator::SameBase) {
+        stats.base_hits += 1;
+      }
+      let (dev_sep, id_or_dev) = self.dedup_deviation(&deviation)?;
+      res.put_chunk(&sep, dev_sep.as_ref(), &id_or_base, &id_or_dev)?;
+      if self.multi_symbol {
+        prev = Some((base, deviation));
+      }
+    }
+    if run > 0 {
+      res.put_run(run);
+    }
+
+    stats.distinct_deviations = deviations.len();
+    self.last_stats = stats;
+    Ok(res.finish())
+  }
+
+  // (base, deviation) of chunks given by tokens, which also updates dictionaries
+  pub(crate) fn decode_tokens(
+    &mut self,
+    data: &U8SRep,
+    info_bitlen: usize,
+    dev_bitlen: usize,
+  ) -> Result<Vec<(T, T)>> {
+    let deduped_bs = BitSlice::<u8, Msb0>::from_slice(data);
+    let id_bitlen = self.id_bitlen();
+
+    let mut decoded_chunks: Vec<(T, T)> = Vec::new();
+    let mut bitptr = 0usize;
+    let max_bit_pads = 7usize;
+    let mut prev: Option<(T, T)> = None;
+    let mut run_chunks = 0usize;
+    // max bit pad = 7 bits, if actual bitlen = 9 (0..8), 7bits pad is given.
+    // then bitptr = 9 here and deduped_bs.len() = 15
+    // Pads are 0 while tokens shorter than 8 bits always contain 1, i.e., Deduped ones, e.g., of a small dictionary
+    // in multi-level GD, and short ones of the multi-symbol separator. Tokens beginning with 0 contain whole bases.
+    while bitptr + max_bit_pads < deduped_bs.len() || deduped_bs[bitptr..].any() {
+      let sep = if self.multi_symbol {
+        let (sep, len) = Separator::parse_multi_symbol(&deduped_bs[bitptr..])?;
+        bitptr += len;
+        sep
+      } else {
+        bitptr += 1;
+        Separator::from(deduped_bs[bitptr - 1])
+      };
+      // no deviation follows a run
+      let has_dev_sep = self.deviation_dict.is_some() && !matches!(sep, Separator::Run);
+      ensure!(
+        !has_dev_sep || bitptr < deduped_bs.len(),
+        "Truncated deduplicated data"
+      );
+      let dev_sep = has_dev_sep.then(|| {
+        bitptr += 1;
+        Separator::from(deduped_bs[bitptr - 1])
+      });
+
+      let base = match sep {
+        Separator::Run => {
+          let (run, len) = get_gamma(&deduped_bs[bitptr..])?;
+          bitptr += len;
+          run_chunks = run_chunks.saturating_add(run);
+          ensure!(
+            run_chunks <= MAX_RUN_CHUNKS,
+            "Too many repeated chunks in a frame"
+          );
+          let chunk = prev
+            .clone()
+            .ok_or(anyhow!("Run without a preceding chunk"))?;
+          decoded_chunks.extend(std::iter::repeat_n(chunk, run));
+          continue;
+        }
+        Separator::AsIs => {
+          let assigned_id_bitlen = self.shared.as_ref().map_or(0, |_| id_bitlen);
+          ensure!(
+            bitptr + assigned_id_bitlen + info_bitlen <= deduped_bs.len(),
+            "Truncated deduplicated data"
+          );
+          let assigned_id = self.shared.as_ref().map(|_| {
+            bitptr += id_bitlen;
+            deduped_bs[bitptr - id_bitlen..bitptr].to_owned()
+          });
+          let part = T::from_bits(&deduped_bs[bitptr..bitptr + info_bitlen]);
+          self.put_base_with_id(&part, assigned_id.as_deref())?;
+          bitptr += info_bitlen;
+          part
+        }
+        Separator::Deduped => {
+          ensure!(
+            bitptr + id_bitlen <= deduped_bs.len(),
+            "Truncated deduplicated data"
+          );
+          let id = deduped_bs[bitptr..bitptr + id_bitlen].to_owned();
+          bitptr += id_bitlen;
+          self.get_base(&id)?
+        }
+        Separator::SameBase => prev
+          .as_ref()
+          .map(|(b, _)| b.to_owned())
+          .ok_or(anyhow!("Same base without a preceding chunk"))?,
+        Separator::RawLiteral => {
+          ensure!(
+            bitptr + info_bitlen <= deduped_bs.len(),
+            "Truncated deduplicated data"
+          );
+          let part = T::from_bits(&deduped_bs[bitptr..bitptr + info_bitlen]);
+          bitptr += info_bitlen;
+          part
+        }
+      };
+      let (dev, dev_consumed) = self.dup_deviation(dev_sep, &deduped_bs[bitptr..], dev_bitlen)?;
+      bitptr += dev_consumed;
+      if self.multi_symbol {
+        prev = Some((base.clone(), dev.clone()));
+      }
+      decoded_chunks.push((base, dev));
+    }
+    Ok(decoded_chunks)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tokens_work_for_bytes_and_bits() {
+    let chunks = vec![
+      (vec![1u8, 2], vec![3u8]),
+      (vec![1u8, 2], vec![3u8]),
+      (vec![1u8, 2], vec![4u8]),
+      (vec![5u8, 6], vec![3u8]),
+    ];
+    for multi_symbol in [false, true] {
+      let mut dedup = FrameCoder::new(BasisDict::<U8VRep>::new(4));
+      let mut dup = FrameCoder::new(BasisDict::<U8VRep>::new(4));
+      dedup.multi_symbol = multi_symbol;
+      dup.multi_symbol = multi_symbol;
+      let tokens = dedup.encode_tokens(chunks.iter().cloned().map(Ok)).unwrap();
+      assert_eq!(dup.decode_tokens(&tokens, 16, 8).unwrap(), chunks);
+      assert_eq!((dedup.last_stats.chunks, dedup.last_stats.base_hits), (4, 2));
+    }
+
+    let bits = chunks
+      .iter()
+      .map(|(b, d)| (b[..1].to_vec().to_bits(), d.to_bits()[..3].to_bitvec()))
+      .collect::<Vec<_>>();
+    let mut dedup = FrameCoder::new(BasisDict::<BVRep>::new(4));
+    let mut dup = FrameCoder::new(BasisDict::<BVRep>::new(4));
+    let tokens = dedup.encode_tokens(bits.iter().cloned().map(Ok)).unwrap();
+    assert_eq!(dup.decode_tokens(&tokens, 8, 3).unwrap(), bits);
+  }
+}
@@ -1,5 +1,13 @@
 use super::{Deduped, GDTrait};
-use crate::{dict::BasisDict, error::*, separator::Separator};
+use crate::{
+  chunk::{merge_bit_chunks, merge_chunks, pad_bitlen, split_into_bit_chunks, split_into_chunks},
+  dict::BasisDict,
+  error::*,
+  frame::{FrameCoder, FrameUnit},
+  store::{BaseKey, BaseStore},
+  stored::{dedup_with_fallback, dup_with_fallback},
+  util::{get_varint, put_varint},
+};
 use async_trait::async_trait;
 use bitvec::prelude::*;
 use futures::{
@@ -8,127 +16,197 @@ use futures::{
 };
 use libecc::{types::*, *};
 use tokio::task::spawn_blocking;
+
+// key of base and deviation of a chunk in a frame deduplicated into a base store
+type KeyedChunk = (BaseKey, BVRep);
+type StoreFrame = (Option<Vec<usize>>, usize, Vec<KeyedChunk>);
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct BitGD<C>
 where
   C: Code + BitUnitCode + Clone,
 {
-  pub code: C,
-  pub basis_dict: BasisDict<BVRep>,
+  pub(crate) code: C,
   // TODO: separator, sometimes this should be a byte?
-  pub chunk_bytelen: usize,
+  pub(crate) chunk_bytelen: usize,
+  pub(crate) coder: FrameCoder<BVRep>,
+  // chunks of exactly code_bit_len bits across byte boundaries instead of chunk_bytelen bytes with zero bits
+  pub(crate) bitstream: bool,
 }
 
-///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
-#[async_trait]
-impl<C> GDTrait for BitGD<C>
+impl<C> BitGD<C>
 where
-  C: BitUnitCode + Send + Sync + Clone + 'static,
+  C: Code + BitUnitCode + Clone,
 {
-  fn unit_check(&self) {
-    println!("bit unit code");
+  pub(crate) fn new(code: C, chunk_bytelen: usize, basis_dict: BasisDict<BVRep>) -> Self {
+    BitGD {
+      code,
+      chunk_bytelen,
+      coder: FrameCoder::new(basis_dict),
+      bitstream: false,
+    }
   }
 
-  async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
-    let residue = buf.len() % self.chunk_bytelen;
-    let (chunk_num, last_chunk_pad_bytelen) = if residue == 0 {
-      (buf.len() / self.chunk_bytelen, 0)
-    } else {
-      (
-        (buf.len() - residue) / self.chunk_bytelen + 1,
-        self.chunk_bytelen - residue,
-      )
+  // Copy of the instance, which fails if its dictionaries cannot be copied
+  pub fn try_clone(&self) -> Result<Self> {
+    Ok(BitGD {
+      code: self.code.clone(),
+      chunk_bytelen: self.chunk_bytelen,
+      coder: self.coder.try_clone()?,
+      bitstream: self.bitstream,
+    })
+  }
+
+  // Upper bound of the byte length of a frame of bytelen bytes
+  pub(crate) fn max_frame_bytelen(&self, bytelen: usize) -> Result<usize> {
+    let chunk_bitlen = match self.bitstream {
+      true => self.code.code_bit_len(),
+      false => self.chunk_bytelen * u8::BITS as usize,
     };
+    let info_bitlen = self.code.info_bit_len();
+    let dev_bitlen = self.code.code_bit_len() - info_bitlen;
+    self
+      .coder
+      .max_frame_bytelen(bytelen, chunk_bitlen, info_bitlen, dev_bitlen)
+  }
 
-    // Currently Byte Alignment is employed, i.e., message is always in bytes and some padding of < 8bits is applied;
+  pub async fn set_error_alignment(&mut self, mat_slice: &[U8VRep]) -> Result<()> {
+    // shape and nonsingularity of the matrix are checked in the code with a detailed diagnostic
+    self.code.set_precoding(mat_slice)
+  }
+
+  // Segment lengths, segment header and padding of the last chunk in bytes.
+  // In bitstream mode, padding of the last chunk is given in bits as a varint header instead.
+  fn segment(&self, buf: &U8SRep) -> (Vec<usize>, U8VRep, usize) {
+    if self.bitstream && self.coder.chunker.is_none() {
+      let mut header = U8VRep::new();
+      put_varint(&mut header, pad_bitlen(buf.len(), self.code.code_bit_len()));
+      return (vec![buf.len()], header, 0);
+    }
+    self.coder.segment(buf, self.chunk_bytelen)
+  }
+
+  // Segment lengths if given, padding of the last chunk in bits if given, and the header length
+  fn parse_segment_header(
+    &self,
+    data: &U8SRep,
+  ) -> Result<(Option<Vec<usize>>, Option<usize>, usize)> {
+    if self.bitstream && self.coder.chunker.is_none() {
+      let (pad_bitlen, header_bytelen) = get_varint(data)?;
+      return Ok((None, Some(pad_bitlen), header_bytelen));
+    }
+    let (segment_lens, header_bytelen) = self.coder.parse_segment_header(data)?;
+    Ok((segment_lens, None, header_bytelen))
+  }
+
+  // Chunks of code_bit_len bits to be decoded
+  fn targets(&self, buf: &U8SRep, segment_lens: &[usize]) -> Vec<BVRep> {
     let code_len = self.code.code_bit_len();
+    if self.bitstream {
+      return split_into_bit_chunks(buf, code_len, segment_lens);
+    }
+    // Byte alignment, i.e., message is always in bytes and some padding of < 8bits is applied
     let code_pad_len = code_len - self.chunk_bytelen * 8;
-    let mut padded = vec![0u8; last_chunk_pad_bytelen];
-    let targets = (0..chunk_num)
-      .map(|i| {
-        let byte_ptr = self.chunk_bytelen * i;
+    split_into_chunks(buf, self.chunk_bytelen, segment_lens)
+      .into_iter()
+      .map(|chunk| {
         let mut target_bitslice = bitvec![u8, Msb0; 0; code_pad_len];
-        target_bitslice.extend_from_raw_slice({
-          if i == chunk_num - 1 && residue > 0 {
-            padded.extend_from_slice(&buf[byte_ptr..buf.len()]);
-            padded.as_slice()
-          } else {
-            &buf[byte_ptr..byte_ptr + self.chunk_bytelen]
-          }
-        });
+        target_bitslice.extend_from_raw_slice(&chunk);
         target_bitslice
       })
-      .collect::<Vec<BVRep>>();
-    let decoded_chunks: Vec<_> = join_all(
-      stream::iter(targets)
-        .map(|v| async {
-          let code = self.code.to_owned();
-          spawn_blocking(move || code.decode(&v)).await?
-        })
-        .collect::<Vec<_>>()
-        .await,
-    )
-    .await;
+      .collect()
+  }
 
-    let mut res = BVRep::new();
-    for decoded_wrapped in decoded_chunks {
-      let decoded = decoded_wrapped?;
-      // write result and update dict
-      let (sep, id_or_base) = match self.basis_dict.get_id(&decoded.base) {
-        Some(bit_id) => (Separator::Deduped.bv(), bit_id),
-        None => {
-          let _new_id = self.basis_dict.put_base(&decoded.base)?;
-          (Separator::AsIs.bv(), decoded.base)
-        }
-      };
-      res.extend_from_bitslice(&sep);
-      res.extend_from_bitslice(&id_or_base);
-      res.extend_from_bitslice(&decoded.deviation);
+  // Merge encoded chunks of code_bit_len bits, where padding of the last chunk is given in bits in bitstream mode
+  fn merge(
+    &self,
+    chunks: Vec<BVRep>,
+    segment_lens: Option<Vec<usize>>,
+    last_chunk_pad: usize,
+  ) -> Result<U8VRep> {
+    let code_len = self.code.code_bit_len();
+    if self.bitstream {
+      let segment_lens = segment_lens.unwrap_or_else(|| {
+        vec![(chunks.len() * code_len).saturating_sub(last_chunk_pad) / u8::BITS as usize]
+      });
+      return merge_bit_chunks(&chunks, code_len, &segment_lens);
     }
+    let chunks = chunks
+      .into_iter()
+      .map(|chunk| {
+        ensure!(chunk.len() == code_len, "Invalid target in dup");
+        let mut target_bitvec = BVRep::with_capacity(self.chunk_bytelen * 8);
+        target_bitvec.extend_from_bitslice(&chunk[code_len - self.chunk_bytelen * 8..]);
+        Ok(target_bitvec.into_vec())
+      })
+      .collect::<Result<Vec<U8VRep>>>()?;
+    let segment_lens = segment_lens
+      .unwrap_or_else(|| vec![(chunks.len() * self.chunk_bytelen).saturating_sub(last_chunk_pad)]);
+    merge_chunks(&chunks, self.chunk_bytelen, &segment_lens)
+  }
+}
 
-    res.force_align();
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+impl<C> BitGD<C>
+where
+  C: BitUnitCode + Send + Sync + Clone + 'static,
+{
+  // Deduplication into a content-addressed base store for archives, where every base is put to the store
+  // and each chunk is given as [key of base (64 bits)][deviation] without separator.
+  pub async fn dedup_into_store(&self, buf: &U8SRep, store: &mut BaseStore) -> Result<Deduped> {
+    let (segment_lens, mut header, last_chunk_pad_bytelen) = self.segment(buf);
+    let targets = self.targets(buf, &segment_lens);
+    let decoded_chunks = self
+      .decode_chunks(targets)
+      .await
+      .into_iter()
+      .collect::<Result<Vec<_>>>()?;
+    header.extend_from_slice(&store.put_chunks(decoded_chunks));
     Ok(Deduped {
-      data: res.as_raw_slice().to_vec(),
+      data: header,
       last_chunk_pad_bytelen,
     })
   }
 
-  async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
-    let deduped_bs = BitSlice::from_slice(&deduped.data);
-    let code_len = self.code.code_bit_len();
-    let info_len = self.code.info_bit_len();
-    let dev_len = code_len - info_len;
-    let id_bitlen = self.basis_dict.id_bitlen();
-    let mut res = BVRep::new();
-
-    let mut decoded_chunks: Vec<(BVRep, BVRep)> = Vec::new();
-    let mut bitptr = 0usize;
-    let max_bit_pads = 7usize;
-    // max bit pad = 7 bits, if actual bitlen = 9 (0..8), 7bits pad is given.
-    // then bitptr = 9 here and deduped_bs.len() = 15
-    while bitptr < deduped_bs.len() - max_bit_pads {
-      let sep = Separator::from(deduped_bs[bitptr]);
-      bitptr += 1;
-
-      decoded_chunks.push(match sep {
-        Separator::AsIs => {
-          let part = &deduped_bs[bitptr..bitptr + info_len];
-          let _new_id = self.basis_dict.put_base(&part.to_bitvec())?;
-          bitptr += info_len;
-          let dev = deduped_bs[bitptr..bitptr + dev_len].to_bitvec();
-          (part.to_bitvec(), dev)
-        }
-        Separator::Deduped => {
-          let id = deduped_bs[bitptr..bitptr + id_bitlen].to_owned();
-          bitptr += id_bitlen;
-          let dev = deduped_bs[bitptr..bitptr + dev_len].to_bitvec();
-          (self.basis_dict.get_base(&id)?, dev)
-        }
-      });
-      bitptr += dev_len;
-    }
+  pub async fn dup_from_store(&self, deduped: &Deduped, store: &BaseStore) -> Result<U8VRep> {
+    let (segment_lens, last_chunk_pad, keyed_chunks) = self.parse_store_frame(deduped)?;
+    let chunks = self.encode_chunks(store.get_chunks(keyed_chunks)?).await?;
+    self.merge(chunks, segment_lens, last_chunk_pad)
+  }
+
+  // Release bases referenced by a frame deduplicated into the store, e.g., when an archive is deleted
+  pub fn release_from_store(&self, deduped: &Deduped, store: &mut BaseStore) -> Result<()> {
+    let (_, _, keyed_chunks) = self.parse_store_frame(deduped)?;
+    store.release_chunks(&keyed_chunks)
+  }
+
+  // Segment lengths if given, padding of the last chunk, and keyed chunks
+  fn parse_store_frame(&self, deduped: &Deduped) -> Result<StoreFrame> {
+    let (segment_lens, pad_bitlen, header_bytelen) = self.parse_segment_header(&deduped.data)?;
+    let dev_bitlen = self.code.code_bit_len() - self.code.info_bit_len();
+    let chunks = BaseStore::parse_chunks(&deduped.data[header_bytelen..], dev_bitlen)?;
+    let last_chunk_pad = pad_bitlen.unwrap_or(deduped.last_chunk_pad_bytelen);
+    Ok((segment_lens, last_chunk_pad, chunks))
+  }
 
+  // Bases and deviations of chunks
+  async fn decode_chunks(&self, targets: Vec<BVRep>) -> Vec<Result<(BVRep, BVRep)>> {
+    join_all(
+      stream::iter(targets)
+        .map(|v| async {
+          let code = self.code.to_owned();
+          let decoded = spawn_blocking(move || code.decode(&v)).await?;
+          decoded.map(|d| (d.base, d.deviation))
+        })
+        .collect::<Vec<_>>()
+        .await,
+    )
+    .await
+  }
+
+  // Chunks encoded from bases and deviations
+  async fn encode_chunks(&self, decoded_chunks: Vec<(BVRep, BVRep)>) -> Result<Vec<BVRep>> {
     let encoded_chunks: Vec<_> = join_all(
       stream::iter(decoded_chunks)
         .map(|(base, dev)| async {
@@ -140,18 +218,78 @@ where
     )
     .await;
 
-    let chunk_num = encoded_chunks.len();
-    for (i, chunk_wrapped) in encoded_chunks.into_iter().enumerate() {
-      let chunk = chunk_wrapped?;
-      let target = if i == chunk_num - 1 {
-        &chunk.0[code_len - self.chunk_bytelen * 8 + deduped.last_chunk_pad_bytelen * 8..]
-      } else {
-        &chunk.0[code_len - self.chunk_bytelen * 8..]
-      };
-      ensure!(target.len() % 8 == 0, "Invalid target in dup");
-      res.extend_from_bitslice(target);
-    }
+    encoded_chunks
+      .into_iter()
+      .map(|chunk_wrapped| chunk_wrapped.map(|chunk| chunk.0))
+      .collect()
+  }
+}
+
+impl<C> BitGD<C>
+where
+  C: BitUnitCode + Send + Sync + Clone + 'static,
+{
+  async fn dedup_frame(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let mut header = self.coder.begin_dedup_frame();
+    let (segment_lens, segment_header, last_chunk_pad_bytelen) = self.segment(buf);
+    header.extend_from_slice(&segment_header);
+
+    let targets = self.targets(buf, &segment_lens);
+    let decoded_chunks = self.decode_chunks(targets).await;
 
-    Ok(res.as_raw_slice().to_owned())
+    let tokens = self.coder.encode_tokens(decoded_chunks)?;
+    header.extend_from_slice(&tokens);
+    self.coder.end_dedup_frame()?;
+    Ok(Deduped {
+      data: header,
+      last_chunk_pad_bytelen,
+    })
+  }
+
+  async fn dup_frame(&mut self, data: &U8SRep, last_chunk_pad_bytelen: usize) -> Result<U8VRep> {
+    let control_bytelen = self.coder.begin_dup_frame(data)?;
+    let (segment_lens, pad_bitlen, segment_header_bytelen) =
+      self.parse_segment_header(&data[control_bytelen..])?;
+    let header_bytelen = control_bytelen + segment_header_bytelen;
+    let code_len = self.code.code_bit_len();
+    let info_len = self.code.info_bit_len();
+    let dev_len = code_len - info_len;
+    let decoded_chunks = self
+      .coder
+      .decode_tokens(&data[header_bytelen..], info_len, dev_len)?;
+
+    let chunks = self.encode_chunks(decoded_chunks).await?;
+    let last_chunk_pad = pad_bitlen.unwrap_or(last_chunk_pad_bytelen);
+    let res = self.merge(chunks, segment_lens, last_chunk_pad)?;
+    self.coder.end_dup_frame();
+    Ok(res)
+  }
+}
+
+impl<C> FrameUnit for BitGD<C>
+where
+  C: Code + BitUnitCode + Clone,
+{
+  type Base = BVRep;
+  fn coder_mut(&mut self) -> &mut FrameCoder<Self::Base> {
+    &mut self.coder
+  }
+}
+
+#[async_trait]
+impl<C> GDTrait for BitGD<C>
+where
+  C: BitUnitCode + Send + Sync + Clone + 'static,
+{
+  fn unit_check(&self) {
+    println!("bit unit code");
+  }
+
+  async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    dedup_with_fallback(self, buf, |gd, buf| Box::pin(gd.dedup_frame(buf))).await
+  }
+
+  async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
+    dup_with_fallback(self, deduped, |gd, data, pad| Box::pin(gd.dup_frame(data, pad))).await
   }
 }
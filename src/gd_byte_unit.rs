@@ -1,143 +1,130 @@
 use super::{Deduped, GDTrait};
-use crate::{dict::BasisDict, error::*, separator::Separator};
+use crate::{
+  chunk::{merge_chunks, split_into_chunks},
+  dict::BasisDict,
+  error::*,
+  frame::{FrameCoder, FrameUnit},
+  store::{BaseKey, BaseStore},
+  stored::{dedup_with_fallback, dup_with_fallback},
+};
 use async_trait::async_trait;
-use bitvec::prelude::*;
 use futures::{
   future::join_all,
   stream::{self, StreamExt},
 };
 use libecc::{types::*, *};
 use tokio::task::spawn_blocking;
+
+// key of base and deviation of a chunk in a frame deduplicated into a base store
+type KeyedChunk = (BaseKey, U8VRep);
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ByteGD<C>
 where
   C: Code + ByteUnitCode + Clone,
 {
-  pub code: C,
-  pub basis_dict: BasisDict<U8VRep>,
+  pub(crate) code: C,
   // TODO: separator, sometimes this should be a byte?
-  pub chunk_bytelen: usize,
+  pub(crate) chunk_bytelen: usize,
+  pub(crate) coder: FrameCoder<U8VRep>,
 }
 
 impl<C> ByteGD<C>
 where
   C: Code + ByteUnitCode + Clone,
 {
+  pub(crate) fn new(code: C, chunk_bytelen: usize, basis_dict: BasisDict<U8VRep>) -> Self {
+    ByteGD {
+      code,
+      chunk_bytelen,
+      coder: FrameCoder::new(basis_dict),
+    }
+  }
+
+  // Copy of the instance, which fails if its dictionaries cannot be copied
+  pub fn try_clone(&self) -> Result<Self> {
+    Ok(ByteGD {
+      code: self.code.clone(),
+      chunk_bytelen: self.chunk_bytelen,
+      coder: self.coder.try_clone()?,
+    })
+  }
+
+  // Upper bound of the byte length of a frame of bytelen bytes
+  pub(crate) fn max_frame_bytelen(&self, bytelen: usize) -> Result<usize> {
+    let u8size = u8::BITS as usize;
+    let info_bitlen = self.code.info_byte_len() * u8size;
+    let dev_bitlen = (self.code.code_byte_len() - self.code.info_byte_len()) * u8size;
+    self
+      .coder
+      .max_frame_bytelen(bytelen, self.chunk_bytelen * u8size, info_bitlen, dev_bitlen)
+  }
+
   pub async fn set_error_alignment(&mut self, mat_slice: &[U8VRep]) -> Result<()> {
-    ensure!(mat_slice.len() == self.code.code_byte_len(), "Invalid matrix size");
+    // shape and nonsingularity of the matrix are checked in the code with a detailed diagnostic
     self.code.set_precoding(mat_slice)
   }
 }
 
-#[async_trait]
-impl<C> GDTrait for ByteGD<C>
+impl<C> ByteGD<C>
 where
   C: ByteUnitCode + Send + Sync + Clone + 'static,
 {
-  fn unit_check(&self) {
-    println!("byte unit code");
+  // Deduplication into a content-addressed base store for archives, where every base is put to the store
+  // and each chunk is given as [key of base (64 bits)][deviation] without separator.
+  pub async fn dedup_into_store(&self, buf: &U8SRep, store: &mut BaseStore) -> Result<Deduped> {
+    let (segment_lens, mut header, last_chunk_pad_bytelen) =
+      self.coder.segment(buf, self.chunk_bytelen);
+    let targets = split_into_chunks(buf, self.chunk_bytelen, &segment_lens);
+    let decoded_chunks = self
+      .decode_chunks(targets)
+      .await
+      .into_iter()
+      .collect::<Result<Vec<_>>>()?;
+    header.extend_from_slice(&store.put_chunks(decoded_chunks));
+    Ok(Deduped {
+      data: header,
+      last_chunk_pad_bytelen,
+    })
   }
 
-  async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
-    let residue = buf.len() % self.chunk_bytelen;
-    let (chunk_num, last_chunk_pad_bytelen) = if residue == 0 {
-      (buf.len() / self.chunk_bytelen, 0)
-    } else {
-      (
-        (buf.len() - residue) / self.chunk_bytelen + 1,
-        self.chunk_bytelen - residue,
-      )
-    };
-
-    let mut padded = vec![0u8; last_chunk_pad_bytelen];
-
-    let targets = (0..chunk_num)
-      .map(|i| {
-        let byte_ptr = self.chunk_bytelen * i;
-        if i == chunk_num - 1 && residue > 0 {
-          padded.extend_from_slice(&buf[byte_ptr..buf.len()]);
-          padded.clone()
-        } else {
-          buf[byte_ptr..byte_ptr + self.chunk_bytelen].to_owned()
-        }
-      })
-      .collect::<Vec<U8VRep>>();
-
-    let decoded_chunks: Vec<_> = join_all(
+  pub async fn dup_from_store(&self, deduped: &Deduped, store: &BaseStore) -> Result<U8VRep> {
+    let (segment_lens, keyed_chunks) = self.parse_store_frame(deduped)?;
+    let chunks = self.encode_chunks(store.get_chunks(keyed_chunks)?).await?;
+    self.merge(&chunks, segment_lens, deduped.last_chunk_pad_bytelen)
+  }
+
+  // Release bases referenced by a frame deduplicated into the store, e.g., when an archive is deleted
+  pub fn release_from_store(&self, deduped: &Deduped, store: &mut BaseStore) -> Result<()> {
+    let (_, keyed_chunks) = self.parse_store_frame(deduped)?;
+    store.release_chunks(&keyed_chunks)
+  }
+
+  fn parse_store_frame(&self, deduped: &Deduped) -> Result<(Option<Vec<usize>>, Vec<KeyedChunk>)> {
+    let (segment_lens, header_bytelen) = self.coder.parse_segment_header(&deduped.data)?;
+    let dev_bitlen = (self.code.code_byte_len() - self.code.info_byte_len()) * u8::BITS as usize;
+    let chunks = BaseStore::parse_chunks(&deduped.data[header_bytelen..], dev_bitlen)?;
+    Ok((segment_lens, chunks))
+  }
+
+  // Bases and deviations of chunks
+  async fn decode_chunks(&self, targets: Vec<U8VRep>) -> Vec<Result<(U8VRep, U8VRep)>> {
+    join_all(
       stream::iter(targets)
         .map(|v| async {
           let code = self.code.to_owned();
-          spawn_blocking(move || code.decode(&v)).await?
+          let decoded = spawn_blocking(move || code.decode(&v)).await?;
+          decoded.map(|d| (d.base, d.deviation))
         })
         .collect::<Vec<_>>()
         .await,
     )
-    .await;
-
-    let mut res = BVRep::new();
-    for decoded_wrapped in decoded_chunks {
-      let decoded = decoded_wrapped?;
-      // write result and update dict
-      let (sep, id_or_base) = match self.basis_dict.get_id(&decoded.base) {
-        Some(bit_id) => (Separator::Deduped.bv(), bit_id),
-        None => {
-          let _new_id = self.basis_dict.put_base(&decoded.base)?;
-          (Separator::AsIs.bv(), BVRep::from_slice(&decoded.base))
-        }
-      };
-      res.extend_from_bitslice(&sep);
-      res.extend_from_bitslice(&id_or_base);
-      res.extend_from_bitslice(&BVRep::from_slice(&decoded.deviation));
-    }
-
-    res.force_align();
-    Ok(Deduped {
-      data: res.as_raw_slice().to_vec(),
-      last_chunk_pad_bytelen,
-    })
+    .await
   }
-  async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
-    let deduped_bs = BitSlice::from_slice(&deduped.data);
-
-    let u8size = u8::BITS as usize;
-    let code_bitlen = self.code.code_byte_len() * u8size;
-    let info_bitlen = self.code.info_byte_len() * u8size;
-    let dev_bitlen = code_bitlen - info_bitlen;
-    let id_bitlen = self.basis_dict.id_bitlen();
-    let mut res = U8VRep::new();
-
-    let mut decoded_chunks: Vec<(U8VRep, U8VRep)> = Vec::new();
-    let mut bitptr = 0usize;
-    let max_bit_pads = 7usize;
-    // max bit pad = 7 bits, if actual bitlen = 9 (0..8), 7bits pad is given.
-    // then bitptr = 9 here and deduped_bs.len() = 15
-    while bitptr < deduped_bs.len() - max_bit_pads {
-      let sep = Separator::from(deduped_bs[bitptr]);
-      bitptr += 1;
-
-      decoded_chunks.push(match sep {
-        Separator::AsIs => {
-          let mut bv = deduped_bs[bitptr..bitptr + info_bitlen].to_bitvec().clone();
-          bv.force_align();
-          let part = bv.as_raw_slice().to_owned();
-          let _new_id = self.basis_dict.put_base(&part)?;
-          bitptr += info_bitlen;
-          let mut bv = deduped_bs[bitptr..bitptr + dev_bitlen].to_bitvec().clone();
-          bv.force_align();
-          (part.to_owned(), bv.as_raw_slice().to_owned())
-        }
-        Separator::Deduped => {
-          let id = deduped_bs[bitptr..bitptr + id_bitlen].to_owned();
-          bitptr += id_bitlen;
-          let mut bv = deduped_bs[bitptr..bitptr + dev_bitlen].to_bitvec().clone();
-          bv.force_align();
-          (self.basis_dict.get_base(&id)?, bv.as_raw_slice().to_owned())
-        }
-      });
-      bitptr += dev_bitlen;
-    }
 
+  // Chunks encoded from bases and deviations
+  async fn encode_chunks(&self, decoded_chunks: Vec<(U8VRep, U8VRep)>) -> Result<Vec<U8VRep>> {
     let encoded_chunks: Vec<_> = join_all(
       stream::iter(decoded_chunks)
         .map(|(base, dev)| async {
@@ -149,17 +136,90 @@ where
     )
     .await;
 
-    let chunk_num = encoded_chunks.len();
-    for (i, chunk_wrapped) in encoded_chunks.into_iter().enumerate() {
-      let chunk = chunk_wrapped?;
-      let target = if i == chunk_num - 1 {
-        &chunk.0[deduped.last_chunk_pad_bytelen..]
-      } else {
-        &chunk.0[..]
-      };
-      res.extend_from_slice(target);
-    }
+    encoded_chunks
+      .into_iter()
+      .map(|chunk_wrapped| chunk_wrapped.map(|chunk| chunk.0))
+      .collect()
+  }
+
+  fn merge(
+    &self,
+    chunks: &[U8VRep],
+    segment_lens: Option<Vec<usize>>,
+    last_chunk_pad_bytelen: usize,
+  ) -> Result<U8VRep> {
+    let segment_lens = segment_lens.unwrap_or_else(|| {
+      vec![(chunks.len() * self.chunk_bytelen).saturating_sub(last_chunk_pad_bytelen)]
+    });
+    merge_chunks(chunks, self.chunk_bytelen, &segment_lens)
+  }
+}
 
+impl<C> ByteGD<C>
+where
+  C: ByteUnitCode + Send + Sync + Clone + 'static,
+{
+  async fn dedup_frame(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let mut header = self.coder.begin_dedup_frame();
+    let (segment_lens, segment_header, last_chunk_pad_bytelen) =
+      self.coder.segment(buf, self.chunk_bytelen);
+    header.extend_from_slice(&segment_header);
+    let targets = split_into_chunks(buf, self.chunk_bytelen, &segment_lens);
+    let decoded_chunks = self.decode_chunks(targets).await;
+
+    let tokens = self.coder.encode_tokens(decoded_chunks)?;
+    header.extend_from_slice(&tokens);
+    self.coder.end_dedup_frame()?;
+    Ok(Deduped {
+      data: header,
+      last_chunk_pad_bytelen,
+    })
+  }
+  async fn dup_frame(&mut self, data: &U8SRep, last_chunk_pad_bytelen: usize) -> Result<U8VRep> {
+    let control_bytelen = self.coder.begin_dup_frame(data)?;
+    let (segment_lens, segment_header_bytelen) =
+      self.coder.parse_segment_header(&data[control_bytelen..])?;
+    let header_bytelen = control_bytelen + segment_header_bytelen;
+
+    let u8size = u8::BITS as usize;
+    let code_bitlen = self.code.code_byte_len() * u8size;
+    let info_bitlen = self.code.info_byte_len() * u8size;
+    let dev_bitlen = code_bitlen - info_bitlen;
+    let decoded_chunks = self
+      .coder
+      .decode_tokens(&data[header_bytelen..], info_bitlen, dev_bitlen)?;
+
+    let chunks = self.encode_chunks(decoded_chunks).await?;
+    let res = self.merge(&chunks, segment_lens, last_chunk_pad_bytelen)?;
+    self.coder.end_dup_frame();
     Ok(res)
   }
 }
+
+impl<C> FrameUnit for ByteGD<C>
+where
+  C: Code + ByteUnitCode + Clone,
+{
+  type Base = U8VRep;
+  fn coder_mut(&mut self) -> &mut FrameCoder<Self::Base> {
+    &mut self.coder
+  }
+}
+
+#[async_trait]
+impl<C> GDTrait for ByteGD<C>
+where
+  C: ByteUnitCode + Send + Sync + Clone + 'static,
+{
+  fn unit_check(&self) {
+    println!("byte unit code");
+  }
+
+  async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    dedup_with_fallback(self, buf, |gd, buf| Box::pin(gd.dedup_frame(buf))).await
+  }
+
+  async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
+    dup_with_fallback(self, deduped, |gd, data, pad| Box::pin(gd.dup_frame(data, pad))).await
+  }
+}
@@ -0,0 +1,198 @@
+use super::{Deduped, GDTrait};
+use crate::{
+  chunk::{merge_chunks, split_into_chunks},
+  dict::BasisDict,
+  error::*,
+  frame::{FrameCoder, FrameUnit},
+  stored::{dedup_with_fallback, dup_with_fallback},
+  util::SplitMix64,
+};
+use async_trait::async_trait;
+use bitvec::prelude::*;
+use libecc::types::*;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// GD based on deletion (Yggdrasil-style), where a fixed set of bit positions of every chunk is chosen by a seeded PRNG.
+// The deviation of a chunk is given by the bits at the deleted positions and its base by the remaining bits, and hence
+// chunks differing only at the deleted positions share the same base. Both dedup and dup sides must use the same seed.
+#[derive(Debug)]
+pub struct DeletionGD {
+  // true at deleted bit positions of a chunk
+  pub(crate) deletion_mask: BVRep,
+  pub(crate) chunk_bytelen: usize,
+  // dictionary and modes, of which only the single-bit separator without dictionary modes is available
+  pub(crate) coder: FrameCoder<BVRep>,
+}
+
+impl DeletionGD {
+  // Copy of the instance, which fails if its dictionary cannot be copied
+  pub fn try_clone(&self) -> Result<Self> {
+    Ok(DeletionGD {
+      deletion_mask: self.deletion_mask.clone(),
+      chunk_bytelen: self.chunk_bytelen,
+      coder: self.coder.try_clone()?,
+    })
+  }
+
+  pub fn new(
+    chunk_bytelen: usize,
+    deleted_bitlen: usize,
+    seed: u64,
+    basis_dict: BasisDict<BVRep>,
+  ) -> Result<Self> {
+    let chunk_bitlen = chunk_bytelen * u8::BITS as usize;
+    ensure!(
+      deleted_bitlen > 0 && deleted_bitlen < chunk_bitlen,
+      "Deleted bits must be in (0, {})",
+      chunk_bitlen
+    );
+    // partial Fisher-Yates shuffle of bit positions
+    let mut rng = SplitMix64::new(seed);
+    let mut positions = (0..chunk_bitlen).collect::<Vec<_>>();
+    let mut deletion_mask = bitvec![u8, Msb0; 0; chunk_bitlen];
+    for i in 0..deleted_bitlen {
+      let j = i + (rng.next_u64() % (chunk_bitlen - i) as u64) as usize;
+      positions.swap(i, j);
+      deletion_mask.set(positions[i], true);
+    }
+    Ok(DeletionGD {
+      deletion_mask,
+      chunk_bytelen,
+      coder: FrameCoder::new(basis_dict),
+    })
+  }
+
+  pub fn deleted_bitlen(&self) -> usize {
+    self.deletion_mask.count_ones()
+  }
+
+  // Upper bound of the byte length of a frame of bytelen bytes
+  pub(crate) fn max_frame_bytelen(&self, bytelen: usize) -> Result<usize> {
+    let (base_bitlen, dev_bitlen) = (self.base_bitlen(), self.deleted_bitlen());
+    self
+      .coder
+      .max_frame_bytelen(bytelen, self.deletion_mask.len(), base_bitlen, dev_bitlen)
+  }
+
+  fn base_bitlen(&self) -> usize {
+    self.deletion_mask.count_zeros()
+  }
+
+  // Split a chunk into the base of remaining bits and the deviation of deleted bits
+  fn split(&self, chunk: &U8SRep) -> (BVRep, BVRep) {
+    let mut base = BVRep::with_capacity(self.base_bitlen());
+    let mut deviation = BVRep::with_capacity(self.deleted_bitlen());
+    for (bit, deleted) in chunk
+      .view_bits::<Msb0>()
+      .iter()
+      .zip(self.deletion_mask.iter())
+    {
+      if *deleted {
+        deviation.push(*bit);
+      } else {
+        base.push(*bit);
+      }
+    }
+    (base, deviation)
+  }
+
+  // Insert the deleted bits back into the base
+  fn merge(&self, base: &BSRep, deviation: &BSRep) -> U8VRep {
+    let (mut base_iter, mut dev_iter) = (base.iter(), deviation.iter());
+    let mut chunk = BVRep::with_capacity(self.deletion_mask.len());
+    for deleted in self.deletion_mask.iter() {
+      let bit = if *deleted {
+        dev_iter.next()
+      } else {
+        base_iter.next()
+      };
+      chunk.push(bit.is_some_and(|b| *b));
+    }
+    chunk.into_vec()
+  }
+
+  fn dedup_frame(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let (segment_lens, mut header, last_chunk_pad_bytelen) =
+      self.coder.segment(buf, self.chunk_bytelen);
+    let decoded_chunks = split_into_chunks(buf, self.chunk_bytelen, &segment_lens)
+      .iter()
+      .map(|chunk| Ok(self.split(chunk)))
+      .collect::<Vec<_>>();
+
+    header.extend_from_slice(&self.coder.encode_tokens(decoded_chunks)?);
+    Ok(Deduped {
+      data: header,
+      last_chunk_pad_bytelen,
+    })
+  }
+
+  fn dup_frame(&mut self, data: &U8SRep, last_chunk_pad_bytelen: usize) -> Result<U8VRep> {
+    let (segment_lens, header_bytelen) = self.coder.parse_segment_header(data)?;
+    let (base_bitlen, dev_bitlen) = (self.base_bitlen(), self.deleted_bitlen());
+    let decoded_chunks = self
+      .coder
+      .decode_tokens(&data[header_bytelen..], base_bitlen, dev_bitlen)?;
+    let chunks = decoded_chunks
+      .iter()
+      .map(|(base, deviation)| self.merge(base, deviation))
+      .collect::<Vec<_>>();
+
+    let segment_lens = segment_lens.unwrap_or_else(|| {
+      vec![(chunks.len() * self.chunk_bytelen).saturating_sub(last_chunk_pad_bytelen)]
+    });
+    merge_chunks(&chunks, self.chunk_bytelen, &segment_lens)
+  }
+}
+
+impl FrameUnit for DeletionGD {
+  type Base = BVRep;
+  fn coder_mut(&mut self) -> &mut FrameCoder<Self::Base> {
+    &mut self.coder
+  }
+}
+
+#[async_trait]
+impl GDTrait for DeletionGD {
+  fn unit_check(&self) {
+    println!("deletion unit");
+  }
+
+  async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    dedup_with_fallback(self, buf, |gd, buf| Box::pin(async move { gd.dedup_frame(buf) })).await
+  }
+
+  async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
+    dup_with_fallback(self, deduped, |gd, data, pad| {
+      Box::pin(async move { gd.dup_frame(data, pad) })
+    })
+    .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn deletion_split_merge_works() {
+    let gd = DeletionGD::new(4, 5, 1, BasisDict::new(1)).unwrap();
+    assert_eq!(gd.deleted_bitlen(), 5);
+    let chunk = vec![0x12u8, 0x34, 0x56, 0x78];
+    let (base, deviation) = gd.split(&chunk);
+    assert_eq!((base.len(), deviation.len()), (27, 5));
+    assert_eq!(gd.merge(&base, &deviation), chunk);
+
+    // chunks differing only at deleted positions share the base
+    let mut other = chunk.view_bits::<Msb0>().to_bitvec();
+    for pos in gd.deletion_mask.iter_ones() {
+      let bit = !other[pos];
+      other.set(pos, bit);
+    }
+    assert_eq!(gd.split(other.as_raw_slice()).0, base);
+
+    // same seed gives the same positions
+    let same = DeletionGD::new(4, 5, 1, BasisDict::new(1)).unwrap();
+    assert_eq!(same.deletion_mask, gd.deletion_mask);
+    assert!(DeletionGD::new(4, 32, 1, BasisDict::new(1)).is_err());
+  }
+}
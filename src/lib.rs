@@ -1,59 +1,180 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod adaptive;
+#[cfg(feature = "std")]
+mod analyze;
+#[cfg(feature = "std")]
+mod blocking;
+#[cfg(feature = "std")]
+mod cdc;
+mod chunk;
+#[cfg(feature = "codec")]
+mod codec;
+mod dedup_gd;
 mod dict;
+mod dict_backend;
 mod error;
+#[cfg(feature = "std")]
+mod frame;
+#[cfg(feature = "std")]
 mod gd_bit_unit;
+#[cfg(feature = "std")]
 mod gd_byte_unit;
+#[cfg(feature = "std")]
+mod gd_deletion_unit;
+#[cfg(feature = "std")]
+mod literal;
+mod lru;
+#[cfg(feature = "std")]
+mod packet;
+#[cfg(feature = "std")]
+mod record;
+#[cfg(feature = "std")]
+mod resync;
+#[cfg(feature = "std")]
+mod seekable;
 mod separator;
+#[cfg(feature = "std")]
+mod shared_dict;
+#[cfg(feature = "std")]
+mod store;
+#[cfg(feature = "std")]
+mod stored;
+mod util;
 
+#[cfg(feature = "std")]
 use async_trait::async_trait;
-use dict::BasisDict;
+#[cfg(feature = "std")]
+use cdc::ContentDefinedChunker;
 use error::*;
+#[cfg(feature = "std")]
 use gd_bit_unit::BitGD;
+#[cfg(feature = "std")]
 use gd_byte_unit::ByteGD;
+#[cfg(feature = "std")]
+use gd_deletion_unit::DeletionGD;
 use libecc::{types::*, *};
+#[cfg(feature = "std")]
+use literal::LiteralPolicy;
+#[cfg(feature = "std")]
+use packet::PacketDict;
+#[cfg(feature = "std")]
+use resync::StreamSync;
+#[cfg(feature = "std")]
+use shared_dict::SharedDictHandle;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+#[cfg(feature = "std")]
+pub use adaptive::{AdaptiveGD, AdaptivePolicy, FrameStats};
+#[cfg(feature = "std")]
+pub use analyze::{analyze, default_candidates, setup_best, Analysis};
+#[cfg(feature = "std")]
+pub use blocking::{GdReader, GdWriter};
+#[cfg(feature = "codec")]
+pub use codec::{GdCodec, GdFramed, DEFAULT_MAX_FRAME_BYTELEN};
+pub use dedup_gd::DedupGD;
+pub use dict::{BasisDict, DictSnapshot};
+pub use dict_backend::{CompactDictBackend, DictBackend, DictBase};
+#[cfg(feature = "std")]
+pub use dict_backend::{DictStorage, FileDictBackend, MemoryDictBackend};
+pub use lru::{LruList, LruNode, LruNodes, LruOrder};
+#[cfg(feature = "std")]
+pub use record::{RecordField, RecordGD, RecordSchema};
+#[cfg(feature = "std")]
+pub use seekable::{SeekableGdReader, SeekableGdWriter};
+#[cfg(feature = "std")]
+pub use shared_dict::SharedDict;
+#[cfg(feature = "std")]
+pub use store::{BaseKey, BaseStore};
+#[cfg(feature = "std")]
+pub use stored::STORED_FALLBACK_MAX_EXPANSION;
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GD {
   ReedSolomon(usize, usize),
   Hamming(usize),
+  // chunk length in bytes, number of deleted bits in a chunk, and seed of PRNG choosing deleted positions
+  Deletion(usize, usize, u64),
 }
 impl GD {
+  #[cfg(feature = "std")]
   pub async fn setup(&self, dict_size: usize) -> Result<GDInner> {
+    self
+      .setup_with_storage(dict_size, DictStorage::Memory)
+      .await
+  }
+
+  // Setup with a dictionary held in the given storage, e.g., files for dictionaries larger than RAM
+  #[cfg(feature = "std")]
+  pub async fn setup_with_storage(
+    &self,
+    dict_size: usize,
+    storage: DictStorage,
+  ) -> Result<GDInner> {
     // TODO: consider parallelization using async
     match self {
-      GD::ReedSolomon(a, b) => Ok(GDInner::ReedSolomon(ByteGD {
-        code: ReedSolomon::new(*a, *b).await?,
-        basis_dict: BasisDict::<U8VRep>::new(dict_size),
-        chunk_bytelen: *a,
-      })),
+      GD::ReedSolomon(a, b) => Ok(GDInner::ReedSolomon(ByteGD::new(
+        ReedSolomon::new(*a, *b).await?,
+        *a,
+        BasisDict::with_backend(dict_size, storage.backend::<U8VRep>(dict_size)?),
+      ))),
 
       GD::Hamming(a) => {
         let code = Hamming::new(*a as u32)?;
         ensure!(code.code_bit_len >= 8, "Insufficient code length");
         let chunk_bytelen = (code.code_bit_len - code.code_bit_len % 8) / 8;
-        Ok(GDInner::Hamming(BitGD {
+        Ok(GDInner::Hamming(BitGD::new(
           code,
-          basis_dict: BasisDict::<BVRep>::new(dict_size),
           chunk_bytelen,
-        }))
+          BasisDict::with_backend(dict_size, storage.backend::<BVRep>(dict_size)?),
+        )))
       }
+
+      GD::Deletion(chunk_bytelen, deleted_bitlen, seed) => Ok(GDInner::Deletion(DeletionGD::new(
+        *chunk_bytelen,
+        *deleted_bitlen,
+        *seed,
+        BasisDict::with_backend(dict_size, storage.backend::<BVRep>(dict_size)?),
+      )?)),
     }
   }
+
+  // Setup of the synchronous deduplicator, which is also available without std
+  pub fn setup_dedup(&self, dict_size: usize) -> Result<DedupGD> {
+    DedupGD::new(self, dict_size)
+  }
 }
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
-#[derive(Debug, Clone)]
+#[cfg(feature = "std")]
+#[derive(Debug)]
 pub enum GDInner {
   ReedSolomon(ByteGD<ReedSolomon>),
   Hamming(BitGD<Hamming>),
+  Deletion(DeletionGD),
 }
 
+#[cfg(feature = "std")]
 impl GDInner {
   pub fn unit_check(&self) {
     match &self {
       GDInner::Hamming(x) => x.unit_check(),
       GDInner::ReedSolomon(x) => x.unit_check(),
+      GDInner::Deletion(x) => x.unit_check(),
     }
   }
+  // Copy of the instance with its dictionaries, which fails if they cannot be copied, e.g., files of a file-backed one
+  pub fn try_clone(&self) -> Result<Self> {
+    Ok(match self {
+      GDInner::Hamming(x) => GDInner::Hamming(x.try_clone()?),
+      GDInner::ReedSolomon(x) => GDInner::ReedSolomon(x.try_clone()?),
+      GDInner::Deletion(x) => GDInner::Deletion(x.try_clone()?),
+    })
+  }
   // Asynchronous APIs
   // TODO: consider some parallelization only for 'decoding' operation to split chunk into base and deviation.
   // TODO: also consider for 'encoding' as well
@@ -61,6 +182,7 @@ impl GDInner {
     match self {
       GDInner::Hamming(x) => x.dedup(buf).await,
       GDInner::ReedSolomon(x) => x.dedup(buf).await,
+      GDInner::Deletion(x) => x.dedup(buf).await,
     }
   }
 
@@ -68,17 +190,387 @@ impl GDInner {
     match self {
       GDInner::Hamming(x) => x.dup(deduped).await,
       GDInner::ReedSolomon(x) => x.dup(deduped).await,
+      GDInner::Deletion(x) => x.dup(deduped).await,
     }
   }
   pub async fn set_error_alignment(&mut self, trans: &[U8VRep]) -> Result<()> {
     match self {
-      GDInner::Hamming(_) => Err(anyhow!("No such method for Hamming codes")),
+      GDInner::Hamming(x) => x.set_error_alignment(trans).await,
       GDInner::ReedSolomon(x) => x.set_error_alignment(trans).await,
+      GDInner::Deletion(_) => bail!("Error alignment is not available for deletion-based GD"),
+    }
+  }
+  pub fn chunk_bytelen(&self) -> usize {
+    match self {
+      GDInner::Hamming(x) => x.chunk_bytelen,
+      GDInner::ReedSolomon(x) => x.chunk_bytelen,
+      GDInner::Deletion(x) => x.chunk_bytelen,
+    }
+  }
+  // Upper bound of the byte length of deduplicated data of bytelen bytes, which is not given in packet mode
+  pub(crate) fn max_deduped_bytelen(&self, bytelen: usize) -> Result<usize> {
+    match self {
+      GDInner::Hamming(x) => x.max_frame_bytelen(bytelen),
+      GDInner::ReedSolomon(x) => x.max_frame_bytelen(bytelen),
+      GDInner::Deletion(x) => x.max_frame_bytelen(bytelen),
+    }
+  }
+  // Drop all bases in the dictionary. Both dedup and dup instances must be reset at the same position of a stream.
+  pub fn reset_dict(&mut self) {
+    match self {
+      GDInner::Hamming(x) => {
+        x.coder.basis_dict.clear();
+        x.coder.deviation_dict.iter_mut().for_each(|d| d.clear());
+      }
+      GDInner::ReedSolomon(x) => {
+        x.coder.basis_dict.clear();
+        x.coder.deviation_dict.iter_mut().for_each(|d| d.clear());
+      }
+      GDInner::Deletion(x) => x.coder.basis_dict.clear(),
+    }
+  }
+  // Snapshot of the base dictionary, e.g., to resume a stream later or on another node by restore_dict.
+  // Not available with dictionary synchronization, packet mode, shared dictionary and multi-level GD.
+  pub fn dict_snapshot(&self) -> Result<DictSnapshot> {
+    ensure!(
+      !self.is_dict_mode_set(),
+      "Snapshot is not available with another dictionary mode"
+    );
+    match self {
+      GDInner::Hamming(x) => x.coder.basis_dict.snapshot(),
+      GDInner::ReedSolomon(x) => x.coder.basis_dict.snapshot(),
+      GDInner::Deletion(x) => x.coder.basis_dict.snapshot(),
+    }
+  }
+  pub fn restore_dict(&mut self, snapshot: &DictSnapshot) -> Result<()> {
+    ensure!(
+      !self.is_dict_mode_set(),
+      "Snapshot is not available with another dictionary mode"
+    );
+    match self {
+      GDInner::Hamming(x) => x.coder.basis_dict.restore(snapshot),
+      GDInner::ReedSolomon(x) => x.coder.basis_dict.restore(snapshot),
+      GDInner::Deletion(x) => x.coder.basis_dict.restore(snapshot),
+    }
+  }
+  // Enable in-band control codes for dictionary synchronization. This must be set to both dedup and dup instances.
+  // The dedup side resets the dictionary every reset_interval frames (calls of dedup) if given,
+  // and attaches the digest of its dictionary every digest_interval frames.
+  // The dup side detects desynchronization by epochs, frame numbers and digests, and rejects frames until the next reset.
+  pub fn set_dict_sync(
+    &mut self,
+    reset_interval: Option<usize>,
+    digest_interval: usize,
+  ) -> Result<()> {
+    ensure!(
+      !self.is_dict_mode_set(),
+      "Another dictionary mode is already enabled"
+    );
+    ensure!(
+      !self.is_stored_fallback(),
+      "The dictionary mode is not available with stored fallback"
+    );
+    let sync = StreamSync::new(reset_interval, digest_interval)?;
+    match self {
+      GDInner::Hamming(x) => x.coder.sync = Some(sync),
+      GDInner::ReedSolomon(x) => x.coder.sync = Some(sync),
+      GDInner::Deletion(_) => {
+        bail!("Dictionary synchronization is not available for deletion-based GD")
+      }
+    };
+    Ok(())
+  }
+  // Make the dedup side reset the dictionary at the next frame, e.g., when the dup side reported desynchronization.
+  pub fn request_dict_reset(&mut self) -> Result<()> {
+    let sync = match self {
+      GDInner::Hamming(x) => x.coder.sync.as_mut(),
+      GDInner::ReedSolomon(x) => x.coder.sync.as_mut(),
+      GDInner::Deletion(_) => None,
+    };
+    sync
+      .ok_or(anyhow!("Dictionary synchronization is not enabled"))?
+      .request_reset();
+    Ok(())
+  }
+  pub fn is_desynced(&self) -> bool {
+    let sync = match self {
+      GDInner::Hamming(x) => x.coder.sync.as_ref(),
+      GDInner::ReedSolomon(x) => x.coder.sync.as_ref(),
+      GDInner::Deletion(_) => None,
+    };
+    sync.is_some_and(|s| s.is_desynced())
+  }
+  // Enable packet mode, where each deduplicated data (frame) carries a sequence number and may be lost or reordered.
+  // Bases in a frame are referenced by later frames only after the frame is acknowledged by ack.
+  // Frames of seq older than the latest one by window are forgotten and never committed. This must be set to both dedup and dup instances.
+  pub fn set_packet_mode(&mut self, window: usize) -> Result<()> {
+    ensure!(
+      !self.is_dict_mode_set(),
+      "Another dictionary mode is already enabled"
+    );
+    ensure!(
+      !self.is_stored_fallback(),
+      "The dictionary mode is not available with stored fallback"
+    );
+    match self {
+      GDInner::Hamming(x) => {
+        x.coder.packet = Some(PacketDict::new(x.coder.basis_dict.dict_size(), window)?)
+      }
+      GDInner::ReedSolomon(x) => {
+        x.coder.packet = Some(PacketDict::new(x.coder.basis_dict.dict_size(), window)?)
+      }
+      GDInner::Deletion(_) => bail!("Packet mode is not available for deletion-based GD"),
+    };
+    Ok(())
+  }
+  pub fn is_packet_mode(&self) -> bool {
+    match self {
+      GDInner::Hamming(x) => x.coder.packet.is_some(),
+      GDInner::ReedSolomon(x) => x.coder.packet.is_some(),
+      GDInner::Deletion(_) => false,
+    }
+  }
+  // Sequence number of a frame in packet mode, to be acknowledged by the dup side
+  pub fn packet_seq(&self, deduped: &Deduped) -> Result<usize> {
+    ensure!(self.is_packet_mode(), "Packet mode is not enabled");
+    PacketDict::<U8VRep>::packet_seq(&deduped.data)
+  }
+  // Call in the dedup side when the dup side acknowledged the frame of seq
+  pub fn ack(&mut self, seq: usize) -> Result<()> {
+    match self {
+      GDInner::Hamming(x) => x.coder.packet.as_mut().map(|p| p.ack(seq)),
+      GDInner::ReedSolomon(x) => x.coder.packet.as_mut().map(|p| p.ack(seq)),
+      GDInner::Deletion(_) => None,
+    }
+    .ok_or(anyhow!("Packet mode is not enabled"))?
+  }
+  // Call in the dedup side when the dup side reported the loss of the frame of seq
+  pub fn nack(&mut self, seq: usize) -> Result<()> {
+    match self {
+      GDInner::Hamming(x) => x.coder.packet.as_mut().map(|p| p.nack(seq)),
+      GDInner::ReedSolomon(x) => x.coder.packet.as_mut().map(|p| p.nack(seq)),
+      GDInner::Deletion(_) => None,
+    }
+    .ok_or(anyhow!("Packet mode is not enabled"))?
+  }
+  // Deduplication into a content-addressed store for archives, where frames reference bases by their keys in the store.
+  // The dictionary of the instance is not used, and hence frames can be duplicated in any order.
+  pub async fn dedup_into_store(&self, buf: &U8SRep, store: &mut BaseStore) -> Result<Deduped> {
+    match self {
+      GDInner::Hamming(x) => x.dedup_into_store(buf, store).await,
+      GDInner::ReedSolomon(x) => x.dedup_into_store(buf, store).await,
+      GDInner::Deletion(_) => bail!("Base store is not available for deletion-based GD"),
+    }
+  }
+  pub async fn dup_from_store(&self, deduped: &Deduped, store: &BaseStore) -> Result<U8VRep> {
+    match self {
+      GDInner::Hamming(x) => x.dup_from_store(deduped, store).await,
+      GDInner::ReedSolomon(x) => x.dup_from_store(deduped, store).await,
+      GDInner::Deletion(_) => bail!("Base store is not available for deletion-based GD"),
+    }
+  }
+  // Decrement reference counts of bases in a frame. Unreferenced bases are dropped by BaseStore::gc.
+  pub fn release_from_store(&self, deduped: &Deduped, store: &mut BaseStore) -> Result<()> {
+    match self {
+      GDInner::Hamming(x) => x.release_from_store(deduped, store),
+      GDInner::ReedSolomon(x) => x.release_from_store(deduped, store),
+      GDInner::Deletion(_) => bail!("Base store is not available for deletion-based GD"),
+    }
+  }
+  // Use a dictionary shared with other GD instances, e.g., for streams from many devices, instead of its own dictionary.
+  // The dup side must also use a dictionary shared among the corresponding instances, and duplicate frames in the order
+  // in which their deduplication completed. Otherwise, dup fails due to an unknown id and can be retried later.
+  pub fn set_shared_dict(&mut self, dict: Arc<SharedDict>) -> Result<()> {
+    ensure!(
+      !self.is_dict_mode_set(),
+      "Another dictionary mode is already enabled"
+    );
+    ensure!(
+      !self.is_stored_fallback(),
+      "The dictionary mode is not available with stored fallback"
+    );
+    let handle = Some(SharedDictHandle::new(dict));
+    match self {
+      GDInner::Hamming(x) => x.coder.shared = handle,
+      GDInner::ReedSolomon(x) => x.coder.shared = handle,
+      GDInner::Deletion(_) => bail!("Shared dictionary is not available for deletion-based GD"),
+    };
+    Ok(())
+  }
+  // Enable multi-level GD, where deviations are also deduplicated with an LRU dictionary of the given size.
+  // Each chunk is then given as [separator of base][separator of deviation][id or base][id or deviation].
+  // This must be set to both dedup and dup instances.
+  pub fn set_deviation_dict(&mut self, dict_size: usize) -> Result<()> {
+    ensure!(
+      !self.is_dict_mode_set(),
+      "Another dictionary mode is already enabled"
+    );
+    ensure!(dict_size > 0, "Dictionary size must be positive");
+    match self {
+      GDInner::Hamming(x) => x.coder.deviation_dict = Some(BasisDict::new(dict_size)),
+      GDInner::ReedSolomon(x) => x.coder.deviation_dict = Some(BasisDict::new(dict_size)),
+      GDInner::Deletion(_) => bail!("Multi-level GD is not available for deletion-based GD"),
+    };
+    Ok(())
+  }
+  // Use the multi-symbol separator, where each chunk is given as a dictionary hit, a base as is, the same base as the
+  // previous chunk, a run of identical chunks, or a raw literal. This must be set to both dedup and dup instances.
+  pub fn set_multi_symbol_separator(&mut self) -> Result<()> {
+    match self {
+      GDInner::Hamming(x) => x.coder.multi_symbol = true,
+      GDInner::ReedSolomon(x) => x.coder.multi_symbol = true,
+      GDInner::Deletion(_) => {
+        bail!("Multi-symbol separator is not available for deletion-based GD")
+      }
+    };
+    Ok(())
+  }
+  // Enable raw literals for incompressible spans on the dedup side, which requires the multi-symbol separator.
+  // After `threshold` consecutive chunks whose bases are not in the dictionary, such chunks are emitted as raw literals
+  // without being inserted into the dictionary, except every `probe_interval`-th one to detect the end of the span.
+  pub fn set_raw_literal_mode(&mut self, threshold: usize, probe_interval: usize) -> Result<()> {
+    let policy = Some(LiteralPolicy::new(threshold, probe_interval)?);
+    match self {
+      GDInner::Hamming(x) => {
+        ensure!(
+          x.coder.multi_symbol,
+          "Raw literals require the multi-symbol separator"
+        );
+        x.coder.literal = policy;
+      }
+      GDInner::ReedSolomon(x) => {
+        ensure!(
+          x.coder.multi_symbol,
+          "Raw literals require the multi-symbol separator"
+        );
+        x.coder.literal = policy;
+      }
+      GDInner::Deletion(_) => bail!("Raw literal mode is not available for deletion-based GD"),
+    };
+    Ok(())
+  }
+  // Hint that the following input is incompressible, e.g., encrypted, where all chunks not in the dictionary are
+  // emitted as raw literals until the hint is cleared.
+  pub fn set_incompressible_hint(&mut self, incompressible: bool) -> Result<()> {
+    let literal = match self {
+      GDInner::Hamming(x) => x.coder.literal.as_mut(),
+      GDInner::ReedSolomon(x) => x.coder.literal.as_mut(),
+      GDInner::Deletion(_) => None,
+    };
+    literal
+      .ok_or(anyhow!("Raw literal mode is not enabled"))?
+      .set_hint(incompressible);
+    Ok(())
+  }
+  // Fall back to a stored frame carrying the input as is whenever GD does not reduce the size, where every frame is
+  // prefixed with a frame type and hence at most STORED_FALLBACK_MAX_EXPANSION byte larger than its input.
+  // This must be set to both dedup and dup instances, and is not available with dictionary synchronization,
+  // packet mode and shared dictionary.
+  pub fn set_stored_fallback(&mut self) -> Result<()> {
+    let (sync, packet, shared) = match self {
+      GDInner::Hamming(x) => (x.coder.sync.is_some(), x.coder.packet.is_some(), x.coder.shared.is_some()),
+      GDInner::ReedSolomon(x) => (x.coder.sync.is_some(), x.coder.packet.is_some(), x.coder.shared.is_some()),
+      GDInner::Deletion(_) => (false, false, false),
+    };
+    ensure!(
+      !(sync || packet || shared),
+      "Stored fallback is not available with the dictionary mode"
+    );
+    match self {
+      GDInner::Hamming(x) => x.coder.stored_fallback = true,
+      GDInner::ReedSolomon(x) => x.coder.stored_fallback = true,
+      GDInner::Deletion(x) => x.coder.stored_fallback = true,
+    };
+    Ok(())
+  }
+  pub fn is_stored_fallback(&self) -> bool {
+    match self {
+      GDInner::Hamming(x) => x.coder.stored_fallback,
+      GDInner::ReedSolomon(x) => x.coder.stored_fallback,
+      GDInner::Deletion(x) => x.coder.stored_fallback,
+    }
+  }
+  // Count distinct deviations of each frame in the statistics, which are 0 unless enabled, e.g., by AdaptiveGD
+  pub fn set_deviation_stats(&mut self, enabled: bool) {
+    match self {
+      GDInner::Hamming(x) => x.coder.deviation_stats = enabled,
+      GDInner::ReedSolomon(x) => x.coder.deviation_stats = enabled,
+      GDInner::Deletion(x) => x.coder.deviation_stats = enabled,
+    }
+  }
+  // Statistics of the last frame deduplicated by dedup
+  pub fn last_stats(&self) -> &FrameStats {
+    match self {
+      GDInner::Hamming(x) => &x.coder.last_stats,
+      GDInner::ReedSolomon(x) => &x.coder.last_stats,
+      GDInner::Deletion(x) => &x.coder.last_stats,
+    }
+  }
+  pub(crate) fn begin_journal(&mut self) -> Result<Option<LiteralPolicy>> {
+    match self {
+      GDInner::Hamming(x) => x.coder.begin_journal(),
+      GDInner::ReedSolomon(x) => x.coder.begin_journal(),
+      GDInner::Deletion(x) => x.coder.begin_journal(),
+    }
+  }
+  pub(crate) fn end_journal(
+    &mut self,
+    rollback: bool,
+    literal: Option<LiteralPolicy>,
+  ) -> Result<()> {
+    match self {
+      GDInner::Hamming(x) => x.coder.end_journal(rollback, literal),
+      GDInner::ReedSolomon(x) => x.coder.end_journal(rollback, literal),
+      GDInner::Deletion(x) => x.coder.end_journal(rollback, literal),
+    }
+  }
+  // Treat the input as a pure bitstream in Hamming-based GD, where each chunk takes exactly the code length of bits
+  // across byte boundaries instead of bytes padded with zero bits, and the padding of the last chunk is recorded in bits.
+  // This must be set to both dedup and dup instances.
+  pub fn set_bitstream_chunking(&mut self) -> Result<()> {
+    match self {
+      GDInner::Hamming(x) => x.bitstream = true,
+      GDInner::ReedSolomon(_) => bail!("Bitstream chunking is available only for Hamming-based GD"),
+      GDInner::Deletion(_) => bail!("Bitstream chunking is available only for Hamming-based GD"),
+    };
+    Ok(())
+  }
+  // Whether the dictionary depends on states that reset_dict does not drop, i.e., dictionary synchronization, packet
+  // mode or a shared dictionary, with which blocks deduplicated after resets are not independent of each other
+  pub(crate) fn is_dict_kept_across_resets(&self) -> bool {
+    match self {
+      GDInner::Hamming(x) => x.coder.sync.is_some() || x.coder.packet.is_some() || x.coder.shared.is_some(),
+      GDInner::ReedSolomon(x) => x.coder.sync.is_some() || x.coder.packet.is_some() || x.coder.shared.is_some(),
+      GDInner::Deletion(_) => false,
+    }
+  }
+  // Dictionary synchronization, packet mode, shared dictionary and multi-level GD are exclusive
+  fn is_dict_mode_set(&self) -> bool {
+    match self {
+      GDInner::Hamming(x) => self.is_dict_kept_across_resets() || x.coder.deviation_dict.is_some(),
+      GDInner::ReedSolomon(x) => self.is_dict_kept_across_resets() || x.coder.deviation_dict.is_some(),
+      GDInner::Deletion(_) => false,
     }
   }
+  // Enable content-defined pre-chunking, where segment lengths are in bytes. This must be set to both dedup and dup instances.
+  pub fn set_content_defined_chunking(
+    &mut self,
+    min_bytelen: usize,
+    avg_bytelen: usize,
+    max_bytelen: usize,
+  ) -> Result<()> {
+    let chunker = ContentDefinedChunker::new(min_bytelen, avg_bytelen, max_bytelen)?;
+    match self {
+      GDInner::Hamming(x) => x.coder.chunker = Some(chunker),
+      GDInner::ReedSolomon(x) => x.coder.chunker = Some(chunker),
+      GDInner::Deletion(x) => x.coder.chunker = Some(chunker),
+    };
+    Ok(())
+  }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(feature = "std")]
 #[async_trait]
 pub trait GDTrait {
   fn unit_check(&self);
@@ -88,13 +580,14 @@ pub trait GDTrait {
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Deduped {
   pub data: U8VRep,
   pub last_chunk_pad_bytelen: usize,
 }
 /////////////////////////////////////////
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
   use super::*;
   use rand::Rng;
@@ -219,6 +712,10 @@ mod tests {
     assert!(res_dedup.is_ok());
     assert!(res_dup.is_ok());
 
+    let mut invalid = trans.clone();
+    invalid[1] = vec![1u8, 1, 1];
+    assert!(gd_dedup.set_error_alignment(&invalid).await.is_err());
+
     let words = WORD_STR.to_string().into_bytes().repeat(RS_REPEAT);
 
     // println!("RS code ({}, {}) over GF(256)", code_len, msg_len);
@@ -237,4 +734,713 @@ mod tests {
       100.0 * (x.data.len() as f32) / (y.len() as f32)
     );
   }
+
+  #[tokio::test]
+  async fn hamming_align_error_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    let hamming_deg = 5;
+    let dict_size = 7;
+    // 3-byte sensor readings whose LSB of the first byte fluctuates.
+    // The last byte is chosen so that every aligned chunk is a codeword whose parity absorbs the noise.
+    let words: Vec<u8> = [0x12u8, 0x34, 0x5d]
+      .repeat(RS_REPEAT)
+      .into_iter()
+      .enumerate()
+      .map(|(i, b)| {
+        let noise: u8 = if i % 3 == 0 {
+          rng.gen::<u8>() & 0x01
+        } else {
+          0
+        };
+        b ^ noise
+      })
+      .collect();
+
+    // pad bits (7 bits) come first in each 31-bit chunk followed by 24-bit data, and then move the LSB to a parity bit
+    let code_len = 31;
+    let (lsb_pos, parity_pos) = (14, 26);
+    let trans: Vec<U8VRep> = (0..code_len)
+      .map(|row| {
+        let col = match row {
+          r if r == lsb_pos => parity_pos,
+          r if r == parity_pos => lsb_pos,
+          r => r,
+        };
+        let mut v = vec![0u8; code_len];
+        v[col] = 1;
+        v
+      })
+      .collect();
+
+    let mut gd_plain = GD::Hamming(hamming_deg).setup(dict_size).await.unwrap();
+    let mut gd_dedup = GD::Hamming(hamming_deg).setup(dict_size).await.unwrap();
+    let mut gd_dup = GD::Hamming(hamming_deg).setup(dict_size).await.unwrap();
+    assert!(gd_dedup.set_error_alignment(&trans).await.is_ok());
+    assert!(gd_dup.set_error_alignment(&trans).await.is_ok());
+    // aligned chunks share a single base, which is then written as SameBase
+    for gd in [&mut gd_plain, &mut gd_dedup, &mut gd_dup] {
+      gd.set_multi_symbol_separator().unwrap();
+    }
+
+    let x_plain = gd_plain.dedup(&words).await.unwrap();
+    let x = gd_dedup.dedup(&words).await.unwrap();
+    let y = gd_dup.dup(&x).await.unwrap();
+    assert_eq!(y, words);
+    println!(
+      "Hamming code deg = {} > Deduped rate: {:.2} % (w/o alignment: {:.2} %)",
+      hamming_deg,
+      100.0 * (x.data.len() as f32) / (y.len() as f32),
+      100.0 * (x_plain.data.len() as f32) / (y.len() as f32),
+    );
+    assert!(x.data.len() < x_plain.data.len());
+  }
+
+  #[tokio::test]
+  async fn cdc_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    let words: Vec<u8> = (0..8192).map(|_| rng.gen()).collect();
+    // a byte is inserted at the beginning
+    let mut shifted = vec![0xAAu8];
+    shifted.extend_from_slice(&words);
+
+    for gd in [GD::ReedSolomon(8, 7), GD::Hamming(6)] {
+      let mut deduped_sizes = vec![];
+      for cdc in [false, true] {
+        let mut gd_dedup = gd.setup(2047).await.unwrap();
+        let mut gd_dup = gd.setup(2047).await.unwrap();
+        if cdc {
+          gd_dedup
+            .set_content_defined_chunking(64, 256, 1024)
+            .unwrap();
+          gd_dup.set_content_defined_chunking(64, 256, 1024).unwrap();
+        }
+        let x = gd_dedup.dedup(&words).await.unwrap();
+        assert_eq!(gd_dup.dup(&x).await.unwrap(), words);
+        let x = gd_dedup.dedup(&shifted).await.unwrap();
+        assert_eq!(gd_dup.dup(&x).await.unwrap(), shifted);
+        deduped_sizes.push(x.data.len());
+      }
+      println!(
+        "{:?} > Deduped size of shifted data: {} bytes (w/o CDC: {} bytes)",
+        gd, deduped_sizes[1], deduped_sizes[0]
+      );
+      assert!(deduped_sizes[1] < deduped_sizes[0]);
+    }
+  }
+
+  #[tokio::test]
+  async fn dict_sync_works() {
+    let words = WORD_STR.to_string().repeat(16).into_bytes();
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let mut gd_dedup = gd.setup(15).await.unwrap();
+      let mut gd_dup = gd.setup(15).await.unwrap();
+      gd_dedup.set_dict_sync(Some(8), 2).unwrap();
+      gd_dup.set_dict_sync(None, 2).unwrap();
+
+      let frames = words.chunks(100).take(12).collect::<Vec<_>>();
+      assert_eq!(frames.len(), 12);
+      let mut lost_or_rejected = 0;
+      for (i, frame) in frames.iter().enumerate() {
+        let x = gd_dedup.dedup(frame).await.unwrap();
+        if i == 3 {
+          // lost frame
+          continue;
+        }
+        match gd_dup.dup(&x).await {
+          Ok(y) => assert_eq!(y, *frame),
+          Err(_) => {
+            assert!(gd_dup.is_desynced());
+            lost_or_rejected += 1;
+          }
+        }
+      }
+      // frames from 4 to 7 are rejected and the stream recovers at the reset in frame 8
+      assert_eq!(lost_or_rejected, 4);
+      assert!(!gd_dup.is_desynced());
+    }
+  }
+
+  #[tokio::test]
+  async fn packet_mode_works() {
+    let words = WORD_STR.to_string().repeat(16).into_bytes();
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let mut gd_dedup = gd.setup(63).await.unwrap();
+      let mut gd_dup = gd.setup(63).await.unwrap();
+      gd_dedup.set_packet_mode(16).unwrap();
+      gd_dup.set_packet_mode(16).unwrap();
+
+      let frames = words.chunks(150).take(12).collect::<Vec<_>>();
+      let mut sent = frames
+        .iter()
+        .map(|frame| (*frame, None))
+        .collect::<Vec<(&[u8], Option<Deduped>)>>();
+      let mut total_size = 0;
+      // frames are sent two by two, where the former is delivered after the latter, and frame 3 is lost
+      for i in (0..sent.len()).step_by(2) {
+        for j in [i, i + 1] {
+          let x = gd_dedup.dedup(sent[j].0).await.unwrap();
+          total_size += x.data.len();
+          sent[j].1 = Some(x);
+        }
+        for j in [i + 1, i] {
+          let x = sent[j].1.as_ref().unwrap();
+          let seq = gd_dup.packet_seq(x).unwrap();
+          assert_eq!(seq, j);
+          if j == 3 {
+            gd_dedup.nack(seq).unwrap();
+            continue;
+          }
+          assert_eq!(gd_dup.dup(x).await.unwrap(), sent[j].0);
+          gd_dedup.ack(seq).unwrap();
+        }
+      }
+      println!(
+        "{:?} > Packet mode: {} bytes -> {} bytes",
+        gd,
+        frames.iter().map(|f| f.len()).sum::<usize>(),
+        total_size
+      );
+      if let GD::ReedSolomon(_, _) = gd {
+        assert!(total_size < frames.iter().map(|f| f.len()).sum());
+      }
+    }
+  }
+  #[tokio::test]
+  async fn shared_dict_works() {
+    let words = WORD_STR.to_string().repeat(8).into_bytes();
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let dedup_dict = Arc::new(SharedDict::new(1023, 16).unwrap());
+      let dup_dict = Arc::new(SharedDict::new(1023, 16).unwrap());
+
+      // streams of 4 devices with the same content are deduplicated concurrently
+      let handles = (0..4)
+        .map(|_| {
+          let gd = gd.clone();
+          let dict = dedup_dict.clone();
+          let words = words.clone();
+          tokio::spawn(async move {
+            let mut gd_dedup = gd.setup(1023).await.unwrap();
+            gd_dedup.set_shared_dict(dict).unwrap();
+            gd_dedup.dedup(&words).await.unwrap()
+          })
+        })
+        .collect::<Vec<_>>();
+      let mut deduped = vec![];
+      for handle in handles {
+        deduped.push(handle.await.unwrap());
+      }
+
+      // frames referring to bases in frames not yet duplicated are retried
+      let mut gd_dup = gd.setup(1023).await.unwrap();
+      gd_dup.set_shared_dict(dup_dict.clone()).unwrap();
+      let mut remaining = deduped.clone();
+      for _ in 0..deduped.len() {
+        let mut failed = vec![];
+        for x in remaining {
+          match gd_dup.dup(&x).await {
+            Ok(y) => assert_eq!(y, words),
+            Err(_) => failed.push(x),
+          }
+        }
+        remaining = failed;
+      }
+      assert!(remaining.is_empty());
+
+      let total_size: usize = deduped.iter().map(|x| x.data.len()).sum();
+      let mut gd_single = gd.setup(1023).await.unwrap();
+      let single_size = gd_single.dedup(&words).await.unwrap().data.len();
+      println!(
+        "{:?} > Shared dictionary: 4 streams in {} bytes (a single stream in {} bytes)",
+        gd, total_size, single_size
+      );
+      assert!(total_size < single_size * 4);
+      assert!(gd_dup.set_packet_mode(4).is_err());
+    }
+  }
+
+  #[tokio::test]
+  async fn base_store_works() {
+    let words = WORD_STR.to_string().repeat(8).into_bytes();
+    let mut rng = rand::thread_rng();
+    let noisy = words
+      .iter()
+      .map(|b| if rng.gen_bool(0.1) { b ^ 0x01 } else { *b })
+      .collect::<Vec<u8>>();
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let gd_inner = gd.setup(1).await.unwrap();
+      let mut store = BaseStore::new();
+      let archive_a = gd_inner.dedup_into_store(&words, &mut store).await.unwrap();
+      let base_num = store.len();
+      let archive_b = gd_inner.dedup_into_store(&noisy, &mut store).await.unwrap();
+      println!(
+        "{:?} > Base store: {} bases for archives of {} and {} bytes",
+        gd,
+        store.len(),
+        archive_a.data.len(),
+        archive_b.data.len()
+      );
+      assert_eq!(
+        gd_inner.dup_from_store(&archive_b, &store).await.unwrap(),
+        noisy
+      );
+
+      // delete archive a
+      gd_inner.release_from_store(&archive_a, &mut store).unwrap();
+      store.gc();
+      assert!(store.len() < base_num * 2);
+      assert_eq!(
+        gd_inner.dup_from_store(&archive_b, &store).await.unwrap(),
+        noisy
+      );
+      gd_inner.release_from_store(&archive_b, &mut store).unwrap();
+      store.gc();
+      assert!(store.is_empty());
+    }
+  }
+
+  #[tokio::test]
+  async fn file_dict_works() {
+    let words = WORD_STR.to_string().repeat(32).into_bytes();
+    for (i, gd) in [GD::ReedSolomon(10, 8), GD::Hamming(7)].iter().enumerate() {
+      let dir =
+        std::env::temp_dir().join(format!("rust-gd-file-dict-{}-{}", std::process::id(), i));
+      let mut gd_memory = gd.setup(63).await.unwrap();
+      let mut gd_dedup = gd
+        .setup_with_storage(63, DictStorage::File(dir.join("dedup")))
+        .await
+        .unwrap();
+      let mut gd_dup = gd
+        .setup_with_storage(63, DictStorage::File(dir.join("dup")))
+        .await
+        .unwrap();
+
+      // small dictionary to cause evictions, where the output is identical to that of the in-memory dictionary
+      for chunk in words.chunks(500) {
+        let x = gd_dedup.dedup(chunk).await.unwrap();
+        assert_eq!(x.data, gd_memory.dedup(chunk).await.unwrap().data);
+        assert_eq!(gd_dup.dup(&x).await.unwrap(), chunk);
+      }
+
+      // a clone continues from the same dictionary, copied into a new directory for files
+      let mut gd_dedup_clone = gd_dedup.try_clone().unwrap();
+      let x = gd_dedup.dedup(&words).await.unwrap();
+      assert_eq!(gd_dedup_clone.dedup(&words).await.unwrap().data, x.data);
+      assert_eq!(gd_memory.try_clone().unwrap().dedup(&words).await.unwrap().data, x.data);
+      assert!(dir.join("dedup.1").is_dir());
+
+      // files are removed on drop together with directories created for them
+      drop((gd_dedup, gd_dedup_clone, gd_dup));
+      assert!(!dir.join("dedup").exists() && !dir.join("dedup.1").exists());
+      std::fs::remove_dir_all(dir).unwrap();
+    }
+  }
+
+  #[tokio::test]
+  async fn deviation_dict_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    for gd in [GD::ReedSolomon(4, 3), GD::Hamming(5)] {
+      // telemetry-like stream of recurring chunks, where deviations recur as well as bases
+      let chunk_bytelen = gd.setup(1).await.unwrap().chunk_bytelen();
+      let pool: Vec<Vec<u8>> = (0..2)
+        .map(|_| (0..chunk_bytelen).map(|_| rng.gen()).collect())
+        .collect();
+      let words: Vec<u8> = (0..1024)
+        .flat_map(|_| pool[rng.gen_range(0..pool.len())].clone())
+        .collect();
+
+      let mut deduped_sizes = vec![];
+      for multi_level in [false, true] {
+        let mut gd_dedup = gd.setup(255).await.unwrap();
+        let mut gd_dup = gd.setup(255).await.unwrap();
+        if multi_level {
+          gd_dedup.set_deviation_dict(3).unwrap();
+          gd_dup.set_deviation_dict(3).unwrap();
+          assert!(gd_dup.set_packet_mode(8).is_err());
+        }
+        let x = gd_dedup.dedup(&words).await.unwrap();
+        assert_eq!(gd_dup.dup(&x).await.unwrap(), words);
+        deduped_sizes.push(x.data.len());
+      }
+      println!(
+        "{:?} > Deduped size with deviation dictionary: {} bytes (w/o: {} bytes)",
+        gd, deduped_sizes[1], deduped_sizes[0]
+      );
+      assert!(deduped_sizes[1] < deduped_sizes[0]);
+    }
+  }
+
+  #[tokio::test]
+  async fn short_token_works() {
+    // Deduped tokens shorter than 8 bits, which may end at a byte boundary without pads
+    for (gd, dict_size, deviation_dict_size) in [
+      (GD::ReedSolomon(10, 8), 15, Some(1)),
+      (GD::Hamming(5), 15, Some(1)),
+      (GD::Hamming(4), 1, None),
+      (GD::Hamming(4), 3, None),
+    ] {
+      let chunk_bytelen = gd.setup(1).await.unwrap().chunk_bytelen();
+      let words = b"0123456789".repeat(3 * chunk_bytelen);
+      for len in 1..=24 * chunk_bytelen {
+        let mut gd_dedup = gd.setup(dict_size).await.unwrap();
+        let mut gd_dup = gd.setup(dict_size).await.unwrap();
+        if let Some(size) = deviation_dict_size {
+          gd_dedup.set_deviation_dict(size).unwrap();
+          gd_dup.set_deviation_dict(size).unwrap();
+        }
+        let x = gd_dedup.dedup(&words[..len]).await.unwrap();
+        assert_eq!(
+          gd_dup.dup(&x).await.unwrap(),
+          words[..len],
+          "{:?} of {} bytes",
+          gd,
+          len
+        );
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn multi_symbol_separator_works() {
+    // long runs of identical records interleaved with text
+    let mut words = vec![];
+    for (i, b) in WORD_STR.as_bytes().chunks(16).enumerate() {
+      words.extend_from_slice(b);
+      words.extend_from_slice(&[i as u8; 480]);
+    }
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      for multi_level in [false, true] {
+        let mut deduped_sizes = vec![];
+        for multi_symbol in [false, true] {
+          let mut gd_dedup = gd.setup(255).await.unwrap();
+          let mut gd_dup = gd.setup(255).await.unwrap();
+          if multi_level {
+            gd_dedup.set_deviation_dict(15).unwrap();
+            gd_dup.set_deviation_dict(15).unwrap();
+          }
+          if multi_symbol {
+            gd_dedup.set_multi_symbol_separator().unwrap();
+            gd_dup.set_multi_symbol_separator().unwrap();
+          }
+          let x = gd_dedup.dedup(&words).await.unwrap();
+          assert_eq!(gd_dup.dup(&x).await.unwrap(), words);
+          // frames of a single chunk and of a run only
+          for buf in [&words[..1], &words[16..200]] {
+            let x = gd_dedup.dedup(buf).await.unwrap();
+            assert_eq!(gd_dup.dup(&x).await.unwrap(), buf);
+          }
+          deduped_sizes.push(x.data.len());
+        }
+        println!(
+          "{:?} > Deduped size with multi-symbol separator: {} bytes (w/o: {} bytes) of {} bytes",
+          gd,
+          deduped_sizes[1],
+          deduped_sizes[0],
+          words.len()
+        );
+        assert!(deduped_sizes[1] < deduped_sizes[0]);
+      }
+    }
+
+    // a run beyond the limit of a frame is rejected without decoding it, where AsIs of a single chunk is followed by it
+    for (gd, token_bitlen) in [(GD::ReedSolomon(10, 8), 2 + 80), (GD::Hamming(4), 2 + 15)] {
+      let mut gd_dedup = gd.setup(255).await.unwrap();
+      let mut gd_dup = gd.setup(255).await.unwrap();
+      gd_dedup.set_multi_symbol_separator().unwrap();
+      gd_dup.set_multi_symbol_separator().unwrap();
+      let x = gd_dedup
+        .dedup(&words[..gd_dedup.chunk_bytelen()])
+        .await
+        .unwrap();
+      let mut bits = BVRep::from_slice(&x.data);
+      bits.truncate(token_bitlen);
+      bits.extend_from_bitslice(&separator::Separator::Run.multi_symbol_bv());
+      util::put_gamma(&mut bits, separator::MAX_RUN_CHUNKS + 1);
+      bits.force_align();
+      bits.set_uninitialized(false);
+      let broken = Deduped {
+        data: bits.into_vec(),
+        last_chunk_pad_bytelen: 0,
+      };
+      assert!(gd_dup.dup(&broken).await.is_err());
+    }
+  }
+
+  #[tokio::test]
+  async fn raw_literal_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    let words = WORD_STR.to_string().repeat(4).into_bytes();
+    let random: Vec<u8> = (0..8000).map(|_| rng.gen()).collect();
+
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      let mut deduped_sizes = vec![];
+      for literal in [false, true] {
+        let mut gd_dedup = gd.setup(127).await.unwrap();
+        let mut gd_dup = gd.setup(127).await.unwrap();
+        gd_dedup.set_multi_symbol_separator().unwrap();
+        gd_dup.set_multi_symbol_separator().unwrap();
+        if literal {
+          gd_dedup.set_raw_literal_mode(8, 16).unwrap();
+        }
+        // text, random region that evicts bases of text from the dictionary w/o raw literals, and text again
+        let mut sizes = vec![];
+        for buf in [&words, &random, &words] {
+          let x = gd_dedup.dedup(buf).await.unwrap();
+          assert_eq!(&gd_dup.dup(&x).await.unwrap(), buf);
+          sizes.push(x.data.len());
+        }
+        if literal && matches!(gd, GD::ReedSolomon(..)) {
+          // 4 bits of separator per chunk of 10 bytes at most
+          assert!(sizes[1] <= random.len() * 21 / 20 + 1);
+        }
+        deduped_sizes.push(sizes[2]);
+      }
+      println!(
+        "{:?} > Deduped size of text after random data with raw literals: {} bytes (w/o: {} bytes)",
+        gd, deduped_sizes[1], deduped_sizes[0]
+      );
+      assert!(deduped_sizes[1] < deduped_sizes[0]);
+    }
+
+    // caller hint
+    let mut gd_dedup = GD::ReedSolomon(10, 8).setup(127).await.unwrap();
+    let mut gd_dup = GD::ReedSolomon(10, 8).setup(127).await.unwrap();
+    assert!(gd_dedup.set_raw_literal_mode(8, 16).is_err());
+    gd_dedup.set_multi_symbol_separator().unwrap();
+    gd_dup.set_multi_symbol_separator().unwrap();
+    assert!(gd_dedup.set_incompressible_hint(true).is_err());
+    gd_dedup.set_raw_literal_mode(usize::MAX, 1).unwrap();
+    gd_dedup.set_incompressible_hint(true).unwrap();
+    let x = gd_dedup.dedup(&random).await.unwrap();
+    assert_eq!(gd_dup.dup(&x).await.unwrap(), random);
+    assert!(x.data.len() <= random.len() * 21 / 20 + 1);
+  }
+
+  #[tokio::test]
+  async fn stored_fallback_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    let words = WORD_STR.to_string().repeat(32).into_bytes();
+    let random: Vec<u8> = (0..8000).map(|_| rng.gen()).collect();
+
+    for gd in [GD::ReedSolomon(10, 8), GD::Hamming(7)] {
+      for deviation_dict in [false, true] {
+        let mut gd_dedup = gd.setup(127).await.unwrap();
+        let mut gd_dup = gd.setup(127).await.unwrap();
+        gd_dedup.set_stored_fallback().unwrap();
+        gd_dup.set_stored_fallback().unwrap();
+        if deviation_dict {
+          gd_dedup.set_deviation_dict(15).unwrap();
+          gd_dup.set_deviation_dict(15).unwrap();
+        }
+        // stored frames of random data must not evict bases of text from the dictionary on both sides
+        for (i, buf) in [&words, &random, &words, &random[..1], &words]
+          .iter()
+          .enumerate()
+        {
+          let x = gd_dedup.dedup(buf).await.unwrap();
+          assert!(x.data.len() <= buf.len() + STORED_FALLBACK_MAX_EXPANSION);
+          assert_eq!(&gd_dup.dup(&x).await.unwrap(), buf);
+          if i > 0 && **buf == words {
+            assert!(x.data.len() < words.len() / 2);
+          }
+        }
+      }
+    }
+
+    let mut gd_dedup = GD::ReedSolomon(10, 8).setup(127).await.unwrap();
+    gd_dedup.set_stored_fallback().unwrap();
+    assert!(gd_dedup.set_dict_sync(None, 1).is_err());
+    assert!(gd_dedup.set_packet_mode(4).is_err());
+  }
+
+  #[tokio::test]
+  async fn deletion_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    let gd = GD::Deletion(8, 8, 42);
+    let mut gd_dedup = gd.setup(255).await.unwrap();
+    let mut gd_dup = gd.setup(255).await.unwrap();
+
+    // chunks drawn from a pool of 4 and randomly flipped only at deleted positions
+    let mask = match &gd_dedup {
+      GDInner::Deletion(x) => x.deletion_mask.clone(),
+      _ => unreachable!(),
+    };
+    let pool: Vec<U8VRep> = (0..4)
+      .map(|_| (0..8).map(|_| rng.gen()).collect())
+      .collect();
+    let noisy: U8VRep = (0..1024)
+      .flat_map(|_| {
+        let mut chunk = BVRep::from_slice(&pool[rng.gen_range(0..4)]);
+        for pos in mask.iter_ones() {
+          let bit = chunk[pos] ^ rng.gen::<bool>();
+          chunk.set(pos, bit);
+        }
+        chunk.into_vec()
+      })
+      .collect();
+    let x = gd_dedup.dedup(&noisy).await.unwrap();
+    assert_eq!(gd_dup.dup(&x).await.unwrap(), noisy);
+    println!(
+      "Deletion > Deduped rate: {:.2} %",
+      100.0 * (x.data.len() as f32) / (noisy.len() as f32)
+    );
+    // 1-bit separator, 8-bit id and 8 deleted bits per 64-bit chunk
+    assert!(x.data.len() < noisy.len() * 3 / 10);
+    assert!(gd_dedup.last_stats().hit_rate() > 0.99);
+
+    // text of a length not aligned to chunks, with content-defined chunking
+    let words = WORD_STR.to_string().repeat(16).into_bytes();
+    for cdc in [false, true] {
+      let mut gd_dedup = GD::Deletion(5, 3, 7).setup(127).await.unwrap();
+      let mut gd_dup = GD::Deletion(5, 3, 7).setup(127).await.unwrap();
+      if cdc {
+        gd_dedup.set_content_defined_chunking(16, 64, 256).unwrap();
+        gd_dup.set_content_defined_chunking(16, 64, 256).unwrap();
+      }
+      let x = gd_dedup.dedup(&words[1..]).await.unwrap();
+      assert_eq!(gd_dup.dup(&x).await.unwrap(), words[1..]);
+    }
+
+    // Deduped tokens shorter than 8 bits, which may end at a byte boundary without pads
+    for (gd, dict_size) in [(GD::Deletion(1, 2, 7), 15), (GD::Deletion(2, 3, 1), 1)] {
+      let chunk_bytelen = gd.setup(1).await.unwrap().chunk_bytelen();
+      let words = b"0123456789".repeat(3 * chunk_bytelen);
+      for len in 1..=24 * chunk_bytelen {
+        let mut gd_dedup = gd.setup(dict_size).await.unwrap();
+        let mut gd_dup = gd.setup(dict_size).await.unwrap();
+        let x = gd_dedup.dedup(&words[..len]).await.unwrap();
+        assert_eq!(gd_dup.dup(&x).await.unwrap(), words[..len], "{} bytes", len);
+      }
+    }
+
+    assert!(gd_dedup.set_packet_mode(4).is_err());
+    assert!(gd_dedup.set_multi_symbol_separator().is_err());
+    assert!(GD::Deletion(4, 32, 0).setup(255).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn bitstream_works() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    // 127-bit pattern repeated across byte boundaries
+    let pattern: BVRep = (0..127).map(|_| rng.gen::<bool>()).collect();
+    let mut bits = BVRep::new();
+    (0..64).for_each(|_| bits.extend_from_bitslice(&pattern));
+    bits.truncate(bits.len() / 8 * 8);
+    let words = bits.into_vec();
+
+    let mut deduped_sizes = vec![];
+    for bitstream in [false, true] {
+      let mut gd_dedup = GD::Hamming(7).setup(127).await.unwrap();
+      let mut gd_dup = GD::Hamming(7).setup(127).await.unwrap();
+      if bitstream {
+        gd_dedup.set_bitstream_chunking().unwrap();
+        gd_dup.set_bitstream_chunking().unwrap();
+      }
+      let x = gd_dedup.dedup(&words).await.unwrap();
+      assert_eq!(gd_dup.dup(&x).await.unwrap(), words);
+      deduped_sizes.push(x.data.len());
+      // lengths not aligned to chunks
+      for len in [0, 1, 15, 16, 17, 100] {
+        let x = gd_dedup.dedup(&words[..len]).await.unwrap();
+        assert_eq!(gd_dup.dup(&x).await.unwrap(), words[..len]);
+      }
+    }
+    println!(
+      "Hamming(7) > Deduped size of 127-bit periodic data in bitstream: {} bytes (w/o: {} bytes)",
+      deduped_sizes[1], deduped_sizes[0]
+    );
+    assert!(deduped_sizes[1] < deduped_sizes[0] / 4);
+
+    // content-defined chunking and base store
+    let mut gd_dedup = GD::Hamming(5).setup(127).await.unwrap();
+    let mut gd_dup = GD::Hamming(5).setup(127).await.unwrap();
+    for gd in [&mut gd_dedup, &mut gd_dup] {
+      gd.set_bitstream_chunking().unwrap();
+      gd.set_content_defined_chunking(16, 64, 256).unwrap();
+    }
+    let x = gd_dedup.dedup(&words[1..]).await.unwrap();
+    assert_eq!(gd_dup.dup(&x).await.unwrap(), words[1..]);
+    let mut store = BaseStore::new();
+    let x = gd_dedup
+      .dedup_into_store(&words[1..], &mut store)
+      .await
+      .unwrap();
+    assert_eq!(gd_dup.dup_from_store(&x, &store).await.unwrap(), words[1..]);
+
+    let mut gd_rs = GD::ReedSolomon(10, 8).setup(127).await.unwrap();
+    assert!(gd_rs.set_bitstream_chunking().is_err());
+  }
+
+  #[tokio::test]
+  async fn dict_snapshot_works() {
+    let words = WORD_STR.to_string().repeat(8).into_bytes();
+    let (first, second) = words.split_at(words.len() / 2);
+    for gd in [
+      GD::ReedSolomon(10, 8),
+      GD::Hamming(7),
+      GD::Deletion(8, 6, 1),
+    ] {
+      let mut gd_dedup = gd.setup(255).await.unwrap();
+      let mut gd_dup = gd.setup(255).await.unwrap();
+      let x = gd_dedup.dedup(first).await.unwrap();
+      assert_eq!(gd_dup.dup(&x).await.unwrap(), first);
+
+      // both sides resume from snapshots in fresh instances
+      let mut gd_dedup_resumed = gd.setup(255).await.unwrap();
+      let mut gd_dup_resumed = gd.setup(255).await.unwrap();
+      let snapshot = gd_dedup.dict_snapshot().unwrap();
+      assert!(!snapshot.entries.is_empty());
+      gd_dedup_resumed.restore_dict(&snapshot).unwrap();
+      gd_dup_resumed
+        .restore_dict(&gd_dup.dict_snapshot().unwrap())
+        .unwrap();
+      let resumed = gd_dedup_resumed.dedup(second).await.unwrap();
+      assert_eq!(resumed.data, gd_dedup.dedup(second).await.unwrap().data);
+      assert_eq!(gd_dup_resumed.dup(&resumed).await.unwrap(), second);
+
+      let mut invalid = snapshot.clone();
+      invalid.entries[0].0 = 255;
+      assert!(gd_dedup_resumed.restore_dict(&invalid).is_err());
+      assert!(gd_dedup_resumed.dict_snapshot().unwrap().entries.is_empty());
+      let mut gd_small = gd.setup(127).await.unwrap();
+      assert!(gd_small.restore_dict(&snapshot).is_err());
+    }
+  }
+
+  #[cfg(feature = "serde")]
+  #[tokio::test]
+  async fn serde_works() {
+    let gds = vec![
+      GD::ReedSolomon(10, 8),
+      GD::Hamming(7),
+      GD::Deletion(8, 6, 1),
+    ];
+    let json = serde_json::to_string(&gds).unwrap();
+    let loaded: Vec<GD> = serde_json::from_str(&json).unwrap();
+    assert_eq!(format!("{:?}", loaded), format!("{:?}", gds));
+
+    let words = WORD_STR.to_string().repeat(4).into_bytes();
+    let mut gd_dedup = GD::ReedSolomon(10, 8).setup(255).await.unwrap();
+    let mut gd_dup = GD::ReedSolomon(10, 8).setup(255).await.unwrap();
+    let x = gd_dedup.dedup(&words).await.unwrap();
+    let loaded: Deduped = serde_json::from_str(&serde_json::to_string(&x).unwrap()).unwrap();
+    assert_eq!(gd_dup.dup(&loaded).await.unwrap(), words);
+
+    let mut dict = BasisDict::<BVRep>::new(15);
+    for i in 0..20u8 {
+      dict.put_base(&BVRep::from_slice(&[i, i])).unwrap();
+    }
+    let json = serde_json::to_string(&dict).unwrap();
+    let loaded: BasisDict<BVRep> = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.digest(), dict.digest());
+    assert!(loaded.check_inner_integrity().is_ok());
+    let duplicated = r#"{"dict_size":15,"entries":[[0,[16,1,1]],[1,[16,1,1]]]}"#;
+    assert!(serde_json::from_str::<BasisDict<BVRep>>(duplicated).is_err());
+  }
 }
@@ -1,33 +1,48 @@
 mod constant;
 mod util;
 
-use super::{error::*, types::*, BitUnitCode, Code, Decoded, Encoded};
+use super::{error::*, math::*, types::*, BitUnitCode, Code, Decoded, Encoded};
+use alloc::vec::Vec;
 use bitvec::prelude::*;
 use constant::{ERROR_POS_TO_SYNDROME, SYNDROME_TO_ERROR_POS};
 use util::{msb_to_u32, u32_to_msb};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "HammingParams", into = "HammingParams")
+)]
 pub struct Hamming {
-  pub deg: u32,                        // m
-  pub code_bit_len: usize,             // n
-  pub info_bit_len: usize,             // k
-  pub error_pos_to_syndrome: Vec<u32>, // error position -> syndrome value
-  pub syndrome_to_error_pos: Vec<u32>, // syndrome (expressed in usize msb) -> one error bit idx, idx=0 then no error
+  pub deg: u32,                             // m
+  pub code_bit_len: usize,                  // n
+  pub info_bit_len: usize,                  // k
+  pub error_pos_to_syndrome: Vec<u32>,      // error position -> syndrome value
+  pub syndrome_to_error_pos: Vec<u32>,      // syndrome (expressed in usize msb) -> one error bit idx, idx=0 then no error
+  precoding: Option<LinearTransform<GF2>>,  // precoding matrix for error_alignment
+  postcoding: Option<LinearTransform<GF2>>, // postcoding matrix for error_alignment
 }
 
 impl Hamming {
   pub fn new(deg: u32) -> Result<Self> {
+    let error_pos_to_syndrome = ERROR_POS_TO_SYNDROME
+      .get(&deg)
+      .ok_or(anyhow!("Unsupported degree {}", deg))?
+      .to_vec();
+    let syndrome_to_error_pos = SYNDROME_TO_ERROR_POS
+      .get(&deg)
+      .ok_or(anyhow!("Unsupported degree {}", deg))?
+      .to_vec();
     let code_len = (2u32.pow(deg) - 1) as usize;
     let info_len = code_len - deg as usize;
-
-    let error_pos_to_syndrome = ERROR_POS_TO_SYNDROME.get(&deg).unwrap().to_vec();
-    let syndrome_to_error_pos = SYNDROME_TO_ERROR_POS.get(&deg).unwrap().to_vec();
     Ok(Hamming {
       code_bit_len: code_len,
       info_bit_len: info_len,
       deg,
       error_pos_to_syndrome,
       syndrome_to_error_pos,
+      precoding: None,
+      postcoding: None,
     })
   }
 
@@ -45,6 +60,23 @@ impl Hamming {
       })
   }
 
+  fn set_precoding_matrix(&mut self, mat: Matrix<GF2>) -> Result<()> {
+    ensure!(
+      mat.row_size() == self.code_bit_len && mat.col_size() == self.code_bit_len,
+      "Precoding matrix must be {}x{}",
+      self.code_bit_len,
+      self.code_bit_len
+    );
+    mat.ensure_nonsingular(GF2(0), GF2(1))?;
+    let pre = LinearTransform::from_matrix(mat, GF2(0), GF2(1));
+    let inv = pre
+      .inverse(GF2(0), GF2(1))
+      .map_err(|e| anyhow!("Singular matrix: {}", e))?;
+    self.precoding = Some(pre);
+    self.postcoding = Some(inv);
+    Ok(())
+  }
+
   fn one_bit_flip_by_syndrome(&self, data: &BSRep, syn: &BSRep) -> BVRep {
     let mut flipped = data.to_bitvec();
     let syn_val = msb_to_u32(syn);
@@ -64,16 +96,57 @@ impl BitUnitCode for Hamming {
   fn code_bit_len(&self) -> usize {
     self.code_bit_len
   }
+  fn set_precoding(&mut self, pre: &[U8VRep]) -> Result<()> {
+    ensure_matrix_shape(pre, self.code_bit_len, self.code_bit_len)?;
+    self.set_precoding_matrix(Matrix::of_gf2_from_u8(pre)?)
+  }
 }
+
+// Serialized form of Hamming, from which the syndrome tables and the postcoding matrix are recomputed on load
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HammingParams {
+  deg: u32,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  precoding: Option<Matrix<GF2>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Hamming> for HammingParams {
+  fn from(hamming: Hamming) -> Self {
+    HammingParams {
+      deg: hamming.deg,
+      precoding: hamming.precoding.map(|pre| pre.to_matrix(GF2(0), GF2(1))),
+    }
+  }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<HammingParams> for Hamming {
+  type Error = Error;
+
+  fn try_from(params: HammingParams) -> Result<Self> {
+    let mut hamming = Hamming::new(params.deg)?;
+    if let Some(pre) = params.precoding {
+      hamming.set_precoding_matrix(pre)?;
+    }
+    Ok(hamming)
+  }
+}
+
 impl Code for Hamming {
   type Slice = BSRep;
   type Vector = BVRep;
 
   fn decode(&self, data: &Self::Slice) -> Result<Decoded<Self::Vector>> {
     ensure!(data.len() == self.code_bit_len, "Invalid data length");
+    let precoded = match &self.precoding {
+      Some(pre) => pre.mul_on_bitslice_from_right(data),
+      None => data.to_bitvec(),
+    };
 
-    let syn = self.calc_syndrome(data);
-    let no_error = self.one_bit_flip_by_syndrome(data, &syn);
+    let syn = self.calc_syndrome(&precoded);
+    let no_error = self.one_bit_flip_by_syndrome(&precoded, &syn);
     let info = (&no_error[0..self.info_bit_len]).to_bitvec();
     ensure!(
       info.len() == self.info_bit_len && syn.len() == self.deg as usize,
@@ -104,7 +177,12 @@ impl Code for Hamming {
       "Invalid error calculation"
     );
 
-    Ok(Encoded::<Self::Vector>(flipped))
+    let postcoded = match &self.postcoding {
+      Some(post) => post.mul_on_bitslice_from_right(&flipped),
+      None => flipped,
+    };
+
+    Ok(Encoded::<Self::Vector>(postcoded))
   }
 }
 
@@ -201,6 +279,38 @@ mod tests {
     }
   }
 
+  #[test]
+  fn test_precoding() {
+    let mut hamming = Hamming::new(3).unwrap();
+    // swap the first and the last bits, and add the second bit to the third one
+    let trans: Vec<U8VRep> = vec![
+      vec![0, 0, 0, 0, 0, 0, 1],
+      vec![0, 1, 1, 0, 0, 0, 0],
+      vec![0, 0, 1, 0, 0, 0, 0],
+      vec![0, 0, 0, 1, 0, 0, 0],
+      vec![0, 0, 0, 0, 1, 0, 0],
+      vec![0, 0, 0, 0, 0, 1, 0],
+      vec![1, 0, 0, 0, 0, 0, 0],
+    ];
+    assert!(hamming.set_precoding(&trans).is_ok());
+
+    let data = bitvec![u8, Msb0; 1,0,1,1,1,1,0];
+    let decoded = hamming.decode(&data).unwrap();
+    let encoded = hamming.encode(&decoded.base, &decoded.deviation).unwrap();
+    assert_eq!(data, encoded.0);
+
+    // the last bit is moved to the first position, i.e., a single error at the first position
+    let data = bitvec![u8, Msb0; 0,0,0,0,0,0,1];
+    let decoded = hamming.decode(&data).unwrap();
+    assert_eq!("0000", decoded.base.bitdump());
+    assert_eq!("101", decoded.deviation.bitdump());
+
+    let mut singular = trans.clone();
+    singular[1] = vec![0, 0, 1, 0, 0, 0, 0];
+    assert!(hamming.set_precoding(&singular).is_err());
+    assert!(hamming.set_precoding(&trans[1..]).is_err());
+  }
+
   /*
     #[test]
     fn test_deg8_bits() {
@@ -220,4 +330,22 @@ mod tests {
     }
 
   */
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_works() {
+    let mut hamming = Hamming::new(3).unwrap();
+    let pre = (0..7)
+      .map(|i| (0..7).map(|j| (j == (i + 2) % 7) as u8).collect())
+      .collect::<Vec<U8VRep>>();
+    hamming.set_precoding(&pre).unwrap();
+    let json = serde_json::to_string(&hamming).unwrap();
+    let loaded: Hamming = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.syndrome_to_error_pos, hamming.syndrome_to_error_pos);
+    assert_eq!(loaded.postcoding, hamming.postcoding);
+
+    assert!(serde_json::from_str::<Hamming>(r#"{"deg":40}"#).is_err());
+    // entries of a matrix over GF(2) must be 0 or 1
+    assert!(serde_json::from_str::<Matrix<GF2>>("[[1,2],[0,1]]").is_err());
+  }
 }
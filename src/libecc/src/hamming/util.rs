@@ -1,4 +1,5 @@
 use crate::types::*;
+use alloc::vec::Vec;
 use bitvec::prelude::*;
 
 pub fn u32_to_u8vec(num: &u32) -> Vec<u8> {
@@ -1,3 +1,6 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
 mod error;
 mod math;
 pub mod types;
@@ -6,6 +9,7 @@ mod util;
 mod hamming;
 mod rs;
 
+use alloc::string::String;
 use bitvec::prelude::*;
 use error::*;
 pub use hamming::Hamming;
@@ -24,6 +28,7 @@ pub trait Code {
 pub trait BitUnitCode: Code<Slice = BSRep, Vector = BVRep> {
   fn code_bit_len(&self) -> usize;
   fn info_bit_len(&self) -> usize;
+  fn set_precoding(&mut self, pre: &[U8VRep]) -> Result<()>;
 }
 pub trait ByteUnitCode: Code<Slice = U8SRep, Vector = U8VRep> {
   fn code_byte_len(&self) -> usize;
@@ -3,6 +3,7 @@
 // Basic operations overrided for the Galois Field 256 (2**8)
 // Uses pre-calculated tables for 0x11d primitive polynomial (x**8 + x**4 + x**3 + x**2 + 1)
 
+use crate::error::*;
 use core::{
   iter::{Product, Sum},
   ops::{Add, Div, Mul, Sub},
@@ -53,6 +54,11 @@ const EXP_TABLE: [u8; 255] = [
 ];
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(transparent)
+)]
 pub struct GF256(pub u8);
 
 #[allow(clippy::suspicious_arithmetic_impl)]
@@ -131,9 +137,77 @@ impl GF256 {
   }
 }
 
+// Binary field GF(2) used for bit-level precoding of Hamming-based GD.
+// The inner u8 must be either 0 or 1.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "u8", into = "u8")
+)]
+pub struct GF2(pub u8);
+
+impl TryFrom<u8> for GF2 {
+  type Error = Error;
+
+  fn try_from(value: u8) -> Result<Self> {
+    ensure!(value < 2, "Element of GF(2) must be 0 or 1");
+    Ok(GF2(value))
+  }
+}
+impl From<GF2> for u8 {
+  fn from(value: GF2) -> Self {
+    value.0
+  }
+}
+
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Add for GF2 {
+  type Output = GF2;
+
+  fn add(self, other: Self) -> Self::Output {
+    Self(self.0 ^ other.0)
+  }
+}
+
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Sub for GF2 {
+  type Output = Self;
+
+  fn sub(self, other: Self) -> Self::Output {
+    Self(self.0 ^ other.0)
+  }
+}
+
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Mul for GF2 {
+  type Output = Self;
+
+  fn mul(self, other: Self) -> Self::Output {
+    Self(self.0 & other.0)
+  }
+}
+
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Div for GF2 {
+  type Output = Self;
+
+  // The only valid divisor is 1, and division by zero panics as that of integers.
+  fn div(self, other: Self) -> Self::Output {
+    assert!(other.0 != 0, "Division by zero in GF(2)");
+    self
+  }
+}
+
+impl From<bool> for GF2 {
+  fn from(b: bool) -> Self {
+    Self(b as u8)
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use super::{EXP_TABLE, GF256, LOG_TABLE, ORDER};
+  use super::{EXP_TABLE, GF2, GF256, LOG_TABLE, ORDER};
   // use alloc::vec;
 
   #[test]
@@ -252,4 +326,23 @@ mod tests {
     assert_eq!(GF256(4).pow(-1), GF256(71));
     assert_eq!(GF256(4) * GF256(71), GF256(1));
   }
+
+  #[test]
+  fn gf2_works() {
+    for x in 0u8..2 {
+      for y in 0u8..2 {
+        assert_eq!((GF2(x) + GF2(y)).0, x ^ y);
+        assert_eq!((GF2(x) - GF2(y)).0, x ^ y);
+        assert_eq!((GF2(x) * GF2(y)).0, x & y);
+      }
+      assert_eq!(GF2(x) / GF2(1), GF2(x));
+    }
+    assert_eq!(GF2::from(true), GF2(1));
+  }
+
+  #[test]
+  #[should_panic(expected = "Division by zero in GF(2)")]
+  fn gf2_div_by_zero_panics() {
+    let _ = GF2(1) / GF2(0);
+  }
 }
@@ -1,13 +1,44 @@
 use super::{field::*, vectorized::Vectorized};
-use crate::error::*;
+use crate::{error::*, types::*};
+use alloc::{format, vec, vec::Vec};
+use bitvec::prelude::*;
 use core::{
   fmt::Debug,
   ops::{Add, Div, Mul, Sub},
 };
 
+// Serialized as a list of rows, which must be non-empty and of the same length
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(
+    try_from = "Vec<Vec<T>>",
+    into = "Vec<Vec<T>>",
+    bound(
+      serialize = "T: serde::Serialize + Clone",
+      deserialize = "T: serde::Deserialize<'de> + Debug + Clone + PartialEq"
+    )
+  )
+)]
 pub struct Matrix<T>(pub Vec<Vectorized<T>>);
 
+impl<T> TryFrom<Vec<Vec<T>>> for Matrix<T>
+where
+  T: Debug + Clone + PartialEq,
+{
+  type Error = Error;
+
+  fn try_from(rows: Vec<Vec<T>>) -> Result<Self> {
+    Matrix::new(&rows)
+  }
+}
+impl<T> From<Matrix<T>> for Vec<Vec<T>> {
+  fn from(mat: Matrix<T>) -> Self {
+    mat.0.into_iter().map(|row| row.0).collect()
+  }
+}
+
 impl<T> Mul for Matrix<T>
 where
   T: Debug + Add<Output = T> + Mul<Output = T> + Copy + PartialEq,
@@ -51,6 +82,32 @@ impl Matrix<GF256> {
   }
 }
 
+impl Matrix<GF2> {
+  pub fn of_gf2_from_u8(src: &[Vec<u8>]) -> Result<Self> {
+    ensure!(
+      src.iter().all(|v| v.iter().all(|x| *x < 2)),
+      "Entries of a matrix over GF(2) must be 0 or 1"
+    );
+    Matrix::new(
+      &src
+        .iter()
+        .map(|v| v.iter().map(|x| GF2(*x)).collect::<Vec<GF2>>())
+        .collect::<Vec<Vec<GF2>>>(),
+    )
+  }
+
+  pub fn mul_on_bitslice_from_right(&self, coef: &BSRep) -> BVRep {
+    assert_eq!(self.row_size(), coef.len());
+    let mut res = bitvec![u8, Msb0; 0; self.col_size()];
+    for row_idx in coef.iter_ones() {
+      for (mut dst, src) in res.iter_mut().zip(self.0[row_idx].0.iter()) {
+        *dst ^= src.0 == 1;
+      }
+    }
+    res
+  }
+}
+
 impl<T> Matrix<T>
 where
   T: Debug + Clone + PartialEq,
@@ -61,9 +118,7 @@ where
       src.iter().all(|v| v.len() == src[0].len()),
       "Invalid input as a matrix"
     );
-    Ok(Matrix(
-      src.iter().map(|v| Vectorized(v.to_vec())).collect(),
-    ))
+    Ok(Matrix(src.iter().map(|v| Vectorized(v.to_vec())).collect()))
   }
   pub fn row_size(&self) -> usize {
     self.0.len()
@@ -211,6 +266,175 @@ where
     );
     Ok(submatrix)
   }
+
+  pub fn transpose(&self) -> Self {
+    Matrix(
+      (0..self.col_size())
+        .map(|col_idx| Vectorized(self.0.iter().map(|row| row.0[col_idx].clone()).collect()))
+        .collect(),
+    )
+  }
+}
+
+impl<T> Matrix<T>
+where
+  T: Debug + Copy + PartialEq + Div<Output = T> + Mul<Output = T> + Sub<Output = T>,
+{
+  pub fn rank(&self, zero_t: T, identity_t: T) -> usize {
+    self.reduce(zero_t, identity_t).pivots.len()
+  }
+
+  pub fn determinant(&self, zero_t: T, identity_t: T) -> Result<T> {
+    ensure!(
+      self.is_square(),
+      "Determinant is defined only for square matrices"
+    );
+    let reduced = self.reduce(zero_t, identity_t);
+    if reduced.pivots.len() < self.row_size() {
+      Ok(zero_t)
+    } else if reduced.swap_num % 2 == 1 {
+      Ok(zero_t - reduced.pivot_product)
+    } else {
+      Ok(reduced.pivot_product)
+    }
+  }
+
+  // Basis of {x | M x^T = 0}. Note that {x | x M = 0}, i.e., linear dependencies among rows, is given by the transposed one.
+  pub fn null_space(&self, zero_t: T, identity_t: T) -> Vec<Vectorized<T>> {
+    let reduced = self.reduce(zero_t, identity_t);
+    (0..self.col_size())
+      .filter(|col_idx| !reduced.pivots.contains(col_idx))
+      .map(|free_idx| {
+        let mut v = vec![zero_t; self.col_size()];
+        v[free_idx] = identity_t;
+        reduced
+          .pivots
+          .iter()
+          .enumerate()
+          .for_each(|(row_idx, pivot_idx)| {
+            v[*pivot_idx] = zero_t - reduced.rows[row_idx].0[free_idx]
+          });
+        Vectorized(v)
+      })
+      .collect()
+  }
+
+  // Check if the matrix is nonsingular, and if not, report linearly dependent rows
+  pub fn ensure_nonsingular(&self, zero_t: T, identity_t: T) -> Result<()> {
+    ensure!(
+      self.is_square(),
+      "Matrix must be square, but it is of {} x {}",
+      self.row_size(),
+      self.col_size()
+    );
+    let rank = self.rank(zero_t, identity_t);
+    if rank == self.row_size() {
+      return Ok(());
+    }
+
+    let dependencies = self.transpose().null_space(zero_t, identity_t);
+    let mut rows = dependencies
+      .iter()
+      .flat_map(|v| {
+        v.0
+          .iter()
+          .enumerate()
+          .filter(|(_, x)| **x != zero_t)
+          .map(|(row_idx, _)| row_idx)
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    rows.sort_unstable();
+    rows.dedup();
+    let relations = dependencies
+      .iter()
+      .map(|v| {
+        let terms = v
+          .0
+          .iter()
+          .enumerate()
+          .filter(|(_, x)| **x != zero_t)
+          .map(|(row_idx, x)| format!("{:?} * row[{}]", x, row_idx))
+          .collect::<Vec<_>>();
+        format!("{} = 0", terms.join(" + "))
+      })
+      .collect::<Vec<_>>();
+    bail!(
+      "Singular matrix of rank {} (< {}): rows {:?} are linearly dependent, i.e., {}",
+      rank,
+      self.row_size(),
+      rows,
+      relations.join(", ")
+    );
+  }
+
+  // Gauss-Jordan elimination
+  fn reduce(&self, zero_t: T, identity_t: T) -> Reduced<T> {
+    let mut rows = self.0.clone();
+    let mut pivots = Vec::new();
+    let mut swap_num = 0usize;
+    let mut pivot_product = identity_t;
+
+    for col_idx in 0..self.col_size() {
+      let ptr = pivots.len();
+      if ptr == self.row_size() {
+        break;
+      }
+      let Some(found) = (ptr..self.row_size()).find(|i| rows[*i].0[col_idx] != zero_t) else {
+        continue;
+      };
+      if found != ptr {
+        rows.swap(found, ptr);
+        swap_num += 1;
+      }
+      let pivot = rows[ptr].0[col_idx];
+      pivot_product = pivot_product * pivot;
+      rows[ptr].mul_scalar_within(identity_t / pivot);
+
+      let focus = rows[ptr].clone();
+      for (row_idx, row) in rows.iter_mut().enumerate() {
+        if row_idx != ptr && row.0[col_idx] != zero_t {
+          let coefficient = row.0[col_idx];
+          row.sub_within(focus.mul_scalar(coefficient));
+        }
+      }
+      pivots.push(col_idx);
+    }
+
+    Reduced {
+      rows,
+      pivots,
+      swap_num,
+      pivot_product,
+    }
+  }
+}
+
+// Reduced row echelon form with pivot column indices, the number of row swaps and the product of pivots
+struct Reduced<T> {
+  rows: Vec<Vectorized<T>>,
+  pivots: Vec<usize>,
+  swap_num: usize,
+  pivot_product: T,
+}
+
+// Check the shape of a matrix given as a slice of rows before its instantiation
+pub fn ensure_matrix_shape<S>(src: &[Vec<S>], row_size: usize, col_size: usize) -> Result<()> {
+  ensure!(
+    src.len() == row_size,
+    "Invalid matrix size: {} rows are given but {} rows are required",
+    src.len(),
+    row_size
+  );
+  if let Some((row_idx, row)) = src.iter().enumerate().find(|(_, v)| v.len() != col_size) {
+    bail!(
+      "Invalid matrix size: row[{}] has {} columns but {} columns are required",
+      row_idx,
+      row.len(),
+      col_size
+    );
+  }
+  Ok(())
 }
 
 #[cfg(test)]
@@ -248,4 +472,80 @@ mod tests {
     // println!("{:?}", mult);
     assert!(mult.is_identity_matrix(GF256(0), GF256(1)));
   }
+
+  #[test]
+  fn gf2_inverse_works() {
+    let mat = Matrix::of_gf2_from_u8(&[
+      vec![1u8, 1, 0, 0],
+      vec![0u8, 1, 0, 0],
+      vec![0u8, 0, 0, 1],
+      vec![1u8, 0, 1, 0],
+    ])
+    .unwrap();
+    let inverse = mat.inverse_left_submatrix(GF2(0), GF2(1)).unwrap();
+    assert!((inverse.clone() * mat.clone()).is_identity_matrix(GF2(0), GF2(1)));
+
+    let bits = bitvec![u8, Msb0; 1, 0, 1, 1];
+    let transformed = mat.mul_on_bitslice_from_right(&bits);
+    assert_eq!(transformed, bitvec![u8, Msb0; 0, 1, 1, 1]);
+    assert_eq!(inverse.mul_on_bitslice_from_right(&transformed), bits);
+
+    assert!(Matrix::of_gf2_from_u8(&[vec![1u8, 2], vec![0u8, 1]]).is_err());
+  }
+
+  #[test]
+  fn analysis_works() {
+    let mat = Matrix::of_gf256_from_u8(&[
+      vec![1u8, 0, 0, 0],
+      vec![1u8, 1, 1, 4],
+      vec![1u8, 1, 3, 0],
+      vec![1u8, 2, 0, 0],
+    ])
+    .unwrap();
+    assert_eq!(mat.rank(GF256(0), GF256(1)), 4);
+    assert!(mat.null_space(GF256(0), GF256(1)).is_empty());
+    assert!(mat.ensure_nonsingular(GF256(0), GF256(1)).is_ok());
+    assert_ne!(mat.determinant(GF256(0), GF256(1)).unwrap(), GF256(0));
+    assert_eq!(mat.transpose().transpose(), mat);
+    assert_eq!(
+      mat.transpose().0[1],
+      Vectorized::of_gf256_from_u8(&[0, 1, 1, 2])
+    );
+
+    // det [[1, 2], [3, 4]] = 1 * 4 + 2 * 3 over GF(256)
+    let mat = Matrix::of_gf256_from_u8(&[vec![1u8, 2], vec![3u8, 4]]).unwrap();
+    assert_eq!(
+      mat.determinant(GF256(0), GF256(1)).unwrap(),
+      GF256(1) * GF256(4) - GF256(2) * GF256(3)
+    );
+
+    // row[3] = row[1] + row[2]
+    let singular = Matrix::of_gf256_from_u8(&[
+      vec![1u8, 0, 0, 0],
+      vec![1u8, 1, 1, 4],
+      vec![1u8, 1, 3, 0],
+      vec![0u8, 0, 2, 4],
+    ])
+    .unwrap();
+    assert_eq!(singular.rank(GF256(0), GF256(1)), 3);
+    assert_eq!(singular.determinant(GF256(0), GF256(1)).unwrap(), GF256(0));
+    let null_space = singular.null_space(GF256(0), GF256(1));
+    assert_eq!(null_space.len(), 1);
+    assert!(singular
+      .0
+      .iter()
+      .all(|row| row.clone() * null_space[0].clone() == GF256(0)));
+
+    let err = singular
+      .ensure_nonsingular(GF256(0), GF256(1))
+      .unwrap_err()
+      .to_string();
+    assert!(err.contains("rank 3"));
+    assert!(err.contains("rows [1, 2, 3]"));
+
+    assert!(ensure_matrix_shape(&[vec![1u8, 0], vec![0u8, 1]], 2, 2).is_ok());
+    let err = ensure_matrix_shape(&[vec![1u8, 0], vec![0u8]], 2, 2).unwrap_err();
+    assert!(err.to_string().contains("row[1] has 1 columns"));
+    assert!(ensure_matrix_shape(&[vec![1u8, 0]], 2, 2).is_err());
+  }
 }
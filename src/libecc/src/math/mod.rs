@@ -1,7 +1,9 @@
 mod field;
 mod matrix;
+mod transform;
 mod vectorized;
 
 pub use field::*;
 pub use matrix::*;
+pub use transform::*;
 pub use vectorized::*;
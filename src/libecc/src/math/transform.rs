@@ -0,0 +1,276 @@
+use super::{field::*, matrix::Matrix, vectorized::Vectorized};
+use crate::{error::*, types::*};
+use alloc::{vec, vec::Vec};
+use bitvec::prelude::*;
+use core::{
+  fmt::Debug,
+  ops::{Add, Div, Mul, Sub},
+};
+
+// Linear transformation used for error-alignment, i.e., precoding and postcoding.
+// A permutation matrix or a sparse matrix is held in a specialized form to avoid the dense matrix-vector product.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LinearTransform<T> {
+  Permutation(Vec<usize>),             // i-th symbol is moved to perm[i]-th position
+  Sparse(usize, Vec<Vec<(usize, T)>>), // (column size, list of (column index, nonzero coefficient) for each row)
+  Dense(Matrix<T>),
+}
+
+impl<T> LinearTransform<T>
+where
+  T: Debug
+    + Clone
+    + Copy
+    + PartialEq
+    + Add<Output = T>
+    + Div<Output = T>
+    + Mul<Output = T>
+    + Sub<Output = T>,
+{
+  pub fn from_matrix(mat: Matrix<T>, zero_t: T, identity_t: T) -> Self {
+    let nonzeros: Vec<Vec<(usize, T)>> = mat
+      .0
+      .iter()
+      .map(|row| {
+        row
+          .0
+          .iter()
+          .enumerate()
+          .filter(|(_, x)| **x != zero_t)
+          .map(|(col_idx, x)| (col_idx, *x))
+          .collect()
+      })
+      .collect();
+
+    if let Some(perm) = Self::as_permutation(&mat, &nonzeros, identity_t) {
+      return LinearTransform::Permutation(perm);
+    }
+
+    // at most half of entries are nonzero
+    let nonzero_num: usize = nonzeros.iter().map(|row| row.len()).sum();
+    if nonzero_num * 2 <= mat.row_size() * mat.col_size() {
+      LinearTransform::Sparse(mat.col_size(), nonzeros)
+    } else {
+      LinearTransform::Dense(mat)
+    }
+  }
+
+  fn as_permutation(
+    mat: &Matrix<T>,
+    nonzeros: &[Vec<(usize, T)>],
+    identity_t: T,
+  ) -> Option<Vec<usize>> {
+    if !mat.is_square() {
+      return None;
+    }
+    let mut used = vec![false; mat.col_size()];
+    let mut perm = Vec::with_capacity(mat.row_size());
+    for row in nonzeros.iter() {
+      match row.as_slice() {
+        [(col_idx, x)] if *x == identity_t && !used[*col_idx] => {
+          used[*col_idx] = true;
+          perm.push(*col_idx);
+        }
+        _ => return None,
+      }
+    }
+    Some(perm)
+  }
+
+  pub fn is_permutation(&self) -> bool {
+    matches!(self, LinearTransform::Permutation(_))
+  }
+  pub fn is_sparse(&self) -> bool {
+    matches!(self, LinearTransform::Sparse(_, _))
+  }
+
+  // Dense form of the transformation
+  pub fn to_matrix(&self, zero_t: T, identity_t: T) -> Matrix<T> {
+    match self {
+      LinearTransform::Permutation(perm) => Matrix(
+        perm
+          .iter()
+          .map(|p| {
+            let mut v = vec![zero_t; perm.len()];
+            v[*p] = identity_t;
+            Vectorized(v)
+          })
+          .collect(),
+      ),
+      LinearTransform::Sparse(col_size, rows) => Matrix(
+        rows
+          .iter()
+          .map(|row| {
+            let mut v = vec![zero_t; *col_size];
+            row.iter().for_each(|(col_idx, x)| v[*col_idx] = *x);
+            Vectorized(v)
+          })
+          .collect(),
+      ),
+      LinearTransform::Dense(mat) => mat.clone(),
+    }
+  }
+
+  pub fn inverse(&self, zero_t: T, identity_t: T) -> Result<Self> {
+    match self {
+      LinearTransform::Permutation(perm) => {
+        let mut inv = vec![0usize; perm.len()];
+        perm.iter().enumerate().for_each(|(i, p)| inv[*p] = i);
+        Ok(LinearTransform::Permutation(inv))
+      }
+      LinearTransform::Sparse(_, _) => {
+        let dense = self.to_matrix(zero_t, identity_t);
+        let inv = dense.inverse_left_submatrix(zero_t, identity_t)?;
+        Ok(Self::from_matrix(inv, zero_t, identity_t))
+      }
+      LinearTransform::Dense(mat) => {
+        let inv = mat.inverse_left_submatrix(zero_t, identity_t)?;
+        Ok(Self::from_matrix(inv, zero_t, identity_t))
+      }
+    }
+  }
+}
+
+impl LinearTransform<GF256> {
+  pub fn mul_on_vec_from_right(&self, coef: &Vectorized<GF256>) -> Vectorized<GF256> {
+    match self {
+      LinearTransform::Permutation(perm) => {
+        let mut res = Vectorized(vec![GF256(0); perm.len()]);
+        perm
+          .iter()
+          .enumerate()
+          .for_each(|(i, p)| res.0[*p] = coef.0[i]);
+        res
+      }
+      LinearTransform::Sparse(col_size, rows) => {
+        let mut res = Vectorized(vec![GF256(0); *col_size]);
+        rows.iter().enumerate().for_each(|(i, row)| {
+          row
+            .iter()
+            .for_each(|(col_idx, x)| res.0[*col_idx] = res.0[*col_idx] + *x * coef.0[i]);
+        });
+        res
+      }
+      LinearTransform::Dense(mat) => mat.mul_on_vec_from_right(coef),
+    }
+  }
+}
+
+impl LinearTransform<GF2> {
+  pub fn mul_on_bitslice_from_right(&self, coef: &BSRep) -> BVRep {
+    match self {
+      LinearTransform::Permutation(perm) => {
+        let mut res = bitvec![u8, Msb0; 0; perm.len()];
+        coef.iter_ones().for_each(|i| res.set(perm[i], true));
+        res
+      }
+      LinearTransform::Sparse(col_size, rows) => {
+        let mut res = bitvec![u8, Msb0; 0; *col_size];
+        coef.iter_ones().for_each(|i| {
+          rows[i].iter().for_each(|(col_idx, _)| {
+            let b = res[*col_idx];
+            res.set(*col_idx, !b);
+          });
+        });
+        res
+      }
+      LinearTransform::Dense(mat) => mat.mul_on_bitslice_from_right(coef),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn permutation_works() {
+    let mat = Matrix::of_gf256_from_u8(&[
+      vec![0u8, 0, 1, 0],
+      vec![1u8, 0, 0, 0],
+      vec![0u8, 0, 0, 1],
+      vec![0u8, 1, 0, 0],
+    ])
+    .unwrap();
+    let trans = LinearTransform::from_matrix(mat.clone(), GF256(0), GF256(1));
+    assert_eq!(trans, LinearTransform::Permutation(vec![2, 0, 3, 1]));
+    assert_eq!(trans.to_matrix(GF256(0), GF256(1)), mat);
+
+    let v = Vectorized::of_gf256_from_u8(&[10, 20, 30, 40]);
+    let transformed = trans.mul_on_vec_from_right(&v);
+    assert_eq!(transformed, mat.mul_on_vec_from_right(&v));
+
+    let inv = trans.inverse(GF256(0), GF256(1)).unwrap();
+    assert!(inv.is_permutation());
+    assert_eq!(inv.mul_on_vec_from_right(&transformed), v);
+  }
+
+  #[test]
+  fn sparse_works() {
+    // scaled permutation is not a permutation but sparse
+    let mat = Matrix::of_gf256_from_u8(&[
+      vec![0u8, 0, 3, 0],
+      vec![1u8, 0, 0, 0],
+      vec![0u8, 0, 0, 1],
+      vec![0u8, 1, 0, 5],
+    ])
+    .unwrap();
+    let trans = LinearTransform::from_matrix(mat.clone(), GF256(0), GF256(1));
+    assert!(trans.is_sparse());
+    assert_eq!(trans.to_matrix(GF256(0), GF256(1)), mat);
+
+    let v = Vectorized::of_gf256_from_u8(&[10, 20, 30, 40]);
+    let transformed = trans.mul_on_vec_from_right(&v);
+    assert_eq!(transformed, mat.mul_on_vec_from_right(&v));
+
+    let inv = trans.inverse(GF256(0), GF256(1)).unwrap();
+    assert_eq!(inv.mul_on_vec_from_right(&transformed), v);
+  }
+
+  #[test]
+  fn dense_works() {
+    let mat = Matrix::of_gf256_from_u8(&[
+      vec![1u8, 0, 0, 0],
+      vec![1u8, 1, 1, 4],
+      vec![1u8, 1, 3, 0],
+      vec![1u8, 2, 0, 0],
+    ])
+    .unwrap();
+    let trans = LinearTransform::from_matrix(mat.clone(), GF256(0), GF256(1));
+    assert!(matches!(trans, LinearTransform::Dense(_)));
+    let inv = trans.inverse(GF256(0), GF256(1)).unwrap();
+    let v = Vectorized::of_gf256_from_u8(&[10, 20, 30, 40]);
+    assert_eq!(
+      inv.mul_on_vec_from_right(&trans.mul_on_vec_from_right(&v)),
+      v
+    );
+  }
+
+  #[test]
+  fn gf2_works() {
+    let perm =
+      Matrix::of_gf2_from_u8(&[vec![0u8, 1, 0], vec![0u8, 0, 1], vec![1u8, 0, 0]]).unwrap();
+    let trans = LinearTransform::from_matrix(perm.clone(), GF2(0), GF2(1));
+    assert!(trans.is_permutation());
+    let bits = bitvec![u8, Msb0; 1, 1, 0];
+    assert_eq!(
+      trans.mul_on_bitslice_from_right(&bits),
+      perm.mul_on_bitslice_from_right(&bits)
+    );
+
+    let sparse = Matrix::of_gf2_from_u8(&[
+      vec![1u8, 1, 0, 0],
+      vec![0u8, 1, 0, 0],
+      vec![0u8, 0, 0, 1],
+      vec![1u8, 0, 1, 0],
+    ])
+    .unwrap();
+    let trans = LinearTransform::from_matrix(sparse.clone(), GF2(0), GF2(1));
+    assert!(trans.is_sparse());
+    let bits = bitvec![u8, Msb0; 1, 0, 1, 1];
+    let transformed = trans.mul_on_bitslice_from_right(&bits);
+    assert_eq!(transformed, sparse.mul_on_bitslice_from_right(&bits));
+    let inv = trans.inverse(GF2(0), GF2(1)).unwrap();
+    assert_eq!(inv.mul_on_bitslice_from_right(&transformed), bits);
+  }
+}
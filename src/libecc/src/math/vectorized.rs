@@ -1,9 +1,15 @@
 use crate::types::U8VRep;
+use alloc::vec::Vec;
 
 use super::field::GF256;
 use core::ops::{Add, Mul, Sub};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(transparent)
+)]
 pub struct Vectorized<T>(pub Vec<T>);
 
 impl Vectorized<GF256> {
@@ -1,36 +1,36 @@
 use super::{error::*, math::*, types::*, ByteUnitCode, Code, Decoded, Encoded};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use futures::{
   future::join_all,
   stream::{self, StreamExt},
 };
+#[cfg(feature = "std")]
 use tokio::task::{spawn_blocking, JoinError};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "ReedSolomonParams", into = "ReedSolomonParams")
+)]
 pub struct ReedSolomon {
-  pub code_symbol_len: usize,             // n over GF(2^8)
-  pub info_symbol_len: usize,             // k over GF(2^8)
-  pub deviation_symbol_len: usize,        // deviation length over GF(2^8)
-  generator_matrix_parity: Matrix<GF256>, // parity part P of systematic generator matrix G = [I P] as a look-up table for encoding
-  precoding: Option<Matrix<GF256>>,       // precoding matrix for error_alignment
-  postcoding: Option<Matrix<GF256>>,      // postcoding matrix for error_alignment
+  pub code_symbol_len: usize,                 // n over GF(2^8)
+  pub info_symbol_len: usize,                 // k over GF(2^8)
+  pub deviation_symbol_len: usize,            // deviation length over GF(2^8)
+  generator_matrix_parity: Matrix<GF256>,     // parity part P of systematic generator matrix G = [I P] as a look-up table for encoding
+  precoding: Option<LinearTransform<GF256>>,  // precoding matrix for error_alignment
+  postcoding: Option<LinearTransform<GF256>>, // postcoding matrix for error_alignment
 }
 
 impl ReedSolomon {
+  #[cfg(feature = "std")]
   pub async fn new(code_symbol_len: usize, info_symbol_len: usize) -> Result<Self> {
-    ensure!(
-      code_symbol_len > info_symbol_len && code_symbol_len < ORDER && info_symbol_len < ORDER,
-      "Invalid params"
-    );
+    ensure_params(code_symbol_len, info_symbol_len)?;
 
     let res: Vec<_> = join_all(
       stream::iter(0..info_symbol_len)
-        .map(|row| {
-          spawn_blocking(move || {
-            (0..code_symbol_len)
-              .map(|col| GF256(ROOT).pow((row * col) as isize))
-              .collect::<Vec<GF256>>()
-          })
-        })
+        .map(|row| spawn_blocking(move || vandermonde_row(row, code_symbol_len)))
         .collect::<Vec<_>>()
         .await,
     )
@@ -40,7 +40,25 @@ impl ReedSolomon {
         .into_iter()
         .collect::<Result<Vec<Vec<GF256>>, JoinError>>()?,
     )?;
+    Self::from_vandermonde(code_symbol_len, info_symbol_len, vandermonde_matrix)
+  }
+
+  // Same as new, but computes the generator matrix on the calling thread, e.g., to deserialize or without std
+  pub fn new_blocking(code_symbol_len: usize, info_symbol_len: usize) -> Result<Self> {
+    ensure_params(code_symbol_len, info_symbol_len)?;
+    let vandermonde_matrix = Matrix::new(
+      &(0..info_symbol_len)
+        .map(|row| vandermonde_row(row, code_symbol_len))
+        .collect::<Vec<_>>(),
+    )?;
+    Self::from_vandermonde(code_symbol_len, info_symbol_len, vandermonde_matrix)
+  }
 
+  fn from_vandermonde(
+    code_symbol_len: usize,
+    info_symbol_len: usize,
+    vandermonde_matrix: Matrix<GF256>,
+  ) -> Result<Self> {
     let inverse_matrix = vandermonde_matrix
       .inverse_left_submatrix(GF256(0), GF256(1))?;
 
@@ -59,6 +77,25 @@ impl ReedSolomon {
     })
   }
 
+  fn set_precoding_matrix(&mut self, mat: Matrix<GF256>) -> Result<()> {
+    ensure!(
+      mat.row_size() == self.code_symbol_len && mat.col_size() == self.code_symbol_len,
+      "Precoding matrix must be {}x{}",
+      self.code_symbol_len,
+      self.code_symbol_len
+    );
+    mat.ensure_nonsingular(GF256(0), GF256(1))?;
+
+    // permutation and sparse matrices are held in specialized forms
+    let pre = LinearTransform::from_matrix(mat, GF256(0), GF256(1));
+    let inv = pre
+      .inverse(GF256(0), GF256(1))
+      .map_err(|e| anyhow!("Singular matrix: {}", e))?;
+    self.precoding = Some(pre);
+    self.postcoding = Some(inv);
+    Ok(())
+  }
+
   fn msg_encode_gf256_within(
     &self,
     message: &Vectorized<GF256>,
@@ -84,20 +121,60 @@ impl ByteUnitCode for ReedSolomon {
     self.info_symbol_len
   }
   fn set_precoding(&mut self, pre: &[U8VRep]) -> Result<()> {
-    let mat = Matrix::of_gf256_from_u8(pre);
-    ensure!(mat.is_ok(), "Failed to set matrix");
-    self.precoding = Some(mat.unwrap());
-
-    let mat = self.precoding.as_ref().unwrap();
-    ensure!(mat.is_square(), "Matrix for error alignment must be square");
-    let inv = mat
-      .inverse_left_submatrix(GF256(0), GF256(1))
-      .map_err(|e| anyhow!("Singular matrix: {}", e))?;
-    self.postcoding = Some(inv);
-    // assert!((mat.clone() * inv.clone()).is_identity_matrix(GF256(0), GF256(1)));
-    Ok(())
+    ensure_matrix_shape(pre, self.code_symbol_len, self.code_symbol_len)?;
+    self.set_precoding_matrix(Matrix::of_gf256_from_u8(pre)?)
+  }
+}
+
+fn ensure_params(code_symbol_len: usize, info_symbol_len: usize) -> Result<()> {
+  ensure!(
+    code_symbol_len > info_symbol_len && code_symbol_len < ORDER && info_symbol_len < ORDER,
+    "Invalid params"
+  );
+  Ok(())
+}
+
+fn vandermonde_row(row: usize, code_symbol_len: usize) -> Vec<GF256> {
+  (0..code_symbol_len)
+    .map(|col| GF256(ROOT).pow((row * col) as isize))
+    .collect()
+}
+
+// Serialized form of ReedSolomon, from which the parity part of the generator matrix and the postcoding matrix are
+// recomputed on load
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReedSolomonParams {
+  code_symbol_len: usize,
+  info_symbol_len: usize,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  precoding: Option<Matrix<GF256>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<ReedSolomon> for ReedSolomonParams {
+  fn from(rs: ReedSolomon) -> Self {
+    ReedSolomonParams {
+      code_symbol_len: rs.code_symbol_len,
+      info_symbol_len: rs.info_symbol_len,
+      precoding: rs.precoding.map(|pre| pre.to_matrix(GF256(0), GF256(1))),
+    }
   }
 }
+
+#[cfg(feature = "serde")]
+impl TryFrom<ReedSolomonParams> for ReedSolomon {
+  type Error = Error;
+
+  fn try_from(params: ReedSolomonParams) -> Result<Self> {
+    let mut rs = ReedSolomon::new_blocking(params.code_symbol_len, params.info_symbol_len)?;
+    if let Some(pre) = params.precoding {
+      rs.set_precoding_matrix(pre)?;
+    }
+    Ok(rs)
+  }
+}
+
 impl Code for ReedSolomon {
   type Slice = U8SRep;
   type Vector = U8VRep;
@@ -105,12 +182,8 @@ impl Code for ReedSolomon {
   fn decode(&self, data: &Self::Slice) -> Result<Decoded<Self::Vector>> {
     ensure!(data.len() == self.code_symbol_len, "Invalid data length");
     let mut precoded = Vectorized::of_gf256_from_u8(data);
-    if self.precoding.is_some() {
-      precoded = self
-        .precoding
-        .as_ref()
-        .unwrap()
-        .mul_on_vec_from_right(&precoded);
+    if let Some(pre) = &self.precoding {
+      precoded = pre.mul_on_vec_from_right(&precoded);
     }
 
     let (message_part, mut parity_part) = (
@@ -144,11 +217,9 @@ impl Code for ReedSolomon {
     let mut cw = msg_gf256.clone();
     cw.extend_from_slice(&dev_gf256.0);
 
-    let postcoded = if self.postcoding.is_some() {
-      // TODO: More efficient precoding scheme
-      self.postcoding.as_ref().unwrap().mul_on_vec_from_right(&cw)
-    } else {
-      cw
+    let postcoded = match &self.postcoding {
+      Some(post) => post.mul_on_vec_from_right(&cw),
+      None => cw,
     };
 
     Ok(Encoded::<Self::Vector>(postcoded.to_u8_vec()))
@@ -241,6 +312,47 @@ mod tests {
     assert_eq!(encoded.0, ans_cw);
   }
 
+  #[tokio::test]
+  async fn precoding_works() {
+    let mut rs = ReedSolomon::new(4, 3).await.unwrap();
+    let data = vec![1u8, 2, 3, 4];
+
+    // permutation swapping the first and the last symbols
+    let perm: Vec<U8VRep> = vec![
+      vec![0, 0, 0, 1],
+      vec![0, 1, 0, 0],
+      vec![0, 0, 1, 0],
+      vec![1, 0, 0, 0],
+    ];
+    assert!(rs.set_precoding(&perm).is_ok());
+    assert!(rs.precoding.as_ref().unwrap().is_permutation());
+    let decoded = rs.decode(&data).unwrap();
+    assert_eq!(decoded.base, vec![4u8, 2, 3]);
+    assert_eq!(rs.encode(&decoded.base, &decoded.deviation).unwrap().0, data);
+
+    let dense: Vec<U8VRep> = vec![
+      vec![1, 0, 0, 0],
+      vec![1, 1, 1, 4],
+      vec![1, 1, 3, 0],
+      vec![1, 2, 0, 0],
+    ];
+    assert!(rs.set_precoding(&dense).is_ok());
+    let decoded = rs.decode(&data).unwrap();
+    assert_eq!(rs.encode(&decoded.base, &decoded.deviation).unwrap().0, data);
+
+    let mut invalid = dense.clone();
+    invalid[2] = vec![1, 1, 3];
+    let err = rs.set_precoding(&invalid).unwrap_err().to_string();
+    assert!(err.contains("row[2] has 3 columns"));
+    let err = rs.set_precoding(&dense[1..]).unwrap_err().to_string();
+    assert!(err.contains("3 rows are given"));
+
+    let mut singular = dense.clone();
+    singular[3] = vec![0, 0, 2, 4];
+    let err = rs.set_precoding(&singular).unwrap_err().to_string();
+    assert!(err.contains("rows [1, 2, 3] are linearly dependent"));
+  }
+
   #[allow(clippy::useless_vec)]
   #[tokio::test]
   async fn new_works() {
@@ -290,4 +402,40 @@ mod tests {
       .unwrap()
     );
   }
+
+  #[cfg(feature = "serde")]
+  #[tokio::test]
+  async fn serde_works() {
+    let mut rs = ReedSolomon::new(N, K).await.unwrap();
+    let mut pre = vec![vec![0u8; N]; N];
+    pre
+      .iter_mut()
+      .enumerate()
+      .for_each(|(i, row)| row[(i + 1) % N] = 1);
+    pre[0][0] = 3;
+    rs.set_precoding(&pre).unwrap();
+
+    let json = serde_json::to_string(&rs).unwrap();
+    let loaded: ReedSolomon = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.generator_matrix_parity, rs.generator_matrix_parity);
+    assert_eq!(loaded.postcoding, rs.postcoding);
+    let data = (0u8..N as u8).collect::<U8VRep>();
+    let decoded = loaded.decode(&data).unwrap();
+    assert_eq!(decoded.base, rs.decode(&data).unwrap().base);
+    assert_eq!(
+      loaded.encode(&decoded.base, &decoded.deviation).unwrap().0,
+      data
+    );
+
+    let mat = Matrix::of_gf256_from_u8(&pre).unwrap();
+    let json = serde_json::to_string(&mat).unwrap();
+    assert_eq!(serde_json::from_str::<Matrix<GF256>>(&json).unwrap(), mat);
+    assert!(serde_json::from_str::<Matrix<GF256>>("[[1,2],[3]]").is_err());
+
+    // invalid parameters and singular precoding are rejected on load
+    let invalid = r#"{"code_symbol_len":4,"info_symbol_len":4}"#;
+    assert!(serde_json::from_str::<ReedSolomon>(invalid).is_err());
+    let singular = r#"{"code_symbol_len":2,"info_symbol_len":1,"precoding":[[1,1],[1,1]]}"#;
+    assert!(serde_json::from_str::<ReedSolomon>(singular).is_err());
+  }
 }
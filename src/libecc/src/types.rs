@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use bitvec::prelude::*;
 
 pub type IdRep = BitVec<u8, Msb0>;
@@ -1,4 +1,5 @@
 use crate::{error::*, types::*};
+use alloc::{borrow::ToOwned, format, string::String};
 use bitvec::prelude::*;
 
 pub fn hexdump_slice(slice: &U8SRep) -> Result<String> {
@@ -0,0 +1,75 @@
+use crate::error::*;
+
+// Policy of the dedup side to emit chunks as raw literals without inserting their bases into the dictionary, which
+// keeps useful entries from being evicted by random or encrypted regions.
+//
+// After `threshold` consecutive misses, i.e., chunks whose bases are not in the dictionary, the input is regarded as
+// incompressible and misses are emitted as raw literals, except every `probe_interval`-th one that is inserted as usual
+// to detect the end of the incompressible span. A hit returns to the normal mode. While the caller hints that the input
+// is incompressible, all misses are emitted as raw literals.
+//
+// A raw literal costs 4 bits of the separator in addition to the chunk, which bounds the expansion in such spans.
+#[derive(Debug, Clone)]
+pub struct LiteralPolicy {
+  threshold: usize,
+  probe_interval: usize,
+  miss_streak: usize,
+  hint: bool,
+}
+
+impl LiteralPolicy {
+  pub fn new(threshold: usize, probe_interval: usize) -> Result<Self> {
+    ensure!(probe_interval > 0, "Probe interval must be positive");
+    Ok(LiteralPolicy {
+      threshold,
+      probe_interval,
+      miss_streak: 0,
+      hint: false,
+    })
+  }
+
+  pub fn set_hint(&mut self, incompressible: bool) {
+    self.hint = incompressible;
+  }
+
+  pub fn is_incompressible(&self) -> bool {
+    self.hint || self.miss_streak >= self.threshold
+  }
+
+  pub fn on_hit(&mut self) {
+    self.miss_streak = 0;
+  }
+
+  // Returns true if the missed chunk is emitted as a raw literal
+  pub fn on_miss(&mut self) -> bool {
+    if self.hint {
+      return true;
+    }
+    let literal = self.is_incompressible()
+      && !(self.miss_streak - self.threshold + 1).is_multiple_of(self.probe_interval);
+    self.miss_streak = self.miss_streak.saturating_add(1);
+    literal
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn literal_policy_works() {
+    let mut policy = LiteralPolicy::new(2, 3).unwrap();
+    // 2 misses to enter, and then every 3rd miss is a probe
+    let literals = (0..8).map(|_| policy.on_miss()).collect::<Vec<_>>();
+    assert_eq!(
+      literals,
+      vec![false, false, true, true, false, true, true, false]
+    );
+    policy.on_hit();
+    assert!(!policy.is_incompressible());
+    assert!(!policy.on_miss());
+
+    policy.set_hint(true);
+    assert!((0..8).all(|_| policy.on_miss()));
+  }
+}
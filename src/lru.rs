@@ -0,0 +1,269 @@
+use crate::error::*;
+use alloc::vec::Vec;
+
+// Node of an id in the LRU order: its neighbours as id + 1, where 0 means none, and the hash of its base
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LruNode {
+  pub prev: u64,
+  pub next: u64,
+  pub hash: u64,
+}
+
+// Storage of nodes indexed by id, e.g., in memory or in a memory-mapped file, where unset nodes are default ones
+pub trait LruNodes: core::fmt::Debug + Send + Sync {
+  fn node(&self, id: usize) -> LruNode;
+  fn set_node(&mut self, id: usize, node: LruNode);
+  // reset all nodes to default ones
+  fn reset(&mut self);
+}
+
+// Nodes growing with the largest id, since ids are assigned from 0
+impl LruNodes for Vec<LruNode> {
+  fn node(&self, id: usize) -> LruNode {
+    self.get(id).copied().unwrap_or_default()
+  }
+  fn set_node(&mut self, id: usize, node: LruNode) {
+    if self.len() <= id {
+      self.resize(id + 1, LruNode::default());
+    }
+    self[id] = node;
+  }
+  fn reset(&mut self) {
+    self.clear();
+  }
+}
+
+// LRU order of ids from the least recently used one with hashes of their bases, which is kept by a dictionary backend
+// so that its memory is that of the backend, e.g., on files for a file-backed one. BasisDict updates it via this trait.
+pub trait LruOrder {
+  fn len(&self) -> usize;
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+  fn contains(&self, id: usize) -> bool;
+  fn front(&self) -> Option<usize>;
+  fn back(&self) -> Option<usize>;
+  fn prev(&self, id: usize) -> Option<usize>;
+  fn next(&self, id: usize) -> Option<usize>;
+  fn hash(&self, id: usize) -> Option<u64>;
+  // insert id not in the order next to prev, or at the front if None
+  fn insert_after(&mut self, prev: Option<usize>, id: usize, hash: u64) -> Result<()>;
+  fn remove(&mut self, id: usize) -> Option<u64>;
+  fn clear(&mut self);
+
+  fn push_back(&mut self, id: usize, hash: u64) -> Result<()> {
+    let back = self.back();
+    self.insert_after(back, id, hash)
+  }
+  fn pop_front(&mut self) -> Option<(usize, u64)> {
+    let id = self.front()?;
+    self.remove(id).map(|hash| (id, hash))
+  }
+  // move id to the back if it is in the order
+  fn to_back(&mut self, id: usize) {
+    if self.back() != Some(id) {
+      if let Some(hash) = self.remove(id) {
+        // never fails for an id just removed
+        let _ = self.push_back(id, hash);
+      }
+    }
+  }
+}
+
+// Doubly-linked list of ids over nodes indexed by id, which takes a node per id and nothing else
+#[derive(Debug, Clone, Default)]
+pub struct LruList<S>
+where
+  S: LruNodes,
+{
+  nodes: S,
+  head: Option<usize>,
+  tail: Option<usize>,
+  len: usize,
+}
+
+fn to_link(id: Option<usize>) -> u64 {
+  id.map_or(0, |id| id as u64 + 1)
+}
+
+fn from_link(link: u64) -> Option<usize> {
+  link.checked_sub(1).map(|id| id as usize)
+}
+
+impl<S> LruList<S>
+where
+  S: LruNodes,
+{
+  // nodes must be default ones
+  pub fn new(nodes: S) -> Self {
+    LruList {
+      nodes,
+      head: None,
+      tail: None,
+      len: 0,
+    }
+  }
+
+  fn set_prev(&mut self, id: usize, prev: Option<usize>) {
+    let node = self.nodes.node(id);
+    self.nodes.set_node(
+      id,
+      LruNode {
+        prev: to_link(prev),
+        ..node
+      },
+    );
+  }
+
+  fn set_next(&mut self, id: usize, next: Option<usize>) {
+    let node = self.nodes.node(id);
+    self.nodes.set_node(
+      id,
+      LruNode {
+        next: to_link(next),
+        ..node
+      },
+    );
+  }
+}
+
+impl<S> LruOrder for LruList<S>
+where
+  S: LruNodes,
+{
+  fn len(&self) -> usize {
+    self.len
+  }
+
+  fn contains(&self, id: usize) -> bool {
+    let node = self.nodes.node(id);
+    node.prev != 0 || node.next != 0 || self.head == Some(id)
+  }
+
+  fn front(&self) -> Option<usize> {
+    self.head
+  }
+
+  fn back(&self) -> Option<usize> {
+    self.tail
+  }
+
+  fn prev(&self, id: usize) -> Option<usize> {
+    from_link(self.nodes.node(id).prev)
+  }
+
+  fn next(&self, id: usize) -> Option<usize> {
+    from_link(self.nodes.node(id).next)
+  }
+
+  fn hash(&self, id: usize) -> Option<u64> {
+    self.contains(id).then(|| self.nodes.node(id).hash)
+  }
+
+  fn insert_after(&mut self, prev: Option<usize>, id: usize, hash: u64) -> Result<()> {
+    ensure!(!self.contains(id), "Id {} is already in the LRU order", id);
+    let next = match prev {
+      Some(prev) => {
+        ensure!(self.contains(prev), "Id {} is not in the LRU order", prev);
+        let next = self.next(prev);
+        self.set_next(prev, Some(id));
+        next
+      }
+      None => {
+        let next = self.head;
+        self.head = Some(id);
+        next
+      }
+    };
+    match next {
+      Some(next) => self.set_prev(next, Some(id)),
+      None => self.tail = Some(id),
+    }
+    self.nodes.set_node(
+      id,
+      LruNode {
+        prev: to_link(prev),
+        next: to_link(next),
+        hash,
+      },
+    );
+    self.len += 1;
+    Ok(())
+  }
+
+  fn remove(&mut self, id: usize) -> Option<u64> {
+    if !self.contains(id) {
+      return None;
+    }
+    let node = self.nodes.node(id);
+    let (prev, next) = (from_link(node.prev), from_link(node.next));
+    match prev {
+      Some(prev) => self.set_next(prev, next),
+      None => self.head = next,
+    }
+    match next {
+      Some(next) => self.set_prev(next, prev),
+      None => self.tail = prev,
+    }
+    self.nodes.set_node(id, LruNode::default());
+    self.len -= 1;
+    Some(node.hash)
+  }
+
+  fn clear(&mut self) {
+    self.nodes.reset();
+    self.head = None;
+    self.tail = None;
+    self.len = 0;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::{collections::VecDeque, vec};
+
+  #[test]
+  fn lru_list_works() {
+    let mut lru = LruList::<Vec<LruNode>>::default();
+    let mut model = VecDeque::new();
+    let ids = |lru: &LruList<Vec<LruNode>>| {
+      core::iter::successors(lru.front(), |id| lru.next(*id)).collect::<VecDeque<_>>()
+    };
+    for id in 0..8 {
+      lru.push_back(id, id as u64 * 10).unwrap();
+      model.push_back(id);
+    }
+    for id in [3, 0, 7, 3, 5] {
+      lru.to_back(id);
+      model.retain(|x| *x != id);
+      model.push_back(id);
+    }
+    assert_eq!(lru.pop_front(), Some((1, 10)));
+    model.pop_front();
+    assert_eq!(ids(&lru), model);
+    assert_eq!(lru.len(), model.len());
+
+    // reinsertion next to the previous neighbour restores the order
+    let prev = lru.prev(4);
+    let hash = lru.remove(4).unwrap();
+    assert!(!lru.contains(4) && lru.hash(4).is_none());
+    lru.insert_after(prev, 4, hash).unwrap();
+    assert_eq!(ids(&lru), model);
+    assert!(lru.insert_after(None, 4, hash).is_err());
+    assert!(lru.insert_after(Some(1), 8, 0).is_err());
+
+    let front = lru.pop_front().unwrap();
+    lru.insert_after(None, front.0, front.1).unwrap();
+    assert_eq!(ids(&lru), model);
+    assert_eq!(
+      model.iter().map(|id| lru.hash(*id).unwrap()).collect::<Vec<_>>(),
+      model.iter().map(|id| *id as u64 * 10).collect::<Vec<_>>()
+    );
+
+    lru.clear();
+    assert!(lru.is_empty() && lru.front().is_none() && !lru.contains(0));
+    lru.push_back(2, 0).unwrap();
+    assert_eq!(ids(&lru), VecDeque::from(vec![2]));
+  }
+}
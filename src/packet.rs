@@ -0,0 +1,382 @@
+use crate::{
+  dict::{bitvec_id_to_usize_id, id_bitlen_of, usize_id_to_bitvec_id},
+  error::*,
+  util::{get_varint, put_varint},
+};
+use libecc::types::*;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// Dictionary for packet mode, where each deduplicated data, i.e., a frame, may be lost or reordered.
+//
+// Bases introduced as AsIs in a frame are not referenced by later frames until the dup side acknowledges the frame.
+// Upon an ack, the dedup side commits the bases of the frame to the dictionary, which increments the dictionary version.
+// Every frame carries the list of commits that the dup side may not have applied yet, and ids in the frame are resolved
+// in the dictionary of the version at which the frame was deduplicated. So, a lost frame never breaks other frames.
+//
+// Header of a frame in varint:
+// [seq][version][floor][commit num = version - floor][seq of the frame committed at each version of floor+1..=version]
+// where floor is the version that the dup side is known to have applied.
+//
+// Ids are assigned to committed bases cyclically, i.e., the i-th committed base has id (i mod dict_size).
+// The dup side keeps bases overwritten after its floor so that reordered frames can be resolved.
+#[derive(Debug, Clone)]
+pub struct PacketDict<T>
+where
+  T: std::cmp::Eq + std::hash::Hash + std::clone::Clone + std::fmt::Debug,
+{
+  dict_size: usize,
+  id_bitlen: usize,
+  // frames older than the latest seq by window are forgotten
+  window: usize,
+  // (base index, base) for each id, where the last one is the current base. Slots are added as ids are assigned,
+  // i.e., up to the number of committed bases, so that a large dictionary costs nothing until it is filled.
+  slots: Vec<VecDeque<(usize, T)>>,
+  base_to_id: HashMap<T, usize>,
+  // number of committed bases
+  base_num: usize,
+  // number of commits
+  version: usize,
+  // version that the dup side is known to have applied
+  floor: usize,
+  // seq of the frames committed at versions of floor+1..=version
+  commits: VecDeque<usize>,
+  // base_num at each version from version_offset
+  version_base_num: VecDeque<usize>,
+  version_offset: usize,
+  // seq of the next frame (dedup side)
+  next_seq: usize,
+  // uncommitted bases of sent (dedup side) or received (dup side) frames
+  frames: BTreeMap<usize, Vec<T>>,
+  // version at which each frame was sent (dedup side)
+  sent_versions: BTreeMap<usize, usize>,
+  // (seq, version, bases) of the frame being processed
+  current: Option<(usize, usize, Vec<T>)>,
+}
+
+impl<T> PacketDict<T>
+where
+  T: std::cmp::Eq + std::hash::Hash + std::clone::Clone + std::fmt::Debug,
+{
+  pub fn new(dict_size: usize, window: usize) -> Result<Self> {
+    ensure!(window > 0, "Window must be positive");
+    Ok(PacketDict {
+      dict_size,
+      id_bitlen: id_bitlen_of(dict_size),
+      window,
+      slots: Vec::new(),
+      base_to_id: HashMap::new(),
+      base_num: 0,
+      version: 0,
+      floor: 0,
+      commits: VecDeque::new(),
+      version_base_num: VecDeque::from(vec![0]),
+      version_offset: 0,
+      next_seq: 0,
+      frames: BTreeMap::new(),
+      sent_versions: BTreeMap::new(),
+      current: None,
+    })
+  }
+
+  // call in dedup before processing a frame
+  pub fn begin_dedup_frame(&mut self) -> U8VRep {
+    let seq = self.next_seq;
+    self.next_seq += 1;
+    self.current = Some((seq, self.version, Vec::new()));
+
+    let mut res = U8VRep::new();
+    put_varint(&mut res, seq);
+    put_varint(&mut res, self.version);
+    put_varint(&mut res, self.floor);
+    put_varint(&mut res, self.commits.len());
+    self.commits.iter().for_each(|s| put_varint(&mut res, *s));
+    res
+  }
+
+  // call in dup before processing a frame. Returns the byte length of the header.
+  pub fn begin_dup_frame(&mut self, data: &U8SRep) -> Result<usize> {
+    let mut ptr = 0usize;
+    let mut values = [0usize; 4];
+    for v in values.iter_mut() {
+      let (value, len) = get_varint(&data[ptr..])?;
+      *v = value;
+      ptr += len;
+    }
+    let [seq, version, floor, commit_num] = values;
+    ensure!(
+      floor.checked_add(commit_num) == Some(version),
+      "Invalid packet header: {} commits from version {} to {}",
+      commit_num,
+      floor,
+      version
+    );
+    // each commit takes at least a byte
+    ensure!(
+      commit_num <= data.len() - ptr,
+      "Invalid packet header: {} commits in {} bytes",
+      commit_num,
+      data.len() - ptr
+    );
+    let mut commits = Vec::new();
+    for _ in 0..commit_num {
+      let (value, len) = get_varint(&data[ptr..])?;
+      commits.push(value);
+      ptr += len;
+    }
+
+    // apply commits not yet applied
+    ensure!(
+      floor <= self.version,
+      "Commits from version {} to {} are missing",
+      self.version,
+      floor
+    );
+    for commit_seq in commits[(self.version - floor).min(commit_num)..].iter() {
+      let bases = self.frames.remove(commit_seq).ok_or(anyhow!(
+        "Bases of frame {} to be committed are missing",
+        commit_seq
+      ))?;
+      self.commit(bases);
+    }
+    ensure!(
+      version >= self.version_offset,
+      "Frame {} is too old to be resolved",
+      seq
+    );
+    self.prune_history(floor);
+
+    self.current = Some((seq, version, Vec::new()));
+    Ok(ptr)
+  }
+
+  // call in dedup
+  pub fn get_id(&self, base: &T) -> Option<IdRep> {
+    self
+      .base_to_id
+      .get(base)
+      .map(|id| usize_id_to_bitvec_id(*id, self.id_bitlen))
+  }
+
+  // call in dup when id is given
+  pub fn get_base(&self, bit_id: &IdSRep) -> Result<T> {
+    let id = bitvec_id_to_usize_id(bit_id);
+    let version = self
+      .current
+      .as_ref()
+      .map(|(_, v, _)| *v)
+      .unwrap_or(self.version);
+    let base_num = self.version_base_num[version - self.version_offset];
+    self
+      .slots
+      .get(id)
+      .and_then(|slot| slot.iter().rev().find(|(idx, _)| *idx < base_num))
+      .map(|(_, base)| base.to_owned())
+      .ok_or(anyhow!("Invalid dictionary"))
+  }
+
+  // call in both dedup and dup when a base is given as is
+  pub fn put_base(&mut self, base: &T) {
+    if let Some((_, _, bases)) = self.current.as_mut() {
+      bases.push(base.to_owned());
+    }
+  }
+
+  // call after a frame is successfully processed
+  pub fn end_frame(&mut self) {
+    if let Some((seq, version, bases)) = self.current.take() {
+      self.frames.insert(seq, bases);
+      self.sent_versions.insert(seq, version);
+      // forget frames out of the window
+      let latest = *self.frames.keys().next_back().unwrap_or(&seq);
+      self
+        .frames
+        .retain(|s, _| s.saturating_add(self.window) > latest);
+      self
+        .sent_versions
+        .retain(|s, _| s.saturating_add(self.window) > latest);
+    }
+  }
+
+  pub fn packet_seq(data: &U8SRep) -> Result<usize> {
+    get_varint(data).map(|(seq, _)| seq)
+  }
+
+  // call in dedup side when the dup side acknowledged the frame of seq
+  pub fn ack(&mut self, seq: usize) -> Result<()> {
+    ensure!(seq < self.next_seq, "Frame {} has not been sent", seq);
+    if let Some(sent_version) = self.sent_versions.remove(&seq) {
+      self.floor = self.floor.max(sent_version);
+      while self.commits.len() > self.version - self.floor {
+        self.commits.pop_front();
+      }
+    }
+    match self.frames.remove(&seq) {
+      Some(bases) if !bases.is_empty() => {
+        self.commit(bases);
+        self.commits.push_back(seq);
+      }
+      _ => (),
+    }
+    self.prune_history(self.floor);
+    Ok(())
+  }
+
+  // call in dedup side when the dup side reported a loss of the frame of seq
+  pub fn nack(&mut self, seq: usize) -> Result<()> {
+    ensure!(seq < self.next_seq, "Frame {} has not been sent", seq);
+    self.frames.remove(&seq);
+    self.sent_versions.remove(&seq);
+    Ok(())
+  }
+
+  fn commit(&mut self, bases: Vec<T>) {
+    for base in bases {
+      if self.base_to_id.contains_key(&base) {
+        continue;
+      }
+      let id = self.base_num % self.dict_size;
+      // ids are assigned in order until they wrap around
+      if id == self.slots.len() {
+        self.slots.push(VecDeque::new());
+      }
+      if let Some((_, old)) = self.slots[id].back() {
+        if self.base_to_id.get(old) == Some(&id) {
+          self.base_to_id.remove(&old.to_owned());
+        }
+      }
+      self.slots[id].push_back((self.base_num, base.to_owned()));
+      self.base_to_id.insert(base, id);
+      self.base_num += 1;
+    }
+    self.version += 1;
+    self.version_base_num.push_back(self.base_num);
+  }
+
+  // drop bases overwritten before the floor, which are never referenced by frames sent after the floor
+  fn prune_history(&mut self, floor: usize) {
+    if floor <= self.version_offset {
+      return;
+    }
+    let floor_base_num = self.version_base_num[floor - self.version_offset];
+    for slot in self.slots.iter_mut() {
+      while slot.len() > 1 && slot[1].0 <= floor_base_num {
+        slot.pop_front();
+      }
+    }
+    self.version_base_num.drain(..floor - self.version_offset);
+    self.version_offset = floor;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn packet_dict_works() {
+    let mut dedup = PacketDict::<U8VRep>::new(2, 8).unwrap();
+    let mut dup = PacketDict::<U8VRep>::new(2, 8).unwrap();
+
+    // frame 0 introduces base a
+    let h0 = dedup.begin_dedup_frame();
+    assert!(dedup.get_id(&vec![0xa]).is_none());
+    dedup.put_base(&vec![0xa]);
+    dedup.end_frame();
+    assert_eq!(dup.begin_dup_frame(&h0).unwrap(), h0.len());
+    dup.put_base(&vec![0xa]);
+    dup.end_frame();
+    dedup.ack(0).unwrap();
+
+    // frame 1 introduces base b but is lost
+    let _h1 = dedup.begin_dedup_frame();
+    dedup.put_base(&vec![0xb]);
+    dedup.end_frame();
+    dedup.nack(1).unwrap();
+
+    // frame 2 refers to base a and introduces c and d, and frame 3 refers to a
+    let h2 = dedup.begin_dedup_frame();
+    let id_a = dedup.get_id(&vec![0xa]).unwrap();
+    assert!(dedup.get_id(&vec![0xb]).is_none());
+    dedup.put_base(&vec![0xc]);
+    dedup.put_base(&vec![0xd]);
+    dedup.end_frame();
+    let h3 = dedup.begin_dedup_frame();
+    dedup.end_frame();
+
+    // frame 3 arrives earlier than frame 2
+    dup.begin_dup_frame(&h3).unwrap();
+    assert_eq!(dup.get_base(&id_a).unwrap(), vec![0xa]);
+    dup.end_frame();
+    dup.begin_dup_frame(&h2).unwrap();
+    assert_eq!(dup.get_base(&id_a).unwrap(), vec![0xa]);
+    dup.put_base(&vec![0xc]);
+    dup.put_base(&vec![0xd]);
+    dup.end_frame();
+    dedup.ack(3).unwrap();
+    dedup.ack(2).unwrap();
+
+    // frame 4 refers to d that overwrote a
+    let h4 = dedup.begin_dedup_frame();
+    assert!(dedup.get_id(&vec![0xa]).is_none());
+    let id_d = dedup.get_id(&vec![0xd]).unwrap();
+    assert_eq!(id_a, id_d);
+    dedup.end_frame();
+    dup.begin_dup_frame(&h4).unwrap();
+    assert_eq!(dup.get_base(&id_d).unwrap(), vec![0xd]);
+    dup.end_frame();
+    assert_eq!(PacketDict::<U8VRep>::packet_seq(&h4).unwrap(), 4);
+
+    // a delayed frame is resolved in the dictionary of its version
+    dup.begin_dup_frame(&h3).unwrap();
+    assert_eq!(dup.get_base(&id_a).unwrap(), vec![0xa]);
+    assert_eq!(dup.slots.len(), 2);
+  }
+
+  #[test]
+  fn packet_dict_grows_slots_lazily() {
+    // no slot is allocated for ids not yet assigned
+    let mut dedup = PacketDict::<U8VRep>::new(1 << 40, 8).unwrap();
+    let mut dup = PacketDict::<U8VRep>::new(1 << 40, 8).unwrap();
+    assert!(dedup.slots.is_empty());
+
+    let h0 = dedup.begin_dedup_frame();
+    dedup.put_base(&vec![0xa]);
+    dedup.put_base(&vec![0xb]);
+    dedup.end_frame();
+    dup.begin_dup_frame(&h0).unwrap();
+    dup.put_base(&vec![0xa]);
+    dup.put_base(&vec![0xb]);
+    dup.end_frame();
+    dedup.ack(0).unwrap();
+
+    let h1 = dedup.begin_dedup_frame();
+    let id_b = dedup.get_id(&vec![0xb]).unwrap();
+    dedup.end_frame();
+    dup.begin_dup_frame(&h1).unwrap();
+    assert_eq!(dup.get_base(&id_b).unwrap(), vec![0xb]);
+    assert_eq!((dedup.slots.len(), dup.slots.len()), (2, 2));
+    // unassigned ids are unknown
+    assert!(dup.get_base(&usize_id_to_bitvec_id(2, dup.id_bitlen)).is_err());
+  }
+
+  #[test]
+  fn packet_dict_rejects_broken_header() {
+    let mut dup = PacketDict::<U8VRep>::new(2, 8).unwrap();
+    let header = |values: &[usize]| {
+      let mut res = U8VRep::new();
+      values.iter().for_each(|v| put_varint(&mut res, *v));
+      res
+    };
+    // commits more than the remaining bytes
+    let huge = (1 << 63) - 1;
+    assert!(dup.begin_dup_frame(&header(&[0, huge, 0, huge])).is_err());
+    // overflowing version
+    assert!(dup
+      .begin_dup_frame(&header(&[0, 0, usize::MAX, 1]))
+      .is_err());
+    assert!(dup.begin_dup_frame(&header(&[usize::MAX, 0, 0, 0])).is_ok());
+    dup.end_frame();
+    assert!(dup.begin_dup_frame(&header(&[0, 0, 0, 0])).is_ok());
+    dup.end_frame();
+  }
+}
@@ -0,0 +1,291 @@
+use crate::{
+  error::*,
+  util::{get_varint, put_varint},
+  Deduped, GDInner, GD,
+};
+use libecc::types::*;
+
+// A field of a fixed-layout record, i.e., bytes [offset, offset + bytelen) of each record, that is processed by gd_idx-th GD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordField {
+  pub offset: usize,
+  pub bytelen: usize,
+  pub gd_idx: usize,
+}
+
+// Schema of fixed-size records. Fields must cover each record without overlaps.
+// Each GD is given with its dictionary size, and fields assigned to the same GD are concatenated record by record.
+#[derive(Debug, Clone)]
+pub struct RecordSchema {
+  pub record_bytelen: usize,
+  pub fields: Vec<RecordField>,
+  pub gds: Vec<(GD, usize)>,
+}
+
+impl RecordSchema {
+  pub async fn setup(&self) -> Result<RecordGD> {
+    self.validate()?;
+    let mut inners = Vec::with_capacity(self.gds.len());
+    for (gd, dict_size) in self.gds.iter() {
+      inners.push(gd.setup(*dict_size).await?);
+    }
+    Ok(RecordGD {
+      schema: self.clone(),
+      inners,
+    })
+  }
+
+  fn validate(&self) -> Result<()> {
+    ensure!(self.record_bytelen > 0, "Record length must be positive");
+    let mut covered = vec![false; self.record_bytelen];
+    for (i, field) in self.fields.iter().enumerate() {
+      ensure!(field.bytelen > 0, "field[{}] is empty", i);
+      ensure!(
+        field
+          .offset
+          .checked_add(field.bytelen)
+          .is_some_and(|end| end <= self.record_bytelen),
+        "field[{}] exceeds the record length {}",
+        i,
+        self.record_bytelen
+      );
+      ensure!(
+        field.gd_idx < self.gds.len(),
+        "field[{}] refers to gd[{}] but {} GDs are given",
+        i,
+        field.gd_idx,
+        self.gds.len()
+      );
+      for b in covered[field.offset..field.offset + field.bytelen].iter_mut() {
+        ensure!(!*b, "field[{}] overlaps with another field", i);
+        *b = true;
+      }
+    }
+    if let Some(pos) = covered.iter().position(|b| !*b) {
+      bail!("Byte {} of the record is not covered by any field", pos);
+    }
+    for gd_idx in 0..self.gds.len() {
+      ensure!(
+        self.fields.iter().any(|f| f.gd_idx == gd_idx),
+        "gd[{}] has no field",
+        gd_idx
+      );
+    }
+    Ok(())
+  }
+}
+
+// GD instances multiplexed over fields of fixed-size records.
+// Deduplicated data is a single stream: [record num][last chunk pad, data length, data] for each GD in order, all in varint.
+#[derive(Debug)]
+pub struct RecordGD {
+  schema: RecordSchema,
+  inners: Vec<GDInner>,
+}
+
+impl RecordGD {
+  // Copy of the instance, which fails if dictionaries of its GD instances cannot be copied
+  pub fn try_clone(&self) -> Result<Self> {
+    Ok(RecordGD {
+      schema: self.schema.clone(),
+      inners: self.inners.iter().map(GDInner::try_clone).collect::<Result<_>>()?,
+    })
+  }
+
+  // Access to each GD instance, e.g., to set error alignment
+  pub fn gd_mut(&mut self, gd_idx: usize) -> Option<&mut GDInner> {
+    self.inners.get_mut(gd_idx)
+  }
+
+  pub async fn dedup(&mut self, buf: &U8SRep) -> Result<Deduped> {
+    let record_bytelen = self.schema.record_bytelen;
+    ensure!(
+      buf.len().is_multiple_of(record_bytelen),
+      "Data length {} is not a multiple of the record length {}",
+      buf.len(),
+      record_bytelen
+    );
+    let record_num = buf.len() / record_bytelen;
+
+    let mut streams = vec![U8VRep::new(); self.inners.len()];
+    for record in buf.chunks_exact(record_bytelen) {
+      for field in self.schema.fields.iter() {
+        streams[field.gd_idx]
+          .extend_from_slice(&record[field.offset..field.offset + field.bytelen]);
+      }
+    }
+
+    let mut data = U8VRep::new();
+    put_varint(&mut data, record_num);
+    for (inner, stream) in self.inners.iter_mut().zip(streams.iter()) {
+      let deduped = inner.dedup(stream).await?;
+      put_varint(&mut data, deduped.last_chunk_pad_bytelen);
+      put_varint(&mut data, deduped.data.len());
+      data.extend_from_slice(&deduped.data);
+    }
+
+    Ok(Deduped {
+      data,
+      last_chunk_pad_bytelen: 0,
+    })
+  }
+
+  pub async fn dup(&mut self, deduped: &Deduped) -> Result<U8VRep> {
+    let (record_num, mut ptr) = get_varint(&deduped.data)?;
+
+    let mut streams = Vec::with_capacity(self.inners.len());
+    for inner in self.inners.iter_mut() {
+      let (last_chunk_pad_bytelen, len) = get_varint(&deduped.data[ptr..])?;
+      ptr += len;
+      let (data_bytelen, len) = get_varint(&deduped.data[ptr..])?;
+      ptr += len;
+      ensure!(
+        ptr
+          .checked_add(data_bytelen)
+          .is_some_and(|end| end <= deduped.data.len()),
+        "Truncated record stream"
+      );
+      let part = Deduped {
+        data: deduped.data[ptr..ptr + data_bytelen].to_vec(),
+        last_chunk_pad_bytelen,
+      };
+      ptr += data_bytelen;
+      streams.push(inner.dup(&part).await?);
+    }
+    ensure!(ptr == deduped.data.len(), "Trailing bytes in record stream");
+
+    // every stream must consist of the fields of record_num records, which bounds the output by the duplicated streams
+    for (gd_idx, stream) in streams.iter().enumerate() {
+      let field_bytelen: usize = self
+        .schema
+        .fields
+        .iter()
+        .filter(|f| f.gd_idx == gd_idx)
+        .map(|f| f.bytelen)
+        .sum();
+      ensure!(
+        record_num.checked_mul(field_bytelen) == Some(stream.len()),
+        "Data for gd[{}] is inconsistent with {} records",
+        gd_idx,
+        record_num
+      );
+    }
+
+    let record_bytelen = self.schema.record_bytelen;
+    let res_bytelen = record_num
+      .checked_mul(record_bytelen)
+      .ok_or(anyhow!("Invalid record number {}", record_num))?;
+    let mut stream_ptrs = vec![0usize; streams.len()];
+    let mut res = vec![0u8; res_bytelen];
+    for record in res.chunks_exact_mut(record_bytelen) {
+      for field in self.schema.fields.iter() {
+        let stream = &streams[field.gd_idx];
+        let stream_ptr = stream_ptrs[field.gd_idx];
+        ensure!(
+          stream_ptr + field.bytelen <= stream.len(),
+          "Insufficient data for gd[{}]",
+          field.gd_idx
+        );
+        record[field.offset..field.offset + field.bytelen]
+          .copy_from_slice(&stream[stream_ptr..stream_ptr + field.bytelen]);
+        stream_ptrs[field.gd_idx] += field.bytelen;
+      }
+    }
+    ensure!(
+      stream_ptrs
+        .iter()
+        .zip(streams.iter())
+        .all(|(p, s)| *p == s.len()),
+      "Excess data in record stream"
+    );
+
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  // 24-byte record: timestamp (8 bytes), device id (4 bytes), 6 readings of 2 bytes
+  fn schema() -> RecordSchema {
+    RecordSchema {
+      record_bytelen: 24,
+      fields: vec![
+        RecordField {
+          offset: 0,
+          bytelen: 8,
+          gd_idx: 0,
+        },
+        RecordField {
+          offset: 8,
+          bytelen: 4,
+          gd_idx: 0,
+        },
+        RecordField {
+          offset: 12,
+          bytelen: 12,
+          gd_idx: 1,
+        },
+      ],
+      gds: vec![(GD::ReedSolomon(4, 3), 255), (GD::ReedSolomon(6, 4), 255)],
+    }
+  }
+
+  #[tokio::test]
+  async fn record_works() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let records: U8VRep = (0..1024u64)
+      .flat_map(|i| {
+        let mut record = (1_700_000_000u64 + i / 16).to_be_bytes().to_vec();
+        record.extend_from_slice(&[0xDE, 0xAD, 0xBE, (i % 4) as u8]);
+        for _ in 0..6 {
+          record.extend_from_slice(&[0x01, 0x20 | rng.gen_range(0u8..4)]);
+        }
+        record
+      })
+      .collect();
+
+    let mut gd_dedup = schema().setup().await.unwrap();
+    let mut gd_dup = schema().setup().await.unwrap();
+    let deduped = gd_dedup.dedup(&records).await.unwrap();
+    let duped = gd_dup.dup(&deduped).await.unwrap();
+    assert_eq!(duped, records);
+    println!(
+      "Record > Deduped {} bytes -> {} bytes",
+      records.len(),
+      deduped.data.len()
+    );
+    assert!(deduped.data.len() < records.len());
+
+    assert!(gd_dedup.dedup(&records[1..]).await.is_err());
+
+    // record number inconsistent with the streams, which must not be allocated
+    let mut broken = U8VRep::new();
+    put_varint(&mut broken, usize::MAX / 2);
+    let (_, len) = get_varint(&deduped.data).unwrap();
+    broken.extend_from_slice(&deduped.data[len..]);
+    let broken = Deduped {
+      data: broken,
+      last_chunk_pad_bytelen: 0,
+    };
+    assert!(schema().setup().await.unwrap().dup(&broken).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn invalid_schema_is_rejected() {
+    let mut s = schema();
+    s.fields[1].bytelen = 5;
+    assert!(s.setup().await.is_err());
+    let mut s = schema();
+    s.fields.pop();
+    assert!(s.setup().await.is_err());
+    let mut s = schema();
+    s.fields[2].gd_idx = 2;
+    assert!(s.setup().await.is_err());
+    let mut s = schema();
+    s.fields[2].offset = usize::MAX;
+    assert!(s.setup().await.is_err());
+  }
+}
@@ -0,0 +1,189 @@
+use crate::{
+  dict::BasisDict,
+  error::*,
+  util::{get_varint, put_varint},
+};
+use libecc::types::*;
+
+// In-band control codes placed at the head of every deduplicated data to detect and recover from desynchronization
+// of dictionaries between dedup and dup sides:
+// [flags (1 byte)][epoch (varint)][frame number in the epoch (varint)][dictionary digest (8 bytes, LE) if DIGEST]
+// where the digest is computed over the dictionary just before the frame is processed.
+const FLAG_RESET: u8 = 0x01;
+const FLAG_DIGEST: u8 = 0x02;
+
+#[derive(Debug, Clone)]
+pub struct StreamSync {
+  reset_interval: Option<usize>, // dictionary reset every reset_interval frames
+  digest_interval: usize,        // dictionary digest every digest_interval frames
+  epoch: usize,
+  frame: usize,
+  reset_requested: bool,
+  desynced: bool,
+}
+
+impl StreamSync {
+  pub fn new(reset_interval: Option<usize>, digest_interval: usize) -> Result<Self> {
+    ensure!(
+      reset_interval.is_none_or(|r| r > 0) && digest_interval > 0,
+      "Intervals must be positive"
+    );
+    Ok(StreamSync {
+      reset_interval,
+      digest_interval,
+      epoch: 0,
+      frame: 0,
+      reset_requested: true, // the first frame always resets the dictionary
+      desynced: false,
+    })
+  }
+
+  pub fn is_desynced(&self) -> bool {
+    self.desynced
+  }
+  pub fn request_reset(&mut self) {
+    self.reset_requested = true;
+  }
+
+  // call in dedup before processing a frame
+  pub fn encode_control<T>(&mut self, dict: &mut BasisDict<T>) -> U8VRep
+  where
+    T: std::cmp::Eq + std::hash::Hash + std::clone::Clone + std::fmt::Debug,
+  {
+    let periodic_reset = self.reset_interval.is_some_and(|r| self.frame >= r);
+    let mut flags = 0u8;
+    if self.reset_requested || periodic_reset {
+      self.epoch += 1;
+      self.frame = 0;
+      self.reset_requested = false;
+      dict.clear();
+      flags |= FLAG_RESET;
+    }
+    if self.frame.is_multiple_of(self.digest_interval) {
+      flags |= FLAG_DIGEST;
+    }
+
+    let mut res = vec![flags];
+    put_varint(&mut res, self.epoch);
+    put_varint(&mut res, self.frame);
+    if flags & FLAG_DIGEST > 0 {
+      res.extend_from_slice(&dict.digest().to_le_bytes());
+    }
+    self.frame += 1;
+    res
+  }
+
+  // call in dup before processing a frame. Returns the byte length of control codes.
+  pub fn decode_control<T>(&mut self, data: &U8SRep, dict: &mut BasisDict<T>) -> Result<usize>
+  where
+    T: std::cmp::Eq + std::hash::Hash + std::clone::Clone + std::fmt::Debug,
+  {
+    ensure!(!data.is_empty(), "Missing control codes");
+    let flags = data[0];
+    let mut ptr = 1usize;
+    let (epoch, len) = get_varint(&data[ptr..])?;
+    ptr += len;
+    let (frame, len) = get_varint(&data[ptr..])?;
+    ptr += len;
+    // checked before a reset clears the dictionary, since the frame number of a reset is not compared
+    let next_frame = frame
+      .checked_add(1)
+      .ok_or_else(|| anyhow!("Invalid frame number {}", frame))?;
+
+    if flags & FLAG_RESET > 0 {
+      dict.clear();
+      self.epoch = epoch;
+      self.desynced = false;
+    } else if self.desynced {
+      bail!("Desynchronized dictionary: waiting for a dictionary reset");
+    } else if epoch != self.epoch || frame != self.frame {
+      self.desynced = true;
+      bail!(
+        "Desynchronized dictionary: frame {} of epoch {} is given but frame {} of epoch {} is expected",
+        frame,
+        epoch,
+        self.frame,
+        self.epoch
+      );
+    }
+
+    if flags & FLAG_DIGEST > 0 {
+      ensure!(data.len() >= ptr + 8, "Truncated dictionary digest");
+      let digest = u64::from_le_bytes(data[ptr..ptr + 8].try_into()?);
+      ptr += 8;
+      if digest != dict.digest() {
+        self.desynced = true;
+        bail!(
+          "Desynchronized dictionary: digest mismatch at frame {} of epoch {}",
+          frame,
+          epoch
+        );
+      }
+    }
+    self.frame = next_frame;
+    Ok(ptr)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn resync_works() {
+    let mut dict_dedup = BasisDict::<U8VRep>::new(4);
+    let mut dict_dup = BasisDict::<U8VRep>::new(4);
+    let mut sync_dedup = StreamSync::new(Some(3), 1).unwrap();
+    let mut sync_dup = StreamSync::new(None, 1).unwrap();
+
+    // frame 0 and 1 of epoch 1
+    for base in [vec![0u8], vec![1u8]] {
+      let control = sync_dedup.encode_control(&mut dict_dedup);
+      assert_eq!(
+        sync_dup.decode_control(&control, &mut dict_dup).unwrap(),
+        control.len()
+      );
+      dict_dedup.put_base(&base).unwrap();
+      dict_dup.put_base(&base).unwrap();
+    }
+    // frame 2 is lost and the dictionary of dup side is outdated
+    let _lost = sync_dedup.encode_control(&mut dict_dedup);
+    dict_dedup.put_base(&vec![2u8]).unwrap();
+    // frame 0 of epoch 2 resets both dictionaries
+    let control = sync_dedup.encode_control(&mut dict_dedup);
+    assert!(sync_dup.decode_control(&control, &mut dict_dup).is_ok());
+    assert_eq!(sync_dup.epoch, 2);
+
+    // frame 1 is lost, and frame 2 is detected by its frame number and then rejected until a reset
+    dict_dedup.put_base(&vec![3u8]).unwrap();
+    let _lost = sync_dedup.encode_control(&mut dict_dedup);
+    let control = sync_dedup.encode_control(&mut dict_dedup);
+    assert!(sync_dup.decode_control(&control, &mut dict_dup).is_err());
+    assert!(sync_dup.is_desynced());
+    sync_dedup.request_reset();
+    let control = sync_dedup.encode_control(&mut dict_dedup);
+    assert!(sync_dup.decode_control(&control, &mut dict_dup).is_ok());
+    assert!(!sync_dup.is_desynced());
+
+    // digest detects the difference of dictionaries
+    dict_dedup.put_base(&vec![4u8]).unwrap();
+    dict_dup.put_base(&vec![5u8]).unwrap();
+    let control = sync_dedup.encode_control(&mut dict_dedup);
+    assert!(sync_dup.decode_control(&control, &mut dict_dup).is_err());
+  }
+
+  #[test]
+  fn resync_rejects_last_frame_number() {
+    let mut dict = BasisDict::<U8VRep>::new(4);
+    dict.put_base(&vec![0u8]).unwrap();
+    let digest = dict.digest();
+    let mut sync = StreamSync::new(None, 1).unwrap();
+    // a reset of frame usize::MAX, whose frame number is not compared with the expected one
+    let mut control = vec![FLAG_RESET];
+    put_varint(&mut control, 1);
+    put_varint(&mut control, usize::MAX);
+    assert!(sync.decode_control(&control, &mut dict).is_err());
+    // the dictionary is kept
+    assert_eq!(dict.digest(), digest);
+  }
+}
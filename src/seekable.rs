@@ -0,0 +1,444 @@
+use crate::{
+  error::*,
+  util::{get_varint, put_varint},
+  Deduped, GDInner,
+};
+use libecc::types::*;
+use std::{
+  io::{Read, Seek, SeekFrom, Write},
+  ops::Range,
+};
+
+// Seekable format:
+// [MAGIC][block 0]...[block N-1][index][index bytelen (u64, LE)][MAGIC]
+// where each block is deduplicated from an empty dictionary, i.e., a checkpoint is placed at the head of every block.
+// The index consists of the number of blocks and (raw bytelen, deduped bytelen, last chunk pad bytelen) of each block in varint.
+const MAGIC: &[u8; 8] = b"RGDSEEK1";
+const FOOTER_BYTELEN: usize = 8 + MAGIC.len();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BlockEntry {
+  raw_offset: u64,
+  raw_bytelen: usize,
+  deduped_offset: u64,
+  deduped_bytelen: usize,
+  last_chunk_pad_bytelen: usize,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Writer of the seekable format. The dictionary is reset every checkpoint_bytelen bytes of the input.
+// The underlying writer is a blocking std::io::Write called from async methods, which blocks the executor thread during
+// writes, e.g., to a local file. Run it on a thread where blocking is allowed for slow writers.
+pub struct SeekableGdWriter<W: Write> {
+  inner: W,
+  gd: GDInner,
+  checkpoint_bytelen: usize,
+  buffered: U8VRep,
+  entries: Vec<BlockEntry>,
+  raw_offset: u64,
+  deduped_offset: u64,
+}
+
+impl<W: Write> SeekableGdWriter<W> {
+  pub fn new(mut inner: W, gd: GDInner, checkpoint_bytelen: usize) -> Result<Self> {
+    // every block must be deduplicated only from the dictionary dropped by reset_dict
+    ensure!(
+      !gd.is_dict_kept_across_resets(),
+      "Seekable format is not available with dictionary synchronization, packet mode and shared dictionary"
+    );
+    let chunk_bytelen = gd.chunk_bytelen();
+    ensure!(
+      checkpoint_bytelen > 0 && checkpoint_bytelen.is_multiple_of(chunk_bytelen),
+      "Checkpoint interval {} must be a positive multiple of the chunk length {}",
+      checkpoint_bytelen,
+      chunk_bytelen
+    );
+    inner.write_all(MAGIC)?;
+    Ok(SeekableGdWriter {
+      inner,
+      gd,
+      checkpoint_bytelen,
+      buffered: U8VRep::new(),
+      entries: Vec::new(),
+      raw_offset: 0,
+      deduped_offset: MAGIC.len() as u64,
+    })
+  }
+
+  pub async fn write(&mut self, buf: &U8SRep) -> Result<()> {
+    self.buffered.extend_from_slice(buf);
+    // blocks are written in place and the buffer is shifted only once
+    let buffered = std::mem::take(&mut self.buffered);
+    let mut start = 0;
+    let res = async {
+      while buffered.len() - start >= self.checkpoint_bytelen {
+        self
+          .write_block(&buffered[start..start + self.checkpoint_bytelen])
+          .await?;
+        start += self.checkpoint_bytelen;
+      }
+      Ok(())
+    }
+    .await;
+    self.buffered = buffered;
+    self.buffered.drain(..start);
+    res
+  }
+
+  // Flush the buffered data and write the index trailer. Returns the underlying writer.
+  pub async fn finish(mut self) -> Result<W> {
+    if !self.buffered.is_empty() {
+      let block = std::mem::take(&mut self.buffered);
+      self.write_block(&block).await?;
+    }
+
+    let mut index = U8VRep::new();
+    put_varint(&mut index, self.entries.len());
+    for entry in self.entries.iter() {
+      put_varint(&mut index, entry.raw_bytelen);
+      put_varint(&mut index, entry.deduped_bytelen);
+      put_varint(&mut index, entry.last_chunk_pad_bytelen);
+    }
+    self.inner.write_all(&index)?;
+    self.inner.write_all(&(index.len() as u64).to_le_bytes())?;
+    self.inner.write_all(MAGIC)?;
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+
+  async fn write_block(&mut self, block: &U8SRep) -> Result<()> {
+    self.gd.reset_dict();
+    let deduped = self.gd.dedup(block).await?;
+    self.inner.write_all(&deduped.data)?;
+    self.entries.push(BlockEntry {
+      raw_offset: self.raw_offset,
+      raw_bytelen: block.len(),
+      deduped_offset: self.deduped_offset,
+      deduped_bytelen: deduped.data.len(),
+      last_chunk_pad_bytelen: deduped.last_chunk_pad_bytelen,
+    });
+    self.raw_offset += block.len() as u64;
+    self.deduped_offset += deduped.data.len() as u64;
+    Ok(())
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Reader of the seekable format, which decodes only blocks overlapping with the requested range.
+// As SeekableGdWriter, the underlying reader is a blocking std::io::Read + Seek called from async methods.
+pub struct SeekableGdReader<R: Read + Seek> {
+  inner: R,
+  gd: GDInner,
+  entries: Vec<BlockEntry>,
+  checkpoint_bytelen: usize,
+  raw_bytelen: u64,
+  pos: u64,
+  cached: Option<(usize, U8VRep)>, // (block index, duped block)
+}
+
+impl<R: Read + Seek> SeekableGdReader<R> {
+  pub fn new(mut inner: R, gd: GDInner) -> Result<Self> {
+    ensure!(
+      !gd.is_dict_kept_across_resets(),
+      "Seekable format is not available with dictionary synchronization, packet mode and shared dictionary"
+    );
+    let mut magic = [0u8; 8];
+    inner.seek(SeekFrom::Start(0))?;
+    inner.read_exact(&mut magic)?;
+    ensure!(&magic == MAGIC, "Not a seekable GD stream");
+
+    let end = inner.seek(SeekFrom::End(-(FOOTER_BYTELEN as i64)))?;
+    let mut footer = [0u8; FOOTER_BYTELEN];
+    inner.read_exact(&mut footer)?;
+    ensure!(&footer[8..] == MAGIC, "Missing index trailer");
+    let index_bytelen = u64::from_le_bytes(footer[..8].try_into()?);
+    ensure!(
+      index_bytelen
+        .checked_add(MAGIC.len() as u64)
+        .is_some_and(|len| len <= end),
+      "Invalid index length"
+    );
+    inner.seek(SeekFrom::Start(end - index_bytelen))?;
+    let mut index = vec![0u8; index_bytelen as usize];
+    inner.read_exact(&mut index)?;
+
+    let (block_num, mut ptr) = get_varint(&index)?;
+    // each block entry takes at least 3 bytes
+    ensure!(
+      block_num <= (index.len() - ptr) / 3,
+      "Invalid number of blocks"
+    );
+    let mut entries = Vec::with_capacity(block_num);
+    let mut raw_offset = 0u64;
+    let mut deduped_offset = MAGIC.len() as u64;
+    for _ in 0..block_num {
+      let mut values = [0usize; 3];
+      for v in values.iter_mut() {
+        let (value, len) = get_varint(&index[ptr..])?;
+        *v = value;
+        ptr += len;
+      }
+      entries.push(BlockEntry {
+        raw_offset,
+        raw_bytelen: values[0],
+        deduped_offset,
+        deduped_bytelen: values[1],
+        last_chunk_pad_bytelen: values[2],
+      });
+      raw_offset = raw_offset
+        .checked_add(values[0] as u64)
+        .ok_or(anyhow!("Too large offset in index"))?;
+      deduped_offset = deduped_offset
+        .checked_add(values[1] as u64)
+        .ok_or(anyhow!("Too large offset in index"))?;
+    }
+    ensure!(ptr == index.len(), "Trailing bytes in index");
+    // every block but the last spans the checkpoint interval, which bounds the allocation for a block
+    let checkpoint_bytelen = entries.first().map_or(0, |e| e.raw_bytelen);
+    ensure!(
+      checkpoint_bytelen.is_multiple_of(gd.chunk_bytelen())
+        && entries.iter().enumerate().all(|(i, e)| {
+          e.raw_bytelen > 0
+            && (e.raw_bytelen == checkpoint_bytelen
+              || (i == entries.len() - 1 && e.raw_bytelen < checkpoint_bytelen))
+        }),
+      "Block lengths in index are inconsistent with a checkpoint interval"
+    );
+    ensure!(
+      deduped_offset == end - index_bytelen,
+      "Index is inconsistent with the stream length"
+    );
+
+    Ok(SeekableGdReader {
+      inner,
+      gd,
+      entries,
+      checkpoint_bytelen,
+      raw_bytelen: raw_offset,
+      pos: 0,
+      cached: None,
+    })
+  }
+
+  // Total length of the original data
+  pub fn len(&self) -> u64 {
+    self.raw_bytelen
+  }
+  pub fn is_empty(&self) -> bool {
+    self.raw_bytelen == 0
+  }
+
+  pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+    let new_pos = match pos {
+      SeekFrom::Start(p) => Some(p),
+      SeekFrom::End(d) => self.raw_bytelen.checked_add_signed(d),
+      SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+    };
+    let new_pos = new_pos.ok_or(anyhow!("Invalid seek to a negative position"))?;
+    self.pos = new_pos;
+    Ok(new_pos)
+  }
+
+  // Read from the current position and advance it. Returns 0 at the end of the data.
+  pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    let end = self
+      .raw_bytelen
+      .min(self.pos.saturating_add(buf.len() as u64));
+    if self.pos >= end {
+      return Ok(0);
+    }
+    let data = self.read_range(self.pos..end).await?;
+    buf[..data.len()].copy_from_slice(&data);
+    self.pos = end;
+    Ok(data.len())
+  }
+
+  pub async fn read_range(&mut self, range: Range<u64>) -> Result<U8VRep> {
+    ensure!(
+      range.start <= range.end && range.end <= self.raw_bytelen,
+      "Range {:?} is out of the data length {}",
+      range,
+      self.raw_bytelen
+    );
+    let mut res = U8VRep::new();
+    if range.start == range.end {
+      return Ok(res);
+    }
+    let checkpoint_bytelen = self.checkpoint_bytelen as u64;
+    // the nearest checkpoint at or before the start
+    let first = self
+      .entries
+      .partition_point(|e| e.raw_offset + e.raw_bytelen as u64 <= range.start);
+    for block_idx in first..self.entries.len() {
+      let entry = self.entries[block_idx].clone();
+      if entry.raw_offset >= range.end {
+        break;
+      }
+      let block = self.block(block_idx).await?;
+      // lengths in the index are untrusted, and hence the buffer is reserved only after the first block is verified,
+      // and for a checkpoint interval at most
+      if res.is_empty() {
+        res.reserve((range.end - range.start).min(checkpoint_bytelen) as usize);
+      }
+      let from = range.start.saturating_sub(entry.raw_offset) as usize;
+      let to = ((range.end - entry.raw_offset) as usize).min(entry.raw_bytelen);
+      res.extend_from_slice(&block[from..to]);
+    }
+    Ok(res)
+  }
+
+  async fn block(&mut self, block_idx: usize) -> Result<&U8VRep> {
+    let hit = matches!(&self.cached, Some((idx, _)) if *idx == block_idx);
+    if !hit {
+      let entry = &self.entries[block_idx];
+      let mut data = vec![0u8; entry.deduped_bytelen];
+      self.inner.seek(SeekFrom::Start(entry.deduped_offset))?;
+      self.inner.read_exact(&mut data)?;
+      let deduped = Deduped {
+        data,
+        last_chunk_pad_bytelen: entry.last_chunk_pad_bytelen,
+      };
+      self.gd.reset_dict();
+      let duped = self.gd.dup(&deduped).await?;
+      ensure!(
+        duped.len() == entry.raw_bytelen,
+        "Broken block {}",
+        block_idx
+      );
+      self.cached = Some((block_idx, duped));
+    }
+    match &self.cached {
+      Some((_, duped)) => Ok(duped),
+      None => Err(anyhow!("Failed to decode block {}", block_idx)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{SharedDict, GD};
+  use std::{io::Cursor, sync::Arc};
+
+  #[tokio::test]
+  async fn seekable_works() {
+    let words = "寿限無(じゅげむ)寿限無(じゅげむ)五劫(ごこう)のすりきれ海砂利(かいじゃり)"
+      .repeat(200)
+      .into_bytes();
+    let gd = GD::ReedSolomon(10, 8);
+
+    let mut writer =
+      SeekableGdWriter::new(Cursor::new(Vec::new()), gd.setup(255).await.unwrap(), 1000).unwrap();
+    for part in words.chunks(333) {
+      writer.write(part).await.unwrap();
+    }
+    let stream = writer.finish().await.unwrap().into_inner();
+    println!("Seekable > {} bytes -> {} bytes", words.len(), stream.len());
+    assert!(stream.len() < words.len());
+
+    let mut reader =
+      SeekableGdReader::new(Cursor::new(stream), gd.setup(255).await.unwrap()).unwrap();
+    assert_eq!(reader.len(), words.len() as u64);
+    for (start, end) in [(0, 10), (995, 2010), (5000, 5000), (12345, words.len())] {
+      let data = reader.read_range(start as u64..end as u64).await.unwrap();
+      assert_eq!(data, words[start..end]);
+    }
+    assert!(reader.read_range(0..words.len() as u64 + 1).await.is_err());
+
+    reader.seek(SeekFrom::End(-100)).unwrap();
+    let mut buf = [0u8; 64];
+    assert_eq!(reader.read(&mut buf).await.unwrap(), 64);
+    assert_eq!(buf, words[words.len() - 100..words.len() - 36]);
+    let mut whole = vec![0u8; words.len()];
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    assert_eq!(reader.read(&mut whole).await.unwrap(), words.len());
+    assert_eq!(whole, words);
+
+    // positions beyond the end read nothing
+    reader.seek(SeekFrom::Start(u64::MAX)).unwrap();
+    assert_eq!(reader.read(&mut buf).await.unwrap(), 0);
+
+    let mut broken = reader.inner.into_inner();
+    let footer = broken.len() - FOOTER_BYTELEN;
+    broken[footer..footer + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(SeekableGdReader::new(Cursor::new(broken), gd.setup(255).await.unwrap()).is_err());
+  }
+
+  #[tokio::test]
+  async fn seekable_rejects_dict_kept_across_blocks() {
+    let gd = GD::Hamming(7);
+    let stream = SeekableGdWriter::new(Cursor::new(Vec::new()), gd.setup(15).await.unwrap(), 1500)
+      .unwrap()
+      .finish()
+      .await
+      .unwrap()
+      .into_inner();
+
+    let mut packet = gd.setup(15).await.unwrap();
+    packet.set_packet_mode(8).unwrap();
+    let mut shared = gd.setup(15).await.unwrap();
+    shared
+      .set_shared_dict(Arc::new(SharedDict::new(15, 1).unwrap()))
+      .unwrap();
+    for inner in [packet, shared] {
+      let copy = inner.try_clone().unwrap();
+      assert!(SeekableGdWriter::new(Cursor::new(Vec::new()), inner, 1500).is_err());
+      assert!(SeekableGdReader::new(Cursor::new(stream.clone()), copy).is_err());
+    }
+
+    // the deviation dictionary is dropped by reset_dict as well as the base dictionary
+    let mut multi_level = gd.setup(15).await.unwrap();
+    multi_level.set_deviation_dict(3).unwrap();
+    assert!(SeekableGdWriter::new(Cursor::new(Vec::new()), multi_level, 1500).is_ok());
+  }
+
+  #[tokio::test]
+  async fn seekable_rejects_broken_index() {
+    let gd = GD::ReedSolomon(10, 8);
+    let crafted = |values: &[usize]| {
+      let mut index = U8VRep::new();
+      values.iter().for_each(|v| put_varint(&mut index, *v));
+      let mut stream = MAGIC.to_vec();
+      stream.extend_from_slice(&index);
+      stream.extend_from_slice(&(index.len() as u64).to_le_bytes());
+      stream.extend_from_slice(MAGIC);
+      Cursor::new(stream)
+    };
+    // too many blocks for the index
+    let res = SeekableGdReader::new(crafted(&[usize::MAX]), gd.setup(255).await.unwrap());
+    assert!(res.is_err());
+    // overflowing offsets
+    let res = SeekableGdReader::new(
+      crafted(&[2, usize::MAX, 0, 0, usize::MAX, 0, 0]),
+      gd.setup(255).await.unwrap(),
+    );
+    assert!(res.is_err());
+    // blocks longer than the checkpoint interval given by the first block
+    let res = SeekableGdReader::new(crafted(&[2, 10, 0, 0, 20, 0, 0]), gd.setup(255).await.unwrap());
+    assert!(res.is_err());
+    let res = SeekableGdReader::new(crafted(&[0]), gd.setup(255).await.unwrap());
+    assert!(res.is_ok_and(|r| r.is_empty()));
+  }
+
+  #[tokio::test]
+  async fn seekable_does_not_trust_block_lengths() {
+    let gd = GD::ReedSolomon(10, 8);
+    let deduped = gd.setup(255).await.unwrap().dedup(&[7u8; 100]).await.unwrap();
+    // a single block claiming a huge length for its deduplicated data
+    let mut index = U8VRep::new();
+    let raw_bytelen = usize::MAX / 20 * 10;
+    for v in [1, raw_bytelen, deduped.data.len(), deduped.last_chunk_pad_bytelen] {
+      put_varint(&mut index, v);
+    }
+    let mut stream = MAGIC.to_vec();
+    stream.extend_from_slice(&deduped.data);
+    stream.extend_from_slice(&index);
+    stream.extend_from_slice(&(index.len() as u64).to_le_bytes());
+    stream.extend_from_slice(MAGIC);
+
+    let mut reader =
+      SeekableGdReader::new(Cursor::new(stream), gd.setup(255).await.unwrap()).unwrap();
+    assert_eq!(reader.len(), raw_bytelen as u64);
+    assert!(reader.read_range(0..reader.len()).await.is_err());
+  }
+}
@@ -1,9 +1,32 @@
+use crate::error::*;
+#[cfg(feature = "std")]
+use crate::util::put_gamma;
 use bitvec::prelude::*;
-use std::convert::From;
+use core::convert::From;
+use libecc::types::*;
 
+#[cfg(feature = "std")]
+pub(crate) const MAX_RUN_CHUNKS: usize = 1 << 24;
+
+// Separator of each chunk. Only Deduped and AsIs are used in the default single-bit separator, and the others are
+// tokens of the multi-symbol separator given as a prefix code, where frequent tokens are shorter:
+// - Deduped (1): [id][deviation]
+// - AsIs (01): [base][deviation], where the base is inserted into the dictionary
+// - SameBase (001): [deviation], where the base is the same as the previous chunk
+// - Run (0001): [N (Elias gamma)], where the previous chunk is repeated N times
+// - RawLiteral (0000): [base][deviation], where the base is not inserted into the dictionary
+// Dictionaries are never touched for SameBase and Run.
+// Chunks repeated by Run in a frame are at most MAX_RUN_CHUNKS in total, beyond which SameBase is used instead.
+#[derive(Debug)]
 pub enum Separator {
   Deduped,
   AsIs,
+  #[cfg(feature = "std")]
+  SameBase,
+  #[cfg(feature = "std")]
+  Run,
+  #[cfg(feature = "std")]
+  RawLiteral,
 }
 
 impl From<bool> for Separator {
@@ -17,10 +40,99 @@ impl From<bool> for Separator {
 }
 
 impl Separator {
-  pub fn bv(&self) -> BitVec {
+  // Single-bit separator, i.e., only of Deduped and AsIs. Other tokens are given by encode in multi-symbol mode.
+  pub fn bv(&self) -> Result<BitVec> {
+    match *self {
+      Separator::Deduped => Ok(bitvec![1]),
+      Separator::AsIs => Ok(bitvec![0]),
+      #[cfg(feature = "std")]
+      _ => bail!("{:?} is available only in the multi-symbol separator", self),
+    }
+  }
+
+  pub fn multi_symbol_bv(&self) -> BitVec {
     match *self {
       Separator::Deduped => bitvec![1],
-      Self::AsIs => bitvec![0],
+      Separator::AsIs => bitvec![0, 1],
+      #[cfg(feature = "std")]
+      Separator::SameBase => bitvec![0, 0, 1],
+      #[cfg(feature = "std")]
+      Separator::Run => bitvec![0, 0, 0, 1],
+      #[cfg(feature = "std")]
+      Separator::RawLiteral => bitvec![0, 0, 0, 0],
+    }
+  }
+
+  // Returns the token of multi-symbol separator and its bit length
+  #[cfg(feature = "std")]
+  pub fn parse_multi_symbol(bs: &BSRep) -> Result<(Self, usize)> {
+    let zeros = bs.iter().take(4).take_while(|b| !**b).count();
+    ensure!(zeros == 4 || bs.len() > zeros, "Truncated separator");
+    Ok(match zeros {
+      0 => (Separator::Deduped, 1),
+      1 => (Separator::AsIs, 2),
+      2 => (Separator::SameBase, 3),
+      3 => (Separator::Run, 4),
+      _ => (Separator::RawLiteral, 4),
+    })
+  }
+
+  // Separator followed by that of the deviation in multi-level GD
+  pub fn encode(&self, multi_symbol: bool, deviation: Option<&Separator>) -> Result<BitVec> {
+    let mut res = if multi_symbol {
+      self.multi_symbol_bv()
+    } else {
+      self.bv()?
+    };
+    if let Some(dev_sep) = deviation {
+      res.extend_from_bitslice(&dev_sep.bv()?);
     }
+    Ok(res)
+  }
+}
+
+// Writer of tokens of chunks in bits padded to bytes, shared by FrameCoder and DedupGD without std
+pub(crate) struct TokenWriter {
+  res: BVRep,
+  multi_symbol: bool,
+}
+
+impl TokenWriter {
+  pub(crate) fn new(multi_symbol: bool) -> Self {
+    TokenWriter {
+      res: BVRep::new(),
+      multi_symbol,
+    }
+  }
+
+  // [separator][separator of deviation][id or base][id or deviation] of a chunk
+  pub(crate) fn put_chunk(
+    &mut self,
+    sep: &Separator,
+    dev_sep: Option<&Separator>,
+    id_or_base: &BSRep,
+    id_or_dev: &BSRep,
+  ) -> Result<()> {
+    self
+      .res
+      .extend_from_bitslice(&sep.encode(self.multi_symbol, dev_sep)?);
+    self.res.extend_from_bitslice(id_or_base);
+    self.res.extend_from_bitslice(id_or_dev);
+    Ok(())
+  }
+
+  // Run of the previous chunk repeated run times in the multi-symbol separator
+  #[cfg(feature = "std")]
+  pub(crate) fn put_run(&mut self, run: usize) {
+    self
+      .res
+      .extend_from_bitslice(&Separator::Run.multi_symbol_bv());
+    put_gamma(&mut self.res, run);
+  }
+
+  pub(crate) fn finish(mut self) -> U8VRep {
+    self.res.force_align();
+    self.res.set_uninitialized(false);
+    self.res.into_vec()
   }
 }
@@ -0,0 +1,287 @@
+use crate::{
+  dict::{bitvec_id_to_usize_id, id_bitlen_of, usize_id_to_bitvec_id},
+  error::*,
+  util::Fnv64,
+};
+use libecc::types::*;
+use std::{
+  collections::HashMap,
+  hash::{Hash, Hasher},
+  sync::{Arc, RwLock},
+};
+
+// Thread-safe dictionary shared by multiple GD instances, e.g., streams from many devices.
+//
+// Unlike BasisDict, an id is explicitly given with a base in AsIs, i.e., [sep = 0][id][base][deviation],
+// and is never reassigned, i.e., no eviction. So, the dup side reconstructs the same id assignment independently of
+// the order in which bases of concurrent streams were inserted. If the dictionary is full, the id of `capacity` is given
+// and the base is not stored.
+//
+// Bases inserted in a frame (a call of dedup) are referenced by other frames only after the frame is completed.
+// So, frames can be duplicated in the order in which their deduplication completed. Otherwise, dup fails due to an
+// unknown id, and can be retried after the dependent frames. Ids reserved in a frame failed in dedup are released
+// and given to other bases, since no deduplicated data refers to them.
+//
+// Entries are sharded by the hash of bases, where id of the i-th base in j-th shard is j + i * shard_num.
+#[derive(Debug)]
+pub struct SharedDict {
+  capacity: usize,
+  id_bitlen: usize,
+  shards: Vec<RwLock<Shard>>,
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+  base_to_id: HashMap<BVRep, (usize, bool)>, // (id, published)
+  id_to_base: HashMap<usize, BVRep>,
+  next_slot: usize,
+  free_ids: Vec<usize>, // ids released by frames failed in dedup, reused before new slots
+}
+
+impl SharedDict {
+  pub fn new(capacity: usize, shard_num: usize) -> Result<Self> {
+    ensure!(
+      shard_num > 0 && shard_num <= capacity,
+      "Number of shards must be in 1..={}",
+      capacity
+    );
+    Ok(SharedDict {
+      capacity,
+      id_bitlen: id_bitlen_of(capacity),
+      shards: (0..shard_num)
+        .map(|_| RwLock::new(Shard::default()))
+        .collect(),
+    })
+  }
+
+  pub fn id_bitlen(&self) -> usize {
+    self.id_bitlen
+  }
+
+  // number of stored bases
+  pub fn len(&self) -> usize {
+    self
+      .shards
+      .iter()
+      .map(|shard| shard.read().map_or(0, |s| s.id_to_base.len()))
+      .sum()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn shard_of(&self, base: &BVRep) -> usize {
+    let mut hasher = Fnv64::default();
+    base.hash(&mut hasher);
+    (hasher.finish() % self.shards.len() as u64) as usize
+  }
+
+  // call only in dedup
+  fn get_id(&self, base: &BVRep, reserved: &[usize]) -> Option<usize> {
+    let shard = self.shards[self.shard_of(base)].read().ok()?;
+    match shard.base_to_id.get(base) {
+      Some((id, published)) if *published || reserved.contains(id) => Some(*id),
+      _ => None,
+    }
+  }
+
+  // call only in dedup when id was not found in get_id. Returns capacity if the base is not stored.
+  fn reserve(&self, base: &BVRep) -> Result<usize> {
+    let shard_idx = self.shard_of(base);
+    let mut shard = self.shards[shard_idx]
+      .write()
+      .map_err(|_| anyhow!("Poisoned shared dictionary"))?;
+    if shard.base_to_id.contains_key(base) {
+      // being inserted in another frame
+      return Ok(self.capacity);
+    }
+    let id = match shard.free_ids.pop() {
+      Some(id) => id,
+      None => {
+        let id = shard_idx + shard.next_slot * self.shards.len();
+        if id >= self.capacity {
+          // full
+          return Ok(self.capacity);
+        }
+        shard.next_slot += 1;
+        id
+      }
+    };
+    shard.base_to_id.insert(base.to_owned(), (id, false));
+    shard.id_to_base.insert(id, base.to_owned());
+    Ok(id)
+  }
+
+  fn publish(&self, ids: &[usize]) -> Result<()> {
+    for id in ids {
+      let mut shard = self.shards[id % self.shards.len()]
+        .write()
+        .map_err(|_| anyhow!("Poisoned shared dictionary"))?;
+      let base = shard
+        .id_to_base
+        .get(id)
+        .cloned()
+        .ok_or(anyhow!("Invalid dictionary"))?;
+      shard.base_to_id.insert(base, (*id, true));
+    }
+    Ok(())
+  }
+
+  // drop bases reserved but not published, whose ids are never referenced by other frames
+  fn release(&self, ids: &[usize]) -> Result<()> {
+    for id in ids {
+      let mut shard = self.shards[id % self.shards.len()]
+        .write()
+        .map_err(|_| anyhow!("Poisoned shared dictionary"))?;
+      let base = shard
+        .id_to_base
+        .remove(id)
+        .ok_or(anyhow!("Invalid dictionary"))?;
+      ensure!(
+        shard.base_to_id.remove(&base) == Some((*id, false)),
+        "Invalid dictionary"
+      );
+      shard.free_ids.push(*id);
+    }
+    Ok(())
+  }
+
+  // call only in dup when id and base are given
+  fn insert(&self, id: usize, base: &BVRep) -> Result<()> {
+    if id == self.capacity {
+      return Ok(());
+    }
+    ensure!(id < self.capacity, "Invalid id {}", id);
+    let mut shard = self.shards[id % self.shards.len()]
+      .write()
+      .map_err(|_| anyhow!("Poisoned shared dictionary"))?;
+    match shard.id_to_base.get(&id) {
+      Some(b) => ensure!(b == base, "Conflicting bases for id {}", id),
+      None => {
+        shard.id_to_base.insert(id, base.to_owned());
+        shard.base_to_id.insert(base.to_owned(), (id, true));
+      }
+    }
+    Ok(())
+  }
+
+  // call only in dup when id is given
+  fn get_base(&self, id: usize) -> Result<BVRep> {
+    let shard = self.shards[id % self.shards.len()]
+      .read()
+      .map_err(|_| anyhow!("Poisoned shared dictionary"))?;
+    shard
+      .id_to_base
+      .get(&id)
+      .cloned()
+      .ok_or(anyhow!("Unknown id {} in the shared dictionary", id))
+  }
+}
+
+// Reference to a shared dictionary held by each GD instance
+#[derive(Debug, Clone)]
+pub struct SharedDictHandle {
+  dict: Arc<SharedDict>,
+  reserved: Vec<usize>, // ids inserted in the current frame
+}
+
+impl SharedDictHandle {
+  pub fn new(dict: Arc<SharedDict>) -> Self {
+    SharedDictHandle {
+      dict,
+      reserved: Vec::new(),
+    }
+  }
+
+  pub fn id_bitlen(&self) -> usize {
+    self.dict.id_bitlen
+  }
+
+  pub fn get_id(&self, base: &BVRep) -> Option<IdRep> {
+    self
+      .dict
+      .get_id(base, &self.reserved)
+      .map(|id| usize_id_to_bitvec_id(id, self.dict.id_bitlen))
+  }
+
+  // Returns the id to be given with the base in AsIs
+  pub fn put_base(&mut self, base: &BVRep) -> Result<IdRep> {
+    let id = self.dict.reserve(base)?;
+    if id < self.dict.capacity {
+      self.reserved.push(id);
+    }
+    Ok(usize_id_to_bitvec_id(id, self.dict.id_bitlen))
+  }
+
+  pub fn put_base_with_id(&mut self, base: &BVRep, bit_id: &IdSRep) -> Result<()> {
+    self.dict.insert(bitvec_id_to_usize_id(bit_id), base)
+  }
+
+  pub fn get_base(&self, bit_id: &IdSRep) -> Result<BVRep> {
+    self.dict.get_base(bitvec_id_to_usize_id(bit_id))
+  }
+
+  // call after a frame is deduplicated
+  pub fn end_frame(&mut self) -> Result<()> {
+    let reserved = std::mem::take(&mut self.reserved);
+    self.dict.publish(&reserved)
+  }
+
+  // call when deduplication of a frame failed or its frame is discarded, which releases ids reserved in the frame
+  pub fn rollback_frame(&mut self) -> Result<()> {
+    let reserved = std::mem::take(&mut self.reserved);
+    self.dict.release(&reserved)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shared_dict_works() {
+    let dict = Arc::new(SharedDict::new(4, 2).unwrap());
+    let mut handle_a = SharedDictHandle::new(dict.clone());
+    let mut handle_b = SharedDictHandle::new(dict.clone());
+    let base = |b: u8| BVRep::from_slice(&[b]);
+
+    // a base is visible to other handles after the frame is completed
+    let id_0 = handle_a.put_base(&base(0)).unwrap();
+    assert_eq!(handle_a.get_id(&base(0)), Some(id_0.clone()));
+    assert!(handle_b.get_id(&base(0)).is_none());
+    assert_eq!(
+      handle_b.put_base(&base(0)).unwrap(),
+      usize_id_to_bitvec_id(4, 3)
+    );
+    handle_a.end_frame().unwrap();
+    handle_b.end_frame().unwrap();
+    assert_eq!(handle_b.get_id(&base(0)), Some(id_0.clone()));
+
+    // no more than capacity
+    for b in 1..16 {
+      handle_a.put_base(&base(b)).unwrap();
+    }
+    handle_a.end_frame().unwrap();
+    assert_eq!(dict.len(), 4);
+
+    // ids reserved in a failed frame are released and given to other bases
+    let dict = Arc::new(SharedDict::new(4, 1).unwrap());
+    let mut handle_a = SharedDictHandle::new(dict.clone());
+    let mut handle_b = SharedDictHandle::new(dict.clone());
+    let id_0 = handle_a.put_base(&base(0)).unwrap();
+    handle_a.rollback_frame().unwrap();
+    handle_a.end_frame().unwrap();
+    assert!(dict.is_empty());
+    assert!(handle_a.get_id(&base(0)).is_none());
+    assert_eq!(handle_b.put_base(&base(2)).unwrap(), id_0);
+    assert_ne!(handle_a.put_base(&base(0)).unwrap(), usize_id_to_bitvec_id(4, 3));
+
+    // dup side
+    let dup_dict = Arc::new(SharedDict::new(4, 2).unwrap());
+    let mut handle_c = SharedDictHandle::new(dup_dict);
+    assert!(handle_c.get_base(&id_0).is_err());
+    handle_c.put_base_with_id(&base(0), &id_0).unwrap();
+    assert_eq!(handle_c.get_base(&id_0).unwrap(), base(0));
+    assert!(handle_c.put_base_with_id(&base(1), &id_0).is_err());
+  }
+}
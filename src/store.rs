@@ -0,0 +1,330 @@
+#[cfg(feature = "serde")]
+use crate::dict_backend::DictBase;
+use crate::{error::*, frame::TokenBits, util::Fnv64};
+use bitvec::prelude::*;
+use libecc::types::*;
+use std::{
+  collections::HashMap,
+  hash::{Hash, Hasher},
+};
+
+pub type BaseKey = u64;
+const BASE_KEY_BITLEN: usize = BaseKey::BITS as usize;
+
+// Content-addressed store of bases for archival deduplication.
+// Every base is stored once with its 64-bit FNV-1a digest as the key. Since the digest is not cryptographic, a base
+// whose digest collides with that of another stored base takes the next free key instead. So, keys are determined
+// by the bases and the order in which they are put, and never change while stored, but they are valid only for this
+// store, which is persisted via serde with the serde feature.
+// Every chunk costs a key of BASE_KEY_BITLEN bits in addition to its deviation, which expands short chunks, e.g.,
+// a 32-bit chunk of RS(4,3) takes 64 + 8 bits.
+// Bases are reference-counted, and those no longer referenced by any frame are dropped by gc.
+// Each base is kept only once, in its entry, and looked up by its digest, i.e., at the key given by the digest or at
+// keys that colliding bases of the digest took instead, which are the only ones recorded besides the entries.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "StoreSnapshot", into = "StoreSnapshot")
+)]
+pub struct BaseStore {
+  entries: HashMap<BaseKey, StoredBase>,
+  // digest to keys of bases stored elsewhere than at the digest due to collisions
+  collided: HashMap<u64, Vec<BaseKey>>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredBase {
+  base: BVRep,
+  refcount: usize,
+}
+
+impl BaseStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // number of stored bases including those waiting for gc
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn refcount(&self, key: BaseKey) -> Option<usize> {
+    self.entries.get(&key).map(|e| e.refcount)
+  }
+
+  // Store a base or increment its reference count, and return its key
+  pub fn put(&mut self, base: &BVRep) -> BaseKey {
+    let digest = key_of(base);
+    if let Some(key) = self.find(base, digest) {
+      if let Some(entry) = self.entries.get_mut(&key) {
+        entry.refcount += 1;
+      }
+      return key;
+    }
+    let mut key = digest;
+    while self.entries.contains_key(&key) {
+      key = key.wrapping_add(1);
+    }
+    self.insert(key, digest, base.to_owned(), 1);
+    key
+  }
+
+  // key of a stored base with the digest
+  fn find(&self, base: &BVRep, digest: u64) -> Option<BaseKey> {
+    let is_base = |key: &BaseKey| self.entries.get(key).is_some_and(|e| &e.base == base);
+    if is_base(&digest) {
+      return Some(digest);
+    }
+    self.collided.get(&digest)?.iter().copied().find(is_base)
+  }
+
+  fn insert(&mut self, key: BaseKey, digest: u64, base: BVRep, refcount: usize) {
+    if key != digest {
+      self.collided.entry(digest).or_default().push(key);
+    }
+    self.entries.insert(key, StoredBase { base, refcount });
+  }
+
+  pub fn get(&self, key: BaseKey) -> Result<&BVRep> {
+    self
+      .entries
+      .get(&key)
+      .map(|e| &e.base)
+      .ok_or(anyhow!("Unknown base key {:016x}", key))
+  }
+
+  // Decrement the reference count of a base
+  pub fn release(&mut self, key: BaseKey) -> Result<()> {
+    let entry = self
+      .entries
+      .get_mut(&key)
+      .ok_or(anyhow!("Unknown base key {:016x}", key))?;
+    ensure!(entry.refcount > 0, "Base {:016x} is not referenced", key);
+    entry.refcount -= 1;
+    Ok(())
+  }
+
+  // Frames deduplicated into the store are shared by GD units for bases and deviations of bytes or bits, where every
+  // base is put to the store and each chunk is given as [key of base (64 bits)][deviation] without separator.
+  pub(crate) fn put_chunks<T: TokenBits>(&mut self, chunks: Vec<(T, T)>) -> U8VRep {
+    let mut res = BVRep::new();
+    for (base, dev) in chunks.iter() {
+      res.extend_from_raw_slice(&self.put(&base.to_bits()).to_be_bytes());
+      dev.push_bits(&mut res);
+    }
+    res.force_align();
+    res.into_vec()
+  }
+
+  // Keys of bases and deviations of dev_bitlen bits of chunks given by put_chunks
+  pub(crate) fn parse_chunks<T: TokenBits>(data: &U8SRep, dev_bitlen: usize) -> Result<Vec<(BaseKey, T)>> {
+    let deduped_bs = BSRep::from_slice(data);
+    let mut chunks = Vec::new();
+    let mut bitptr = 0usize;
+    let max_bit_pads = 7usize;
+    while bitptr + max_bit_pads < deduped_bs.len() {
+      ensure!(
+        bitptr + BASE_KEY_BITLEN + dev_bitlen <= deduped_bs.len(),
+        "Truncated frame"
+      );
+      let key = deduped_bs[bitptr..bitptr + BASE_KEY_BITLEN].load_be::<BaseKey>();
+      bitptr += BASE_KEY_BITLEN;
+      chunks.push((key, T::from_bits(&deduped_bs[bitptr..bitptr + dev_bitlen])));
+      bitptr += dev_bitlen;
+    }
+    Ok(chunks)
+  }
+
+  // Bases and deviations of chunks given by parse_chunks
+  pub(crate) fn get_chunks<T: TokenBits>(&self, chunks: Vec<(BaseKey, T)>) -> Result<Vec<(T, T)>> {
+    chunks
+      .into_iter()
+      .map(|(key, dev)| Ok((T::from_bits(self.get(key)?), dev)))
+      .collect()
+  }
+
+  // Release bases of chunks given by parse_chunks, where nothing is released if any of them is unknown
+  pub(crate) fn release_chunks<T>(&mut self, chunks: &[(BaseKey, T)]) -> Result<()> {
+    for (key, _) in chunks.iter() {
+      self.get(*key)?;
+    }
+    chunks.iter().try_for_each(|(key, _)| self.release(*key))
+  }
+
+  // Drop bases no longer referenced. Returns the number of dropped bases.
+  pub fn gc(&mut self) -> usize {
+    let unreferenced = self
+      .entries
+      .iter()
+      .filter(|(_, e)| e.refcount == 0)
+      .map(|(k, _)| *k)
+      .collect::<Vec<_>>();
+    for key in unreferenced.iter() {
+      let Some(entry) = self.entries.remove(key) else {
+        continue;
+      };
+      let digest = key_of(&entry.base);
+      if let Some(keys) = self.collided.get_mut(&digest) {
+        keys.retain(|k| k != key);
+        if keys.is_empty() {
+          self.collided.remove(&digest);
+        }
+      }
+    }
+    unreferenced.len()
+  }
+}
+
+fn key_of(base: &BVRep) -> BaseKey {
+  let mut hasher = Fnv64::default();
+  base.hash(&mut hasher);
+  hasher.finish()
+}
+
+// Serialized form of BaseStore, i.e., (key, reference count, base in its byte representation of DictBase) in the
+// order of keys, from which keys of colliding bases are recomputed on load
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoreSnapshot {
+  entries: Vec<(BaseKey, usize, U8VRep)>,
+}
+
+#[cfg(feature = "serde")]
+impl From<BaseStore> for StoreSnapshot {
+  fn from(store: BaseStore) -> Self {
+    let mut entries = store
+      .entries
+      .into_iter()
+      .map(|(key, e)| (key, e.refcount, e.base.to_bytes()))
+      .collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|(key, _, _)| *key);
+    StoreSnapshot { entries }
+  }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<StoreSnapshot> for BaseStore {
+  type Error = anyhow::Error;
+
+  fn try_from(snapshot: StoreSnapshot) -> Result<Self> {
+    let mut store = BaseStore::new();
+    for (key, refcount, bytes) in snapshot.entries {
+      let base = BVRep::from_bytes(&bytes)?;
+      let digest = key_of(&base);
+      ensure!(
+        !store.entries.contains_key(&key) && store.find(&base, digest).is_none(),
+        "Duplicated base in store"
+      );
+      store.insert(key, digest, base, refcount);
+    }
+    Ok(store)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn base_store_works() {
+    let mut store = BaseStore::new();
+    let a = BVRep::from_slice(&[1u8, 2]);
+    let b = BVRep::from_slice(&[3u8, 4]);
+    let key_a = store.put(&a);
+    assert_eq!(store.put(&a), key_a);
+    let key_b = store.put(&b);
+    assert_ne!(key_a, key_b);
+    assert_eq!(store.refcount(key_a), Some(2));
+    assert_eq!(store.get(key_b).unwrap(), &b);
+
+    store.release(key_a).unwrap();
+    store.release(key_b).unwrap();
+    assert_eq!(store.gc(), 1);
+    assert!(store.get(key_b).is_err());
+    assert!(store.release(key_b).is_err());
+    assert_eq!(store.get(key_a).unwrap(), &a);
+
+    // keys depend only on bases unless digests collide
+    assert_eq!(store.put(&b), key_b);
+    store.release(key_a).unwrap();
+    assert!(store.release(key_a).is_err());
+    let mut other = BaseStore::new();
+    assert_eq!([other.put(&b), other.put(&a)], [key_b, key_a]);
+
+    // a base colliding with another stored base takes the next free key
+    let c = BVRep::from_slice(&[5u8, 6]);
+    let key_c = key_of(&c);
+    let fake = BVRep::from_slice(&[7u8]);
+    other.insert(key_c, key_of(&fake), fake, 1);
+    assert_eq!(other.put(&c), key_c.wrapping_add(1));
+    assert_eq!(other.put(&c), key_c.wrapping_add(1));
+    assert_eq!(other.refcount(key_c.wrapping_add(1)), Some(2));
+    assert_eq!(other.get(key_c.wrapping_add(1)).unwrap(), &c);
+
+    // the colliding base is still found after the base at its digest is dropped
+    other.release(key_c).unwrap();
+    assert_eq!(other.gc(), 1);
+    assert_eq!(other.put(&c), key_c.wrapping_add(1));
+    assert_eq!(other.refcount(key_c.wrapping_add(1)), Some(3));
+    for _ in 0..3 {
+      other.release(key_c.wrapping_add(1)).unwrap();
+    }
+    other.gc();
+    assert!(other.collided.is_empty());
+  }
+
+  #[test]
+  fn store_chunks_work_for_bytes_and_bits() {
+    let mut store = BaseStore::new();
+    let bytes = vec![(vec![1u8, 2], vec![3u8]), (vec![1u8, 2], vec![4u8])];
+    let data = store.put_chunks(bytes.clone());
+    assert_eq!(data.len(), 2 * (8 + 1));
+    let keyed = BaseStore::parse_chunks::<U8VRep>(&data, 8).unwrap();
+    assert_eq!(keyed[0].0, keyed[1].0);
+    assert_eq!(store.refcount(keyed[0].0), Some(2));
+    assert_eq!(store.get_chunks(keyed.clone()).unwrap(), bytes);
+    assert!(BaseStore::parse_chunks::<U8VRep>(&data[..data.len() - 1], 8).is_err());
+
+    let bits = vec![(bitvec![u8, Msb0; 1, 0, 1], bitvec![u8, Msb0; 0, 1, 1])];
+    let data = store.put_chunks(bits.clone());
+    let bit_keyed = BaseStore::parse_chunks::<BVRep>(&data, 3).unwrap();
+    assert_eq!(store.get_chunks(bit_keyed).unwrap(), bits);
+
+    // nothing is released if any base is unknown
+    let mut unknown = keyed.clone();
+    unknown.push((keyed[0].0.wrapping_add(1), vec![0u8]));
+    assert!(store.release_chunks(&unknown).is_err());
+    assert_eq!(store.refcount(keyed[0].0), Some(2));
+    store.release_chunks(&keyed).unwrap();
+    assert_eq!(store.refcount(keyed[0].0), Some(0));
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn base_store_serde_works() {
+    use bitvec::prelude::*;
+    let mut store = BaseStore::new();
+    let bases = [
+      BVRep::from_slice(&[1u8, 2]),
+      bitvec![u8, Msb0; 1, 0, 1],
+      BVRep::new(),
+    ];
+    let keys = bases.iter().map(|b| store.put(b)).collect::<Vec<_>>();
+    store.put(&bases[0]);
+
+    let json = serde_json::to_string(&store).unwrap();
+    let loaded: BaseStore = serde_json::from_str(&json).unwrap();
+    for (key, base) in keys.iter().zip(bases.iter()) {
+      assert_eq!(loaded.get(*key).unwrap(), base);
+    }
+    assert_eq!(loaded.refcount(keys[0]), Some(2));
+    assert_eq!(loaded.clone().put(&bases[1]), keys[1]);
+
+    let duplicated = r#"{"entries":[[1,1,[0]],[2,1,[0]]]}"#;
+    assert!(serde_json::from_str::<BaseStore>(duplicated).is_err());
+  }
+}
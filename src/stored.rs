@@ -0,0 +1,163 @@
+use crate::{error::*, frame::FrameUnit, Deduped};
+use futures::future::BoxFuture;
+use libecc::types::*;
+
+// Frame types given as the first byte of every deduplicated data when the stored fallback is enabled:
+// [GD_FRAME][deduplicated data as usual] or [STORED_FRAME][input as is].
+// The dedup side emits a stored frame whenever the GD frame is not smaller than its input, and undoes all changes of
+// its dictionaries made while building the GD frame. The dup side never touches dictionaries for a stored frame.
+const GD_FRAME: u8 = 0;
+const STORED_FRAME: u8 = 1;
+
+// Maximum expansion in bytes of a frame over its input with the stored fallback
+pub const STORED_FALLBACK_MAX_EXPANSION: usize = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Frame<'a> {
+  Gd(&'a U8SRep),
+  Stored(&'a U8SRep),
+}
+
+fn gd_frame(mut deduped: Deduped) -> Deduped {
+  deduped.data.insert(0, GD_FRAME);
+  deduped
+}
+
+fn stored_frame(buf: &U8SRep) -> Deduped {
+  let mut data = Vec::with_capacity(buf.len() + STORED_FALLBACK_MAX_EXPANSION);
+  data.push(STORED_FRAME);
+  data.extend_from_slice(buf);
+  Deduped {
+    data,
+    last_chunk_pad_bytelen: 0,
+  }
+}
+
+fn parse_frame(data: &U8SRep) -> Result<Frame<'_>> {
+  match data.split_first() {
+    Some((&GD_FRAME, body)) => Ok(Frame::Gd(body)),
+    Some((&STORED_FRAME, body)) => Ok(Frame::Stored(body)),
+    Some((t, _)) => bail!("Unknown frame type {}", t),
+    None => bail!("Missing frame type"),
+  }
+}
+
+// Deduplication of a GD unit, where frame gives the GD frame of the input. With the stored fallback, the GD frame is
+// replaced with a stored frame unless smaller than the input, and changes of dictionaries made for it are undone.
+pub(crate) async fn dedup_with_fallback<G, F>(gd: &mut G, buf: &U8SRep, frame: F) -> Result<Deduped>
+where
+  G: FrameUnit,
+  F: for<'a> FnOnce(&'a mut G, &'a U8SRep) -> BoxFuture<'a, Result<Deduped>>,
+{
+  if !gd.coder_mut().stored_fallback {
+    let res = frame(gd, buf).await;
+    if res.is_err() {
+      gd.coder_mut().rollback_dedup_frame()?;
+    }
+    return res;
+  }
+  let literal = gd.coder_mut().begin_journal()?;
+  match frame(gd, buf).await {
+    Ok(deduped) if deduped.data.len() < buf.len() => {
+      gd.coder_mut().end_journal(false, literal)?;
+      Ok(gd_frame(deduped))
+    }
+    Ok(_) => {
+      gd.coder_mut().end_journal(true, literal)?;
+      Ok(stored_frame(buf))
+    }
+    Err(e) => {
+      gd.coder_mut().end_journal(true, literal)?;
+      Err(e)
+    }
+  }
+}
+
+// Duplication of a GD unit, where frame gives the input of a GD frame of the given padding of the last chunk
+pub(crate) async fn dup_with_fallback<G, F>(gd: &mut G, deduped: &Deduped, frame: F) -> Result<U8VRep>
+where
+  G: FrameUnit,
+  F: for<'a> FnOnce(&'a mut G, &'a U8SRep, usize) -> BoxFuture<'a, Result<U8VRep>>,
+{
+  if !gd.coder_mut().stored_fallback {
+    return frame(gd, &deduped.data, deduped.last_chunk_pad_bytelen).await;
+  }
+  match parse_frame(&deduped.data)? {
+    Frame::Gd(data) => frame(gd, data, deduped.last_chunk_pad_bytelen).await,
+    Frame::Stored(data) => Ok(data.to_owned()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    dict::{usize_id_to_bitvec_id, BasisDict},
+    frame::{FrameCoder, TokenBits},
+    shared_dict::{SharedDict, SharedDictHandle},
+  };
+  use std::sync::Arc;
+
+  #[test]
+  fn frame_type_works() {
+    let stored = stored_frame(b"abc");
+    assert_eq!(stored.data.len(), 3 + STORED_FALLBACK_MAX_EXPANSION);
+    assert_eq!(parse_frame(&stored.data).unwrap(), Frame::Stored(b"abc"));
+
+    let gd = gd_frame(Deduped {
+      data: vec![1, 2],
+      last_chunk_pad_bytelen: 0,
+    });
+    assert_eq!(parse_frame(&gd.data).unwrap(), Frame::Gd(&[1, 2]));
+    assert!(parse_frame(&[2]).is_err());
+    assert!(parse_frame(&[]).is_err());
+  }
+
+  struct Unit(FrameCoder<U8VRep>);
+  impl FrameUnit for Unit {
+    type Base = U8VRep;
+    fn coder_mut(&mut self) -> &mut FrameCoder<U8VRep> {
+      &mut self.0
+    }
+  }
+
+  async fn token_frame(unit: &mut Unit, chunks: Vec<Result<(U8VRep, U8VRep)>>) -> Result<Deduped> {
+    let tokens = unit.0.encode_tokens(chunks)?;
+    unit.0.end_dedup_frame()?;
+    Ok(Deduped {
+      data: tokens,
+      last_chunk_pad_bytelen: 0,
+    })
+  }
+
+  #[tokio::test]
+  async fn failed_frame_releases_shared_ids() {
+    let dict = Arc::new(SharedDict::new(4, 1).unwrap());
+    let mut units = [0, 1].map(|_| {
+      let mut coder = FrameCoder::new(BasisDict::<U8VRep>::new(4));
+      coder.shared = Some(SharedDictHandle::new(dict.clone()));
+      Unit(coder)
+    });
+    let base = |b: &[u8]| b.to_vec().to_bits();
+
+    // a frame failing after a base is reserved
+    let res = dedup_with_fallback(&mut units[0], b"a", |unit, buf| {
+      let chunks = vec![Ok((buf.to_vec(), vec![])), Err(anyhow!("Broken chunk"))];
+      Box::pin(token_frame(unit, chunks))
+    })
+    .await;
+    assert!(res.is_err());
+    assert!(units[0].0.shared.as_ref().unwrap().get_id(&base(b"a")).is_none());
+
+    // the id is neither published in the next frame nor lost
+    let res = dedup_with_fallback(&mut units[0], b"b", |unit, buf| {
+      Box::pin(token_frame(unit, vec![Ok((buf.to_vec(), vec![]))]))
+    })
+    .await;
+    assert!(res.is_ok());
+    assert_eq!(dict.len(), 1);
+    let handle = units[1].0.shared.as_mut().unwrap();
+    assert!(handle.get_id(&base(b"a")).is_none());
+    assert_eq!(handle.put_base(&base(b"c")).unwrap(), usize_id_to_bitvec_id(1, 3));
+  }
+}
@@ -0,0 +1,145 @@
+use crate::error::*;
+#[cfg(feature = "std")]
+use bitvec::prelude::*;
+use libecc::types::*;
+
+// LEB128-style variable length encoding of unsigned integers used in headers of deduplicated data
+pub(crate) fn put_varint(buf: &mut U8VRep, mut value: usize) {
+  while value >= 0x80 {
+    buf.push((value as u8 & 0x7F) | 0x80);
+    value >>= 7;
+  }
+  buf.push(value as u8);
+}
+
+// Maximum bytelen of a varint of usize
+pub(crate) const MAX_VARINT_BYTELEN: usize = (usize::BITS as usize).div_ceil(7);
+
+// put_varint into a fixed-size buffer without allocation. Returns the number of written bytes.
+pub(crate) fn put_varint_array(buf: &mut [u8; MAX_VARINT_BYTELEN], mut value: usize) -> usize {
+  let mut len = 0;
+  while value >= 0x80 {
+    buf[len] = (value as u8 & 0x7F) | 0x80;
+    value >>= 7;
+    len += 1;
+  }
+  buf[len] = value as u8;
+  len + 1
+}
+
+// Returns the decoded value and the number of consumed bytes
+pub(crate) fn get_varint(buf: &U8SRep) -> Result<(usize, usize)> {
+  let mut value = 0usize;
+  for (idx, b) in buf.iter().enumerate() {
+    ensure!(idx * 7 < usize::BITS as usize, "Too long varint");
+    value |= ((b & 0x7F) as usize) << (idx * 7);
+    if b & 0x80 == 0 {
+      return Ok((value, idx + 1));
+    }
+  }
+  Err(anyhow!("Truncated varint"))
+}
+
+// Elias gamma code of a positive integer, e.g., the length of a run of chunks
+#[cfg(feature = "std")]
+pub(crate) fn put_gamma(buf: &mut BVRep, value: usize) {
+  let bitlen = (usize::BITS - value.leading_zeros()) as usize;
+  (1..bitlen).for_each(|_| buf.push(false));
+  (0..bitlen)
+    .rev()
+    .for_each(|i| buf.push((value >> i) & 1 == 1));
+}
+
+// Returns the decoded value and the number of consumed bits
+#[cfg(feature = "std")]
+pub(crate) fn get_gamma(bs: &BSRep) -> Result<(usize, usize)> {
+  let zeros = bs.leading_zeros();
+  ensure!(zeros < usize::BITS as usize, "Invalid gamma code");
+  ensure!(bs.len() > 2 * zeros, "Truncated gamma code");
+  Ok((bs[zeros..2 * zeros + 1].load_be::<usize>(), 2 * zeros + 1))
+}
+
+// FNV-1a hasher giving the same digest independently of platforms, where usize is always hashed as u64
+pub(crate) struct Fnv64(u64);
+
+impl Default for Fnv64 {
+  fn default() -> Self {
+    Fnv64(0xcbf2_9ce4_8422_2325)
+  }
+}
+
+impl core::hash::Hasher for Fnv64 {
+  fn finish(&self) -> u64 {
+    self.0
+  }
+  fn write(&mut self, bytes: &[u8]) {
+    for b in bytes {
+      self.0 ^= *b as u64;
+      self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+  }
+  fn write_usize(&mut self, i: usize) {
+    self.write(&(i as u64).to_le_bytes());
+  }
+}
+
+// SplitMix64 PRNG giving the same sequence for a seed independently of platforms and versions of dependencies
+#[cfg(feature = "std")]
+pub(crate) struct SplitMix64(u64);
+
+#[cfg(feature = "std")]
+impl SplitMix64 {
+  pub(crate) fn new(seed: u64) -> Self {
+    SplitMix64(seed)
+  }
+
+  pub(crate) fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::vec;
+
+  #[test]
+  fn varint_works() {
+    for value in [0usize, 1, 127, 128, 300, 16384, usize::MAX] {
+      let mut buf = vec![0xFFu8];
+      put_varint(&mut buf, value);
+      assert_eq!(get_varint(&buf[1..]).unwrap(), (value, buf.len() - 1));
+    }
+    assert!(get_varint(&[0x80u8, 0x80]).is_err());
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn gamma_works() {
+    let mut buf = BVRep::new();
+    let values = [1usize, 2, 3, 7, 8, 1000, usize::MAX];
+    values.iter().for_each(|v| put_gamma(&mut buf, *v));
+    assert_eq!(buf[..4], bits![u8, Msb0; 1, 0, 1, 0]);
+    let mut ptr = 0;
+    for v in values {
+      let (value, len) = get_gamma(&buf[ptr..]).unwrap();
+      assert_eq!(value, v);
+      ptr += len;
+    }
+    assert_eq!(ptr, buf.len());
+    assert!(get_gamma(&buf[..0]).is_err());
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn splitmix_works() {
+    // reference values of SplitMix64 seeded with 0
+    let mut rng = SplitMix64::new(0);
+    assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+    assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+  }
+}